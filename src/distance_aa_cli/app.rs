@@ -42,8 +42,87 @@
 // * SOFTWARE.
 // * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
 
-use actix_web::{get, post, web, HttpResponse, Responder};
-use distance_aa_lib::{amino_acid_library, AminoAcid};
+use crate::auth::{authenticate, batch_scope};
+use crate::batch::batch_distances;
+use crate::caching::conditional;
+use crate::compare::compare_sequences;
+use crate::error::ApiError;
+#[cfg(feature = "graphql")]
+use crate::graphql;
+use crate::jobs::{cancel_job, job_events, job_results, job_status, submit_job};
+use crate::limits::{json_config, rate_limit, timeout, DEFAULT_BODY_LIMIT};
+use crate::matrix::distance_matrix;
+use crate::metrics::{health, prometheus_metrics, ready};
+use crate::openapi::ApiDoc;
+use crate::state::AppState;
+use crate::upload::upload_job;
+use actix_web::http::header;
+use actix_web::middleware::from_fn;
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use distance_aa_lib::{AminoAcid, DistanceMetric, GranthamDistance};
+use serde::Deserialize;
+use utoipa::{IntoParams, OpenApi};
+use utoipa_swagger_ui::SwaggerUi;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DistanceQuery {
+    /// Defaults to `grantham`.
+    #[serde(default)]
+    pub metric: DistanceMetric,
+}
+
+/// Registers every route. API endpoints live under `/api/v1`; the unversioned paths they
+/// replaced are kept as deprecated redirects and registered last, so that the single-segment
+/// legacy route cannot shadow any other top-level path.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    let api = web::scope("/api/v1")
+        .wrap(from_fn(rate_limit))
+        .wrap(from_fn(authenticate))
+        .service(echo)
+        .service(amino_acid)
+        .service(
+            web::resource("/distances/batch")
+                .wrap(from_fn(timeout))
+                .wrap(from_fn(batch_scope))
+                .route(web::post().to(batch_distances)),
+        )
+        .service(distance_matrix)
+        .service(pairwise_distance)
+        .service(compare_sequences)
+        .service(submit_job)
+        .service(upload_job)
+        .service(job_status)
+        .service(job_results)
+        .service(job_events)
+        .service(cancel_job);
+    #[cfg(feature = "graphql")]
+    let api = api.service(graphql::execute);
+
+    cfg.app_data(web::QueryConfig::default().error_handler(|err, req| {
+        ApiError::bad_request(err)
+            .with_input(req.query_string())
+            .into()
+    }))
+    .app_data(json_config(DEFAULT_BODY_LIMIT))
+    .app_data(
+        web::PathConfig::default()
+            .error_handler(|err, req| ApiError::bad_request(err).with_input(req.path()).into()),
+    )
+    .default_service(web::to(not_found))
+    .service(index)
+    .service(health)
+    .service(ready)
+    .service(prometheus_metrics)
+    .service(SwaggerUi::new("/api/v1/docs/{_:.*}").url("/api/v1/openapi.json", ApiDoc::openapi()))
+    .service(web::redirect("/api/v1/docs", "/api/v1/docs/"))
+    .service(api);
+    #[cfg(feature = "graphql")]
+    cfg.app_data(web::Data::new(graphql::schema()));
+    cfg.service(legacy_echo)
+        .service(legacy_pairwise_distance)
+        .service(legacy_amino_acid);
+}
 
 #[get("/")]
 async fn index() -> impl Responder {
@@ -52,19 +131,218 @@ async fn index() -> impl Responder {
         .body("Hello world!")
 }
 
-#[post("/echo")]
+#[post("/echo", name = "echo")]
 async fn echo(req_body: String) -> impl Responder {
     HttpResponse::Ok().content_type("text/html").body(req_body)
 }
 
+/// Looks an amino acid up by name, three-letter or one-letter code.
+#[utoipa::path(
+    context_path = "/api/v1",
+    params(("id" = String, Path, description = "Name, three-letter or one-letter code")),
+    responses(
+        (status = 200, body = AminoAcid),
+        (status = 404, description = "Unknown amino acid, with suggestions", body = ApiError),
+    )
+)]
+#[get(
+    "/amino-acids/{id}",
+    name = "amino_acid",
+    wrap = "from_fn(conditional)"
+)]
+pub async fn amino_acid(
+    state: web::Data<AppState>,
+    id: web::Path<(String,)>,
+) -> Result<web::Json<AminoAcid>, ApiError> {
+    state.find(&id.0).cloned().map(web::Json)
+}
+
+/// The distance between two amino acids.
+#[utoipa::path(
+    context_path = "/api/v1",
+    params(
+        ("first" = String, Path, description = "Name, three-letter or one-letter code"),
+        ("second" = String, Path, description = "Name, three-letter or one-letter code"),
+        DistanceQuery,
+    ),
+    responses(
+        (status = 200, body = GranthamDistance),
+        (status = 400, description = "Invalid query", body = ApiError),
+        (status = 404, description = "Unknown amino acid, with suggestions", body = ApiError),
+    )
+)]
+#[get(
+    "/distances/{first}/{second}",
+    name = "distance",
+    wrap = "from_fn(conditional)"
+)]
+pub async fn pairwise_distance(
+    state: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    query: web::Query<DistanceQuery>,
+) -> Result<web::Json<GranthamDistance>, ApiError> {
+    let first = state.find(&path.0)?;
+    let second = state.find(&path.1)?;
+
+    state
+        .distance(first, second, query.metric)
+        .map(web::Json)
+        .ok_or_else(|| {
+            ApiError::not_found(format!(
+                "No {} distance between {} and {}",
+                query.metric,
+                first.get_name(),
+                second.get_name()
+            ))
+        })
+}
+
+fn route_not_found(req: &HttpRequest) -> ApiError {
+    ApiError::not_found(format!("No route for {} {}", req.method(), req.path()))
+        .with_input(req.path())
+}
+
+async fn not_found(req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    Err(route_not_found(&req))
+}
+
+/// A permanent redirect that keeps the method and body, marked as deprecated.
+fn deprecated_redirect(
+    req: &HttpRequest,
+    name: &str,
+    elements: &[&str],
+) -> Result<HttpResponse, ApiError> {
+    let mut location = req
+        .url_for(name, elements)
+        .map_err(|err| ApiError::internal(err.into()))?;
+    location.set_query(req.uri().query());
+    Ok(HttpResponse::PermanentRedirect()
+        .insert_header((header::LOCATION, location.as_str()))
+        .insert_header(("Deprecation", "true"))
+        .insert_header((
+            header::LINK,
+            format!("<{location}>; rel=\"successor-version\""),
+        ))
+        .finish())
+}
+
+#[post("/echo")]
+async fn legacy_echo(req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    deprecated_redirect(&req, "echo", &[])
+}
+
+#[get("/distance/{first}/{second}")]
+async fn legacy_pairwise_distance(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, ApiError> {
+    deprecated_redirect(&req, "distance", &[&path.0, &path.1])
+}
+
 #[get("/{query_acid}")]
-async fn amino_acid(query_acid: web::Path<(String,)>) -> impl Responder {
-    let selected: AminoAcid = amino_acid_library()
-        .into_iter()
-        .find(|aa| aa.get_name() == query_acid.0)
-        .unwrap_or_default();
+async fn legacy_amino_acid(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    query_acid: web::Path<(String,)>,
+) -> Result<HttpResponse, ApiError> {
+    let known = state
+        .get_library()
+        .iter()
+        .any(|aa| aa.get_name() == query_acid.0);
+    if known {
+        deprecated_redirect(&req, "amino_acid", &[&query_acid.0])
+    } else {
+        Err(route_not_found(&req))
+    }
+}
 
-    HttpResponse::Ok()
-        .content_type("application/json")
-        .body(serde_json::to_string(&selected).unwrap())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::app;
+    use actix_web::http::StatusCode;
+    use actix_web::test;
+
+    #[actix_web::test]
+    async fn test_amino_acid() {
+        let app = test::init_service(app()).await;
+        let req = test::TestRequest::get()
+            .uri("/api/v1/amino-acids/Ala")
+            .to_request();
+        let selected: AminoAcid = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(selected.get_name(), "Alanine");
+    }
+
+    #[actix_web::test]
+    async fn test_pairwise_distance() {
+        let app = test::init_service(app()).await;
+        let req = test::TestRequest::get()
+            .uri("/api/v1/distances/A/lysine?metric=grantham")
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["distance"], 106);
+    }
+
+    #[actix_web::test]
+    async fn test_unknown_amino_acid() {
+        let app = test::init_service(app()).await;
+        let req = test::TestRequest::get()
+            .uri("/api/v1/amino-acids/Alanin")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["code"], "unknown_amino_acid");
+        assert_eq!(body["input"], "Alanin");
+        assert_eq!(body["suggestions"][0], "Alanine");
+    }
+
+    #[actix_web::test]
+    async fn test_bad_query() {
+        let app = test::init_service(app()).await;
+        let req = test::TestRequest::get()
+            .uri("/api/v1/distances/A/K?metric=euclidean")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["code"], "bad_request");
+        assert_eq!(body["input"], "metric=euclidean");
+    }
+
+    #[actix_web::test]
+    async fn test_legacy_amino_acid_redirects() {
+        let app = test::init_service(app()).await;
+        let req = test::TestRequest::get().uri("/Alanine").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            resp.headers().get(header::LOCATION).unwrap(),
+            "http://localhost:8080/api/v1/amino-acids/Alanine"
+        );
+        assert_eq!(resp.headers().get("Deprecation").unwrap(), "true");
+    }
+
+    #[actix_web::test]
+    async fn test_legacy_route_does_not_swallow_unknown_paths() {
+        let app = test::init_service(app()).await;
+        let req = test::TestRequest::get().uri("/favicon.ico").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["code"], "not_found");
+    }
+
+    #[actix_web::test]
+    async fn test_legacy_distance_keeps_query() {
+        let app = test::init_service(app()).await;
+        let req = test::TestRequest::get()
+            .uri("/distance/A/K?metric=grantham")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.headers().get(header::LOCATION).unwrap(),
+            "http://localhost:8080/api/v1/distances/A/K?metric=grantham"
+        );
+    }
 }
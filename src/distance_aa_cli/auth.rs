@@ -0,0 +1,493 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use crate::cli::ApiKeyArgs;
+use crate::error::ApiError;
+use crate::limits::Rate;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Method};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpMessage, ResponseError};
+use anyhow::{anyhow, ensure, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fmt::{self, Display, Formatter, Write};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Header accepted as an alternative to `Authorization: Bearer`.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// What an API key may do. Each scope includes the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// Lookups, comparisons and single scores.
+    #[default]
+    Read,
+    /// Batch scoring and jobs as well.
+    Batch,
+}
+
+impl Scope {
+    pub const ALL: [Self; 2] = [Self::Read, Self::Batch];
+
+    pub const fn get_name(self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Batch => "batch",
+        }
+    }
+}
+
+impl Display for Scope {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.get_name())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|scope| scope.get_name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| anyhow!("Unknown scope: {s}"))
+    }
+}
+
+/// A key from the `[[api_keys]]` tables of the config file. Only the SHA-256 of the key is
+/// kept, so the file does not hold anything a client could use.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKey {
+    name: String,
+    sha256: String,
+    #[serde(default)]
+    scope: Scope,
+    /// Requests per second, replacing the per-client `rate_limit` for this key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rate_limit: Option<f64>,
+    /// Defaults to one second's worth of requests.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rate_limit_burst: Option<u32>,
+}
+
+impl ApiKey {
+    pub fn new(name: &str, key: &str, scope: Scope) -> Self {
+        Self {
+            name: name.to_string(),
+            sha256: hash(key),
+            scope,
+            rate_limit: None,
+            rate_limit_burst: None,
+        }
+    }
+    #[must_use]
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    #[must_use]
+    pub const fn get_scope(&self) -> Scope {
+        self.scope
+    }
+    /// The key's own rate limit, if it has one.
+    #[must_use]
+    pub fn get_rate(&self) -> Option<Rate> {
+        self.rate_limit.map(|per_second| {
+            let burst = self
+                .rate_limit_burst
+                .unwrap_or_else(|| per_second.ceil().min(f64::from(u32::MAX)) as u32);
+            Rate::new(per_second, burst)
+        })
+    }
+    fn validate(&self) -> Result<()> {
+        ensure!(!self.name.trim().is_empty(), "API keys must have a name");
+        ensure!(
+            self.sha256.len() == 64 && self.sha256.bytes().all(|b| b.is_ascii_hexdigit()),
+            "API key {} must have a sha256 of 64 hexadecimal digits",
+            self.name
+        );
+        if let Some(rate) = self.rate_limit {
+            ensure!(
+                rate.is_finite() && rate > 0.0,
+                "API key {} must have a positive rate_limit",
+                self.name
+            );
+        }
+        Ok(())
+    }
+    /// Compares digests without stopping at the first difference.
+    fn matches(&self, digest: &str) -> bool {
+        self.sha256.len() == digest.len()
+            && self
+                .sha256
+                .bytes()
+                .zip(digest.bytes())
+                .fold(0, |diff, (a, b)| diff | (a.to_ascii_lowercase() ^ b))
+                == 0
+    }
+}
+
+/// The SHA-256 of a key as lowercase hex, as written in the config file.
+pub fn hash(key: &str) -> String {
+    let mut digest = String::new();
+    for byte in Sha256::digest(key.as_bytes()) {
+        // Writing to a String cannot fail.
+        let _ = write!(digest, "{byte:02x}");
+    }
+    digest
+}
+
+/// The configured keys, registered as `web::Data` only when there is at least one.
+#[derive(Debug)]
+pub struct Authenticator {
+    keys: Vec<ApiKey>,
+    public_lookups: bool,
+}
+
+impl Authenticator {
+    /// # Errors
+    ///
+    /// Returns an error if a key is malformed or two keys share a name or hash.
+    pub fn new(keys: Vec<ApiKey>, public_lookups: bool) -> Result<Self> {
+        let mut names = HashSet::new();
+        let mut hashes = HashSet::new();
+        for key in &keys {
+            key.validate()?;
+            ensure!(
+                names.insert(&key.name),
+                "Duplicate API key name {}",
+                key.name
+            );
+            ensure!(
+                hashes.insert(key.sha256.to_ascii_lowercase()),
+                "API key {} has the same sha256 as another key",
+                key.name
+            );
+        }
+        Ok(Self {
+            keys,
+            public_lookups,
+        })
+    }
+    #[must_use]
+    pub const fn get_public_lookups(&self) -> bool {
+        self.public_lookups
+    }
+    pub fn identify(&self, key: &str) -> Option<&ApiKey> {
+        let digest = hash(key);
+        self.keys
+            .iter()
+            .find(|candidate| candidate.matches(&digest))
+    }
+}
+
+/// The key a request was made with, kept in the request extensions.
+#[derive(Debug, Clone)]
+pub struct Caller {
+    name: String,
+    scope: Scope,
+    rate: Option<Rate>,
+}
+
+impl Caller {
+    #[must_use]
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    #[must_use]
+    pub const fn get_rate(&self) -> Option<Rate> {
+        self.rate
+    }
+    #[must_use]
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scope >= scope
+    }
+}
+
+fn presented_key(req: &ServiceRequest) -> Option<&str> {
+    let headers = req.headers();
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            value
+                .strip_prefix("Bearer ")
+                .or_else(|| value.strip_prefix("bearer "))
+        })
+        .or_else(|| {
+            headers
+                .get(API_KEY_HEADER)
+                .and_then(|value| value.to_str().ok())
+        })
+        .map(str::trim)
+}
+
+fn reject<B>(req: ServiceRequest, error: &ApiError) -> ServiceResponse<EitherBody<B>> {
+    req.into_response(error.error_response())
+        .map_into_right_body()
+}
+
+/// Middleware for `from_fn`: with a `web::Data<Authenticator>` registered, every request needs a
+/// key with the `read` scope, except `GET` and `HEAD` lookups when public lookups are allowed.
+pub async fn authenticate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let authenticator = match req.app_data::<web::Data<Authenticator>>() {
+        Some(authenticator) => authenticator.clone(),
+        None => {
+            return next
+                .call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        }
+    };
+    match presented_key(&req).map(|key| authenticator.identify(key)) {
+        Some(Some(key)) => {
+            let caller = Caller {
+                name: key.get_name().to_string(),
+                scope: key.get_scope(),
+                rate: key.get_rate(),
+            };
+            req.extensions_mut().insert(caller);
+        }
+        Some(None) => return Ok(reject(req, &ApiError::unauthorized("Invalid API key"))),
+        None => {
+            let lookup = matches!(*req.method(), Method::GET | Method::HEAD);
+            if !(authenticator.public_lookups && lookup) {
+                let error = ApiError::unauthorized("An API key is required");
+                return Ok(reject(req, &error));
+            }
+        }
+    }
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+/// Middleware for `from_fn` on batch resources: needs a key with the `batch` scope whenever
+/// authentication is on.
+pub async fn batch_scope(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    if req.app_data::<web::Data<Authenticator>>().is_some() {
+        let allowed = req
+            .extensions()
+            .get::<Caller>()
+            .is_some_and(|caller| caller.allows(Scope::Batch));
+        if !allowed {
+            let error = ApiError::forbidden("This API key may not submit batches");
+            return Ok(reject(req, &error));
+        }
+    }
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+/// Generates a new key and prints it with the config entry that accepts it.
+pub fn run(args: &ApiKeyArgs) -> Result<()> {
+    let key = format!("daa_{}", Uuid::new_v4().simple());
+    let entry = ApiKey::new(&args.name, &key, args.scope);
+    entry.validate()?;
+    #[derive(Serialize)]
+    struct Entries<'a> {
+        api_keys: [&'a ApiKey; 1],
+    }
+    println!("API key for {} (it is not stored anywhere):", args.name);
+    println!("{key}");
+    println!();
+    println!("Add this to the config file:");
+    println!();
+    print!("{}", toml::to_string(&Entries { api_keys: [&entry] })?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::{rate_limit, RateLimiter};
+    use actix_web::http::StatusCode;
+    use actix_web::middleware::from_fn;
+    use actix_web::test::{self as actix_test, TestRequest};
+    use actix_web::{App, HttpResponse};
+
+    fn authenticator(public_lookups: bool) -> Authenticator {
+        Authenticator::new(
+            vec![
+                ApiKey::new("reader", "read-key", Scope::Read),
+                ApiKey {
+                    rate_limit: Some(1.0),
+                    rate_limit_burst: Some(1),
+                    ..ApiKey::new("pipeline", "batch-key", Scope::Batch)
+                },
+            ],
+            public_lookups,
+        )
+        .unwrap()
+    }
+
+    async fn status(public_lookups: bool, req: TestRequest) -> StatusCode {
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(authenticator(public_lookups)))
+                .service(
+                    web::scope("")
+                        .wrap(from_fn(authenticate))
+                        .route("/lookup", web::get().to(HttpResponse::Ok))
+                        .route("/echo", web::post().to(HttpResponse::Ok))
+                        .service(
+                            web::resource("/batch")
+                                .wrap(from_fn(batch_scope))
+                                .route(web::post().to(HttpResponse::Ok)),
+                        ),
+                ),
+        )
+        .await;
+        actix_test::call_service(&app, req.to_request())
+            .await
+            .status()
+    }
+
+    #[test]
+    fn test_identify() {
+        let authenticator = authenticator(false);
+        assert_eq!(
+            authenticator.identify("read-key").map(ApiKey::get_name),
+            Some("reader")
+        );
+        assert!(authenticator.identify("READ-KEY").is_none());
+        assert!(authenticator.identify("").is_none());
+    }
+    #[test]
+    fn test_new_rejects_bad_keys() {
+        let mut key = ApiKey::new("a", "secret", Scope::Read);
+        key.sha256.truncate(10);
+        assert!(Authenticator::new(vec![key], false).is_err());
+        let keys = vec![
+            ApiKey::new("a", "secret", Scope::Read),
+            ApiKey::new("a", "other", Scope::Read),
+        ];
+        assert!(Authenticator::new(keys, false).is_err());
+        let keys = vec![
+            ApiKey::new("a", "secret", Scope::Read),
+            ApiKey::new("b", "secret", Scope::Batch),
+        ];
+        assert!(Authenticator::new(keys, false).is_err());
+    }
+    #[test]
+    fn test_config_entry() {
+        let key: ApiKey = toml::from_str(&format!(
+            "name = \"lab\"\nsha256 = \"{}\"\nrate_limit = 2.5\n",
+            hash("secret").to_ascii_uppercase()
+        ))
+        .unwrap();
+        assert_eq!(key.get_scope(), Scope::Read);
+        assert_eq!(key.get_rate(), Some(Rate::new(2.5, 3)));
+        assert!(Authenticator::new(vec![key], false)
+            .unwrap()
+            .identify("secret")
+            .is_some());
+    }
+    #[actix_web::test]
+    async fn test_authenticate() {
+        let lookup = || TestRequest::get().uri("/lookup");
+        assert_eq!(status(false, lookup()).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(true, lookup()).await, StatusCode::OK);
+        let req = lookup().insert_header((header::AUTHORIZATION, "Bearer read-key"));
+        assert_eq!(status(false, req).await, StatusCode::OK);
+        let req = lookup().insert_header((API_KEY_HEADER, "batch-key"));
+        assert_eq!(status(false, req).await, StatusCode::OK);
+        let req = lookup().insert_header((API_KEY_HEADER, "wrong"));
+        assert_eq!(status(true, req).await, StatusCode::UNAUTHORIZED);
+
+        let echo = || TestRequest::post().uri("/echo");
+        assert_eq!(status(true, echo()).await, StatusCode::UNAUTHORIZED);
+        let req = echo().insert_header((API_KEY_HEADER, "read-key"));
+        assert_eq!(status(false, req).await, StatusCode::OK);
+    }
+    #[actix_web::test]
+    async fn test_batch_scope() {
+        let batch = || TestRequest::post().uri("/batch");
+        let req = batch().insert_header((API_KEY_HEADER, "read-key"));
+        assert_eq!(status(true, req).await, StatusCode::FORBIDDEN);
+        let req = batch().insert_header((API_KEY_HEADER, "batch-key"));
+        assert_eq!(status(false, req).await, StatusCode::OK);
+    }
+    #[actix_web::test]
+    async fn test_rate_limit_per_key() {
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(authenticator(false)))
+                .app_data(web::Data::new(RateLimiter::new(Some(Rate::new(
+                    100.0, 100,
+                )))))
+                .service(
+                    web::scope("")
+                        .wrap(from_fn(rate_limit))
+                        .wrap(from_fn(authenticate))
+                        .route("/lookup", web::get().to(HttpResponse::Ok)),
+                ),
+        )
+        .await;
+        let call = |key: &'static str| {
+            TestRequest::get()
+                .uri("/lookup")
+                .insert_header((API_KEY_HEADER, key))
+                .to_request()
+        };
+        let first = actix_test::call_service(&app, call("batch-key")).await;
+        assert_eq!(first.status(), StatusCode::OK);
+        let second = actix_test::call_service(&app, call("batch-key")).await;
+        assert_eq!(second.status(), StatusCode::TOO_MANY_REQUESTS);
+        let other = actix_test::call_service(&app, call("read-key")).await;
+        assert_eq!(other.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,378 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use crate::app::DistanceQuery;
+use crate::error::ApiError;
+use crate::state::AppState;
+use actix_web::web::Bytes;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use distance_aa_lib::{DistanceMetric, GranthamDistance, ProteinVariant};
+use futures_util::stream;
+use serde::de::{self, DeserializeSeed, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::cell::Cell;
+use std::fmt;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy)]
+pub struct BatchConfig {
+    max_items: usize,
+}
+
+impl BatchConfig {
+    pub const fn new(max_items: usize) -> Self {
+        Self { max_items }
+    }
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self::new(10_000)
+    }
+}
+
+/// One item to score: an explicit pair of residues or a protein variant, either as
+/// `{"variant": "p.Arg97Cys"}` or a bare string.
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum BatchItem {
+    Pair { first: String, second: String },
+    Variant { variant: String },
+    Notation(String),
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchResult {
+    index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    variant: Option<ProteinVariant>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<GranthamDistance>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ApiError>,
+}
+
+/// Scores a JSON array, or newline-delimited JSON when sent as `application/x-ndjson`, in
+/// which case the results are streamed back the same way, each line as soon as it is scored.
+/// A bad item fails on its own and never the whole batch.
+#[utoipa::path(
+    post,
+    path = "/api/v1/distances/batch",
+    params(DistanceQuery),
+    request_body(
+        description = "A JSON array, or one item per line as NDJSON",
+        content(
+            (Vec<BatchItem> = "application/json"),
+            (BatchItem = "application/x-ndjson"),
+        )
+    ),
+    responses(
+        (
+            status = 200,
+            description = "One result per item, in the format of the request",
+            content(
+                (Vec<BatchResult> = "application/json"),
+                (BatchResult = "application/x-ndjson"),
+            )
+        ),
+        (status = 400, description = "The body is not a JSON array", body = ApiError),
+        (status = 413, description = "Too many items", body = ApiError),
+    )
+)]
+pub async fn batch_distances(
+    req: HttpRequest,
+    body: web::Bytes,
+    state: web::Data<AppState>,
+    config: web::Data<BatchConfig>,
+    query: web::Query<DistanceQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let body = std::str::from_utf8(&body).map_err(ApiError::bad_request)?;
+    let ndjson = is_ndjson(&req);
+    let items = parse_items(body, ndjson, config.max_items)?;
+    let metric = query.metric;
+
+    if ndjson {
+        let lines = items.into_iter().enumerate().map(move |(index, item)| {
+            let mut line = serde_json::to_vec(&score(index, item, &state, metric))?;
+            line.push(b'\n');
+            Ok::<_, serde_json::Error>(Bytes::from(line))
+        });
+        Ok(HttpResponse::Ok()
+            .content_type("application/x-ndjson")
+            .streaming(stream::iter(lines)))
+    } else {
+        let results: Vec<BatchResult> = items
+            .into_iter()
+            .enumerate()
+            .map(|(index, item)| score(index, item, &state, metric))
+            .collect();
+        Ok(HttpResponse::Ok().json(results))
+    }
+}
+
+/// Splits a JSON array, or NDJSON, into items that each parsed or failed on their own.
+///
+/// # Errors
+///
+/// Returns an error if a body that should be a JSON array is not one, or if it has more than
+/// `max_items` items, which is found before any item is kept.
+pub fn parse_items(
+    body: &str,
+    ndjson: bool,
+    max_items: usize,
+) -> Result<Vec<Result<BatchItem, ApiError>>, ApiError> {
+    let too_many =
+        || ApiError::payload_too_large(format!("Batches are limited to {max_items} items"));
+    if ndjson {
+        let lines = body.lines().filter(|line| !line.trim().is_empty());
+        if lines.clone().count() > max_items {
+            return Err(too_many());
+        }
+        return Ok(lines
+            .map(|line| parse_item(serde_json::from_str(line), line))
+            .collect());
+    }
+    let exceeded = Cell::new(false);
+    let seed = LimitedArray {
+        max_items,
+        exceeded: &exceeded,
+    };
+    let mut deserializer = serde_json::Deserializer::from_str(body);
+    let values = seed
+        .deserialize(&mut deserializer)
+        .and_then(|values| deserializer.end().map(|()| values))
+        .map_err(|err| {
+            if exceeded.get() {
+                too_many()
+            } else {
+                ApiError::bad_request(format!("Expected a JSON array: {err}"))
+            }
+        })?;
+    Ok(values
+        .into_iter()
+        .map(|value| {
+            let input = value.to_string();
+            parse_item(serde_json::from_value(value), &input)
+        })
+        .collect())
+}
+
+/// A JSON array read one value at a time, which stops as soon as it has too many.
+struct LimitedArray<'a> {
+    max_items: usize,
+    exceeded: &'a Cell<bool>,
+}
+
+impl<'de> DeserializeSeed<'de> for LimitedArray<'_> {
+    type Value = Vec<serde_json::Value>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for LimitedArray<'_> {
+    type Value = Vec<serde_json::Value>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut values = Vec::new();
+        while let Some(value) = seq.next_element()? {
+            if values.len() == self.max_items {
+                self.exceeded.set(true);
+                return Err(de::Error::custom("too many items"));
+            }
+            values.push(value);
+        }
+        Ok(values)
+    }
+}
+
+pub fn is_ndjson(req: &HttpRequest) -> bool {
+    matches!(
+        req.content_type(),
+        "application/x-ndjson"
+            | "application/ndjson"
+            | "application/jsonl"
+            | "application/x-jsonlines"
+    )
+}
+
+fn parse_item(item: serde_json::Result<BatchItem>, input: &str) -> Result<BatchItem, ApiError> {
+    item.map_err(|_| {
+        ApiError::bad_request(
+            "Expected {\"first\": ..., \"second\": ...}, {\"variant\": ...} or a variant string",
+        )
+        .with_input(input.trim())
+    })
+}
+
+pub fn score(
+    index: usize,
+    item: Result<BatchItem, ApiError>,
+    state: &AppState,
+    metric: DistanceMetric,
+) -> BatchResult {
+    let mut variant = None;
+    let outcome = item.and_then(|item| {
+        let (first, second) = match item {
+            BatchItem::Pair { first, second } => (first, second),
+            BatchItem::Variant { variant: notation } | BatchItem::Notation(notation) => {
+                let parsed = ProteinVariant::parse(&notation)
+                    .map_err(|err| ApiError::bad_request(err).with_input(&notation))?;
+                let residues = (parsed.get_reference(), parsed.get_alternate());
+                variant = Some(parsed);
+                residues
+            }
+        };
+        let first = state.find(&first)?;
+        let second = state.find(&second)?;
+        state.distance(first, second, metric).ok_or_else(|| {
+            ApiError::not_found(format!(
+                "No {metric} distance between {} and {}",
+                first.get_name(),
+                second.get_name()
+            ))
+        })
+    });
+
+    let (result, error) = match outcome {
+        Ok(result) => (Some(result), None),
+        Err(error) => (None, Some(error)),
+    };
+    BatchResult {
+        index,
+        variant,
+        result,
+        error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::app;
+    use actix_web::http::StatusCode;
+    use actix_web::test;
+
+    #[actix_web::test]
+    async fn test_batch_json() {
+        let app = test::init_service(app()).await;
+        let req = test::TestRequest::post()
+            .uri("/api/v1/distances/batch")
+            .set_json(serde_json::json!([
+                {"first": "A", "second": "Lys"},
+                {"variant": "p.Arg97Cys"},
+                "W12",
+                {"first": "A", "second": "Alanin"}
+            ]))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body[0]["result"]["distance"], 106);
+        assert_eq!(body[1]["variant"]["position"], 97);
+        assert_eq!(body[1]["result"]["distance"], 180);
+        assert_eq!(body[2]["error"]["code"], "bad_request");
+        assert_eq!(body[2]["error"]["input"], "W12");
+        assert_eq!(body[3]["index"], 3);
+        assert_eq!(body[3]["error"]["code"], "unknown_amino_acid");
+    }
+
+    #[actix_web::test]
+    async fn test_batch_ndjson() {
+        let app = test::init_service(app()).await;
+        let req = test::TestRequest::post()
+            .uri("/api/v1/distances/batch")
+            .insert_header(("Content-Type", "application/x-ndjson"))
+            .set_payload("\"R97C\"\n\nnot json\n")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.headers().get("Content-Type").unwrap(),
+            "application/x-ndjson"
+        );
+        let body = test::read_body(resp).await;
+        let lines: Vec<serde_json::Value> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["result"]["distance"], 180);
+        assert_eq!(lines[1]["error"]["input"], "not json");
+    }
+
+    #[actix_web::test]
+    async fn test_batch_too_large() {
+        let app = test::init_service(app().app_data(web::Data::new(BatchConfig::new(1)))).await;
+        let req = test::TestRequest::post()
+            .uri("/api/v1/distances/batch")
+            .set_json(serde_json::json!(["R97C", "R98C"]))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/distances/batch")
+            .insert_header(("Content-Type", "application/x-ndjson"))
+            .set_payload("\"R97C\"\n\n\"R98C\"\n")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[actix_web::test]
+    async fn test_parse_items_limit() {
+        let items = parse_items(r#"["R97C", {"first": "A", "second": "K"}]"#, false, 2).unwrap();
+        assert_eq!(items.len(), 2);
+        let error = parse_items(r#"["R97C", "R98C", "R99C"]"#, false, 2).unwrap_err();
+        assert_eq!(error.to_string(), "Batches are limited to 2 items");
+        let error = parse_items(r#"{"variant": "R97C"}"#, false, 2).unwrap_err();
+        assert!(error.to_string().starts_with("Expected a JSON array"));
+        let error = parse_items(r#"["R97C"] garbage"#, false, 2).unwrap_err();
+        assert!(error.to_string().starts_with("Expected a JSON array"));
+    }
+}
@@ -0,0 +1,283 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use crate::auth::Authenticator;
+use crate::state::AppState;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, CacheControl, CacheDirective, EntityTag, Header, IfNoneMatch};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpResponse};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+
+/// How long clients may reuse a response before revalidating it.
+#[derive(Debug, Clone, Copy)]
+pub struct CachePolicy {
+    max_age: u32,
+}
+
+impl CachePolicy {
+    pub const fn new(max_age: u32) -> Self {
+        Self { max_age }
+    }
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        Self::new(3600)
+    }
+}
+
+/// A strong ETag for one representation of one resource: the data version, and a digest of the
+/// server version, the URI and the headers that select the representation. The server version
+/// is included because a release can change how the same data is rendered.
+fn entity_tag(req: &ServiceRequest, version: &str) -> EntityTag {
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    hasher.update([0]);
+    hasher.update(req.uri().to_string());
+    for name in [header::ACCEPT, header::ACCEPT_ENCODING] {
+        hasher.update([0]);
+        if let Some(value) = req.headers().get(name) {
+            hasher.update(value.as_bytes());
+        }
+    }
+    let mut tag = format!("{version}-");
+    for byte in &hasher.finalize()[..8] {
+        // Writing to a String cannot fail.
+        let _ = write!(tag, "{byte:02x}");
+    }
+    EntityTag::new_strong(tag)
+}
+
+/// What a request's `If-None-Match` asks for.
+enum Precondition {
+    /// No usable header, or no listed tag matches.
+    None,
+    /// A listed tag matches, so the handler does not need to run.
+    Matches,
+    /// `*`: only a resource that exists matches, which is known once the handler has run.
+    Any,
+}
+
+fn precondition(req: &ServiceRequest, tag: &EntityTag) -> Precondition {
+    match IfNoneMatch::parse(req.request()) {
+        Ok(IfNoneMatch::Any) => Precondition::Any,
+        Ok(IfNoneMatch::Items(items)) if items.iter().any(|item| item.weak_eq(tag)) => {
+            Precondition::Matches
+        }
+        _ => Precondition::None,
+    }
+}
+
+/// Middleware for `from_fn` on read-only routes whose responses only change with the data:
+/// adds `ETag` and `Cache-Control` to successful responses and answers a matching
+/// `If-None-Match` with `304 Not Modified` without running the handler. `If-None-Match: *`
+/// runs the handler and only becomes a 304 when the resource exists. Responses are `private`
+/// when lookups need an API key, so shared caches never hand them to unauthenticated clients.
+pub async fn conditional(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let version = req
+        .app_data::<web::Data<AppState>>()
+        .map(|state| state.get_version().to_string());
+    let version = match version {
+        Some(version) if matches!(*req.method(), Method::GET | Method::HEAD) => version,
+        _ => {
+            return next
+                .call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        }
+    };
+    let policy = req
+        .app_data::<web::Data<CachePolicy>>()
+        .map_or_else(CachePolicy::default, |policy| *policy.get_ref());
+    let tag = entity_tag(&req, &version);
+    let keyed = req
+        .app_data::<web::Data<Authenticator>>()
+        .is_some_and(|authenticator| !authenticator.get_public_lookups());
+    let cache_control = CacheControl(vec![
+        if keyed {
+            CacheDirective::Private
+        } else {
+            CacheDirective::Public
+        },
+        CacheDirective::MaxAge(policy.max_age),
+    ]);
+
+    let not_modified = |request, tag, cache_control| {
+        let response = HttpResponse::NotModified()
+            .insert_header(header::ETag(tag))
+            .insert_header(cache_control)
+            .finish();
+        ServiceResponse::new(request, response).map_into_right_body()
+    };
+    let precondition = precondition(&req, &tag);
+    if let Precondition::Matches = precondition {
+        let (request, _) = req.into_parts();
+        return Ok(not_modified(request, tag, cache_control));
+    }
+    let mut response = next.call(req).await?;
+    if response.status() == StatusCode::OK {
+        if let Precondition::Any = precondition {
+            let (request, _) = response.into_parts();
+            return Ok(not_modified(request, tag, cache_control));
+        }
+        let headers = response.headers_mut();
+        headers.insert(header::ETAG, tag.to_string().parse()?);
+        headers.insert(header::CACHE_CONTROL, cache_control.to_string().parse()?);
+    }
+    Ok(response.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::{ApiKey, Authenticator, Scope};
+    use crate::testing::app;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{test, web};
+
+    #[actix_web::test]
+    async fn test_conditional() {
+        let app = test::init_service(app()).await;
+        let req = test::TestRequest::get()
+            .uri("/api/v1/amino-acids/K")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let etag = resp.headers().get(header::ETAG).unwrap().clone();
+        assert!(!etag.to_str().unwrap().starts_with("W/"));
+        assert_eq!(
+            resp.headers().get(header::CACHE_CONTROL).unwrap(),
+            "public, max-age=3600"
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/amino-acids/K")
+            .insert_header((header::IF_NONE_MATCH, etag.clone()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(resp.headers().get(header::ETAG).unwrap(), &etag);
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/distances/matrix")
+            .insert_header((header::IF_NONE_MATCH, etag.clone()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_ne!(resp.headers().get(header::ETAG).unwrap(), &etag);
+    }
+
+    #[actix_web::test]
+    async fn test_conditional_depends_on_accept() {
+        let app = test::init_service(app()).await;
+        let mut tags = Vec::new();
+        for accept in ["application/json", "text/csv"] {
+            let req = test::TestRequest::get()
+                .uri("/api/v1/distances/matrix")
+                .insert_header((header::ACCEPT, accept))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            tags.push(resp.headers().get(header::ETAG).unwrap().clone());
+        }
+        assert_ne!(tags[0], tags[1]);
+    }
+
+    #[actix_web::test]
+    async fn test_conditional_skips_errors() {
+        let app = test::init_service(app()).await;
+        let req = test::TestRequest::get()
+            .uri("/api/v1/amino-acids/Xyz")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert!(resp.headers().get(header::ETAG).is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_conditional_any() {
+        let app = test::init_service(app()).await;
+        let req = test::TestRequest::get()
+            .uri("/api/v1/amino-acids/K")
+            .insert_header((header::IF_NONE_MATCH, "*"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        assert!(resp.headers().get(header::ETAG).is_some());
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/amino-acids/Xyz")
+            .insert_header((header::IF_NONE_MATCH, "*"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_conditional_private_with_keys() {
+        for (public_lookups, expected) in [(false, "private"), (true, "public")] {
+            let authenticator = Authenticator::new(
+                vec![ApiKey::new("reader", "read-key", Scope::Read)],
+                public_lookups,
+            )
+            .unwrap();
+            let app = test::init_service(app().app_data(web::Data::new(authenticator))).await;
+            let req = test::TestRequest::get()
+                .uri("/api/v1/amino-acids/K")
+                .insert_header((header::AUTHORIZATION, "Bearer read-key"))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(
+                resp.headers().get(header::CACHE_CONTROL).unwrap(),
+                &format!("{expected}, max-age=3600")
+            );
+        }
+    }
+}
@@ -42,11 +42,31 @@
 // * SOFTWARE.
 // * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
 
-use clap::Parser;
+use crate::auth::Scope;
+use crate::logging::LogFormat;
+use clap::{Parser, Subcommand};
+use distance_aa_lib::AMINO_ACID_DATA_PATH;
+use distance_aa_lib::{CurveModel, LoadingSample};
+use std::path::PathBuf;
 
 #[derive(Debug, Parser)]
 #[clap(author, about, version, long_about = None)]
 pub struct Args {
+    /// Configuration File
+    ///
+    /// This is a TOML file of settings, keyed by the long option names with underscores, such
+    /// as port = 8080. Command line options and environment variables, including those from a
+    /// .env file in the working directory, take precedence over it.
+    /// This can also be set using the environment variable CONFIG.
+    #[clap(short, long, env)]
+    pub config: Option<PathBuf>,
+
+    /// Print Configuration
+    ///
+    /// Print the effective settings and where each one came from, then exit.
+    #[clap(long)]
+    pub print_config: bool,
+
     /// Bind Address
     ///
     /// This is the address that the server will bind to. This can be an IP address or a hostname.
@@ -70,4 +90,324 @@ pub struct Args {
     /// This can also be set using the environment variable LOGLEVEL.
     #[clap(short, long, env, default_value = "INFO")]
     pub loglevel: String,
+
+    /// Log Format
+    ///
+    /// This is the format of every log line, either text or json. JSON lines carry the ID of
+    /// the request being handled, also returned to clients in the X-Request-ID header.
+    /// This is set to default to text.
+    /// This can also be set using the environment variable LOG_FORMAT.
+    #[clap(long, env, default_value = "text")]
+    pub log_format: LogFormat,
+
+    /// Access Log Format
+    ///
+    /// This is the actix-web Logger format string used for request logging, such as
+    /// '%a "%r" %s %T'. If unset, a default matching the log format is used.
+    /// This can also be set using the environment variable ACCESS_LOG_FORMAT.
+    #[clap(long, env)]
+    pub access_log_format: Option<String>,
+
+    /// TLS Certificate
+    ///
+    /// This is a PEM file with the server certificate chain. Together with the TLS key, it makes
+    /// the server speak HTTPS. Send the process SIGHUP to reload both files after renewal.
+    /// This can also be set using the environment variable TLS_CERT.
+    #[clap(long, env, requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// TLS Key
+    ///
+    /// This is a PEM file with the private key of the TLS certificate.
+    /// This can also be set using the environment variable TLS_KEY.
+    #[clap(long, env, requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Workers
+    ///
+    /// This is the number of worker threads serving requests. This is set to default to the
+    /// number of physical CPU cores.
+    /// This can also be set using the environment variable WORKERS.
+    #[clap(short, long, env)]
+    pub workers: Option<usize>,
+
+    /// Amino Acid Data Path
+    ///
+    /// This is the JSON file of amino acid properties loaded at startup.
+    /// This can also be set using the environment variable DATA_PATH.
+    #[clap(long, env, default_value = AMINO_ACID_DATA_PATH)]
+    pub data_path: PathBuf,
+
+    /// CORS Origins
+    ///
+    /// These are the origins allowed to call the API from a browser, such as
+    /// https://distaance.example.org, or * for any origin. The option can be repeated, or given
+    /// as a comma-separated list. No cross-origin requests are allowed by default.
+    /// This can also be set using the environment variable CORS_ORIGINS.
+    #[clap(long = "cors-origin", env = "CORS_ORIGINS", value_delimiter = ',')]
+    pub cors_origins: Vec<String>,
+
+    /// CORS Methods
+    ///
+    /// These are the HTTP methods allowed in cross-origin requests. This is set to default to
+    /// GET and POST.
+    /// This can also be set using the environment variable CORS_METHODS.
+    #[clap(
+        long = "cors-method",
+        env = "CORS_METHODS",
+        value_delimiter = ',',
+        default_value = "GET,POST"
+    )]
+    pub cors_methods: Vec<String>,
+
+    /// CORS Headers
+    ///
+    /// These are the request headers allowed in cross-origin requests, on top of those
+    /// browsers always allow. This is set to default to Content-Type, Authorization, X-API-Key
+    /// and X-Request-ID.
+    /// This can also be set using the environment variable CORS_HEADERS.
+    #[clap(
+        long = "cors-header",
+        env = "CORS_HEADERS",
+        value_delimiter = ',',
+        default_value = "content-type,authorization,x-api-key,x-request-id"
+    )]
+    pub cors_headers: Vec<String>,
+
+    /// Permissive CORS
+    ///
+    /// Allow any origin, method and header, ignoring the settings above. This is meant for
+    /// local development only.
+    /// This can also be set using the environment variable CORS_PERMISSIVE.
+    #[clap(long, env)]
+    pub cors_permissive: bool,
+
+    /// Cache Max Age
+    ///
+    /// This is how many seconds clients may reuse lookup and matrix responses before checking
+    /// their ETag again. This is set to default to 3600.
+    /// This can also be set using the environment variable CACHE_MAX_AGE.
+    #[clap(long, env, default_value = "3600")]
+    pub cache_max_age: u32,
+
+    /// Maximum Batch Size
+    ///
+    /// This is the largest number of items accepted by a single batch scoring request.
+    /// This is set to default to 10000.
+    /// This can also be set using the environment variable MAX_BATCH_SIZE.
+    #[clap(long, env, default_value = "10000")]
+    pub max_batch_size: usize,
+
+    /// Maximum Alignment Cells
+    ///
+    /// This is the largest product of two sequence lengths that a comparison will align; the
+    /// time an alignment takes grows with it. This is set to default to 25000000.
+    /// This can also be set using the environment variable MAX_ALIGNMENT_CELLS.
+    #[clap(long, env, default_value = "25000000")]
+    pub max_alignment_cells: usize,
+
+    /// Maximum Body Size
+    ///
+    /// This is the largest request body, in bytes, accepted by any endpoint.
+    /// This is set to default to 1048576.
+    /// This can also be set using the environment variable MAX_BODY_SIZE.
+    #[clap(long, env, default_value = "1048576")]
+    pub max_body_size: usize,
+
+    /// Rate Limit
+    ///
+    /// This is how many API requests per second each client address may make over time; 0
+    /// turns rate limiting off. This is set to default to 0.
+    /// This can also be set using the environment variable RATE_LIMIT.
+    #[clap(long, env, default_value = "0")]
+    pub rate_limit: f64,
+
+    /// Rate Limit Burst
+    ///
+    /// This is how many API requests a client may make at once before the rate limit applies.
+    /// This is set to default to 20.
+    /// This can also be set using the environment variable RATE_LIMIT_BURST.
+    #[clap(long, env, default_value = "20")]
+    pub rate_limit_burst: u32,
+
+    /// Request Timeout
+    ///
+    /// This is how many seconds a batch, matrix or comparison request may take before it is
+    /// answered with a 503; 0 turns the timeout off. This is set to default to 30.
+    /// This can also be set using the environment variable REQUEST_TIMEOUT.
+    #[clap(long, env, default_value = "30")]
+    pub request_timeout: u64,
+
+    /// Job Workers
+    ///
+    /// This is how many background jobs may run at once; others wait in the queue.
+    /// This is set to default to 2.
+    /// This can also be set using the environment variable JOB_WORKERS.
+    #[clap(long, env, default_value = "2")]
+    pub job_workers: usize,
+
+    /// Maximum Jobs
+    ///
+    /// This is how many jobs the server holds at once, counting finished jobs until they
+    /// expire. Submissions beyond it are refused. This is set to default to 100.
+    /// This can also be set using the environment variable MAX_JOBS.
+    #[clap(long, env, default_value = "100")]
+    pub max_jobs: usize,
+
+    /// Maximum Jobs per Client
+    ///
+    /// This is how many of those jobs any one API key, or client address without a key, may
+    /// hold. This is set to default to 10.
+    /// This can also be set using the environment variable MAX_JOBS_PER_CLIENT.
+    #[clap(long, env, default_value = "10")]
+    pub max_jobs_per_client: usize,
+
+    /// Job Time to Live
+    ///
+    /// This is how many seconds a finished job and its results are kept.
+    /// This is set to default to 3600.
+    /// This can also be set using the environment variable JOB_TTL.
+    #[clap(long, env, default_value = "3600")]
+    pub job_ttl: u64,
+
+    /// Maximum Upload Size
+    ///
+    /// This is the largest body, in bytes, accepted when submitting a job.
+    /// This is set to default to 67108864.
+    /// This can also be set using the environment variable MAX_UPLOAD_SIZE.
+    #[clap(long, env, default_value = "67108864")]
+    pub max_upload_size: usize,
+
+    /// Public Lookups
+    ///
+    /// When API keys are configured, still allow GET requests without a key. Everything else
+    /// needs a key either way.
+    /// This can also be set using the environment variable PUBLIC_LOOKUPS.
+    #[clap(long, env)]
+    pub public_lookups: bool,
+
+    /// GraphQL Playground
+    ///
+    /// Serve the GraphQL playground at /graphql when built with the graphql feature. The page
+    /// loads its scripts, styles and fonts from the jsDelivr CDN and Google Fonts, so it is off
+    /// unless asked for.
+    /// This can also be set using the environment variable GRAPHQL_PLAYGROUND.
+    #[clap(long, env)]
+    pub graphql_playground: bool,
+
+    /// Command
+    ///
+    /// A one-off calculation to run instead of starting the server.
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Equal Loading
+    ///
+    /// Computes the sample, buffer and loading dye volumes needed to load the same amount of
+    /// protein in every lane of a western blot.
+    Loading(LoadingArgs),
+    /// New API Key
+    ///
+    /// Generates an API key and prints it along with the config file entry, holding only its
+    /// SHA-256, that lets the server accept it.
+    ApiKey(ApiKeyArgs),
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ApiKeyArgs {
+    /// Name
+    ///
+    /// Who the key is for. This is used in logs and to tell keys apart in the config file.
+    pub name: String,
+
+    /// Scope
+    ///
+    /// What the key may do: read for lookups and single scores, or batch to also submit
+    /// batches. This is set to default to read.
+    #[clap(short, long, default_value = "read")]
+    pub scope: Scope,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct LoadingArgs {
+    /// Concentrations File
+    ///
+    /// A CSV file with one `name,concentration` line per sample, in µg/mL.
+    /// A header line is allowed.
+    #[clap(short, long)]
+    pub concentrations: Option<PathBuf>,
+
+    /// Sample
+    ///
+    /// A sample given as NAME=CONCENTRATION, in µg/mL. This can be repeated and is
+    /// combined with any samples from the concentrations file.
+    #[clap(long = "sample", value_parser = parse_sample)]
+    pub samples: Vec<LoadingSample>,
+
+    /// Plate-Reader Export
+    ///
+    /// A BCA plate-reader export whose samples are quantified with --layout and loaded.
+    #[clap(long, requires = "layout")]
+    pub plate: Option<PathBuf>,
+
+    /// Plate Layout
+    ///
+    /// The TOML layout of the wells in --plate.
+    #[clap(long, requires = "plate")]
+    pub layout: Option<PathBuf>,
+
+    /// Curve Model
+    ///
+    /// The standard curve fitted to --plate: linear, quadratic or 4pl.
+    /// This is set to default to linear.
+    #[clap(long, default_value = "linear")]
+    pub curve_model: CurveModel,
+
+    /// BCA Results File
+    ///
+    /// A JSON plate analysis, or a plate job result holding one, whose samples are loaded.
+    #[clap(long)]
+    pub bca_results: Option<PathBuf>,
+
+    /// Target Protein
+    ///
+    /// The amount of protein to load per lane, in µg.
+    #[clap(short, long)]
+    pub target: f64,
+
+    /// Lane Volume
+    ///
+    /// The total volume to load per lane, including loading dye, in µL.
+    /// This should not exceed the well volume of the gel.
+    #[clap(short = 'v', long)]
+    pub lane_volume: f64,
+
+    /// Loading Dye Factor
+    ///
+    /// The concentration of the loading dye stock, e.g. 4 for 4x Laemmli buffer.
+    /// This is set to default to 4.
+    #[clap(short, long, default_value = "4")]
+    pub dye_factor: f64,
+
+    /// Output File
+    ///
+    /// Writes the loading table as CSV to this path as well as printing it.
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+fn parse_sample(value: &str) -> Result<LoadingSample, String> {
+    let (name, concentration) = value
+        .rsplit_once('=')
+        .ok_or_else(|| format!("expected NAME=CONCENTRATION, got '{value}'"))?;
+    let concentration = concentration
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|concentration| concentration.is_finite())
+        .ok_or_else(|| format!("invalid concentration '{concentration}'"))?;
+    Ok(LoadingSample::new(name.trim(), concentration))
 }
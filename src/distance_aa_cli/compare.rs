@@ -0,0 +1,232 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use crate::app::DistanceQuery;
+use crate::error::ApiError;
+use crate::limits::{timeout, Cancellation};
+use crate::state::AppState;
+use actix_web::middleware::from_fn;
+use actix_web::{post, web};
+use distance_aa_lib::{ProteinSequence, SequenceComparison};
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy)]
+pub struct CompareConfig {
+    max_alignment_cells: usize,
+}
+
+impl CompareConfig {
+    /// Alignments are refused when the product of the sequence lengths is over
+    /// `max_alignment_cells`.
+    pub const fn new(max_alignment_cells: usize) -> Self {
+        Self {
+            max_alignment_cells,
+        }
+    }
+}
+
+impl Default for CompareConfig {
+    fn default() -> Self {
+        Self::new(25_000_000)
+    }
+}
+
+/// Two sequences, each raw or a single FASTA record.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CompareRequest {
+    first: String,
+    second: String,
+    /// Globally align the sequences first, so that they may differ in length.
+    #[serde(default)]
+    align: bool,
+}
+
+#[utoipa::path(
+    context_path = "/api/v1",
+    params(DistanceQuery),
+    request_body = CompareRequest,
+    responses(
+        (status = 200, body = SequenceComparison),
+        (
+            status = 400,
+            description = "Invalid sequence, or lengths differ without `align`",
+            body = ApiError
+        ),
+        (status = 413, description = "The sequences are too long to align", body = ApiError),
+    )
+)]
+#[post(
+    "/sequences/compare",
+    name = "compare_sequences",
+    wrap = "from_fn(timeout)"
+)]
+pub async fn compare_sequences(
+    state: web::Data<AppState>,
+    config: web::Data<CompareConfig>,
+    body: web::Json<CompareRequest>,
+    query: web::Query<DistanceQuery>,
+) -> Result<web::Json<SequenceComparison>, ApiError> {
+    let first = parse_one("first", &body.first)?;
+    let second = parse_one("second", &body.second)?;
+    let (metric, align) = (query.metric, body.align);
+    let cells = first.len().saturating_mul(second.len());
+    if align && cells > config.max_alignment_cells {
+        return Err(ApiError::payload_too_large(format!(
+            "Aligning sequences of {} and {} residues is over the limit of {} residues \
+             multiplied together",
+            first.len(),
+            second.len(),
+            config.max_alignment_cells
+        )));
+    }
+    // Alignment is quadratic in the sequence lengths, so keep it off the worker thread, and
+    // stop it if the request times out.
+    let cancellation = Cancellation::default();
+    let cancelled = cancellation.flag();
+    web::block(move || {
+        let matrix = state.get_matrix(metric);
+        SequenceComparison::cancellable(&first, &second, matrix, align, &cancelled)
+    })
+    .await
+    .map_err(|err| ApiError::internal(err.into()))?
+    .map(web::Json)
+    .map_err(ApiError::bad_request)
+}
+
+fn parse_one(field: &str, input: &str) -> Result<ProteinSequence, ApiError> {
+    let mut sequences = ProteinSequence::parse(input)
+        .map_err(|err| ApiError::bad_request(format!("{field}: {err}")))?;
+    if sequences.len() != 1 {
+        return Err(ApiError::bad_request(format!(
+            "{field}: expected one sequence, found {}",
+            sequences.len()
+        )));
+    }
+    Ok(sequences.remove(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::app;
+    use actix_web::http::StatusCode;
+    use actix_web::test;
+
+    #[actix_web::test]
+    async fn test_compare_sequences() {
+        let app = test::init_service(app()).await;
+        let req = test::TestRequest::post()
+            .uri("/api/v1/sequences/compare")
+            .set_json(serde_json::json!({
+                "first": ">wild type\nAKRW",
+                "second": "KKCW"
+            }))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["total_distance"], 286);
+        assert_eq!(body["counts"]["identical"], 2);
+        assert_eq!(body["counts"]["radical"], 1);
+        assert_eq!(body["positions"][2]["distance"], 180);
+        assert_eq!(body["positions"][2]["class"], "radical");
+    }
+
+    #[actix_web::test]
+    async fn test_compare_sequences_lengths_differ() {
+        let app = test::init_service(app()).await;
+        let req = test::TestRequest::post()
+            .uri("/api/v1/sequences/compare")
+            .set_json(serde_json::json!({"first": "MKWVTF", "second": "MKVTF"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert!(body["message"]
+            .as_str()
+            .unwrap()
+            .contains("request an alignment"));
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/sequences/compare")
+            .set_json(serde_json::json!({"first": "MKWVTF", "second": "MKVTF", "align": true}))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["counts"]["gaps"], 1);
+    }
+
+    #[actix_web::test]
+    async fn test_compare_sequences_bad_body() {
+        let app = test::init_service(app()).await;
+        let req = test::TestRequest::post()
+            .uri("/api/v1/sequences/compare")
+            .set_json(serde_json::json!({"first": "MK"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["code"], "bad_request");
+    }
+
+    #[actix_web::test]
+    async fn test_compare_sequences_too_long_to_align() {
+        let app = test::init_service(app().app_data(web::Data::new(CompareConfig::new(100)))).await;
+        let req = test::TestRequest::post()
+            .uri("/api/v1/sequences/compare")
+            .set_json(serde_json::json!({
+                "first": "MKWVTFISLL",
+                "second": "MKWVTFISLLF",
+                "align": true
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/sequences/compare")
+            .set_json(serde_json::json!({"first": "MKWVTFISLL", "second": "MKWVTFISLL"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,423 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use crate::auth::ApiKey;
+use crate::cli::Args;
+use crate::logging::LogFormat;
+use anyhow::{ensure, Context, Result};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt::{self, Display, Formatter, Write};
+use std::path::{Path, PathBuf};
+
+/// Where an effective setting came from, from lowest to highest precedence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    File(PathBuf),
+    DotEnv(String),
+    Environment(String),
+    CommandLine,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::File(path) => write!(f, "config file {}", path.display()),
+            Self::DotEnv(var) => write!(f, ".env ({var})"),
+            Self::Environment(var) => write!(f, "environment ({var})"),
+            Self::CommandLine => write!(f, "command line"),
+        }
+    }
+}
+
+/// The settings a config file may give, keyed like the long options.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    server: Option<String>,
+    port: Option<u16>,
+    loglevel: Option<String>,
+    log_format: Option<LogFormat>,
+    access_log_format: Option<String>,
+    tls_cert: Option<PathBuf>,
+    tls_key: Option<PathBuf>,
+    workers: Option<usize>,
+    data_path: Option<PathBuf>,
+    cors_origins: Option<Vec<String>>,
+    cors_methods: Option<Vec<String>>,
+    cors_headers: Option<Vec<String>>,
+    cors_permissive: Option<bool>,
+    max_batch_size: Option<usize>,
+    max_alignment_cells: Option<usize>,
+    cache_max_age: Option<u32>,
+    max_body_size: Option<usize>,
+    rate_limit: Option<f64>,
+    rate_limit_burst: Option<u32>,
+    request_timeout: Option<u64>,
+    job_workers: Option<usize>,
+    max_jobs: Option<usize>,
+    max_jobs_per_client: Option<usize>,
+    job_ttl: Option<u64>,
+    max_upload_size: Option<usize>,
+    public_lookups: Option<bool>,
+    graphql_playground: Option<bool>,
+    api_keys: Option<Vec<ApiKey>>,
+}
+
+impl FileConfig {
+    fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read config file {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+}
+
+/// The effective server settings: command line, then environment (including `.env`), then the
+/// config file, then the defaults declared on `Args`.
+#[derive(Debug, Serialize)]
+pub struct Settings {
+    pub server: String,
+    pub port: u16,
+    pub loglevel: String,
+    pub log_format: LogFormat,
+    pub access_log_format: Option<String>,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    pub workers: Option<usize>,
+    pub data_path: PathBuf,
+    pub cors_origins: Vec<String>,
+    pub cors_methods: Vec<String>,
+    pub cors_headers: Vec<String>,
+    pub cors_permissive: bool,
+    pub max_batch_size: usize,
+    pub max_alignment_cells: usize,
+    pub cache_max_age: u32,
+    pub max_body_size: usize,
+    pub rate_limit: f64,
+    pub rate_limit_burst: u32,
+    pub request_timeout: u64,
+    pub job_workers: usize,
+    pub max_jobs: usize,
+    pub max_jobs_per_client: usize,
+    pub job_ttl: u64,
+    pub max_upload_size: usize,
+    pub public_lookups: bool,
+    pub graphql_playground: bool,
+    /// Only read from the config file, so that keys are not passed around in the environment.
+    pub api_keys: Vec<ApiKey>,
+    #[serde(skip)]
+    sources: Vec<(&'static str, Source)>,
+}
+
+impl Settings {
+    /// `dotenv` holds the variables that were set from `.env` rather than the real environment.
+    pub fn resolve(args: &Args, matches: &ArgMatches, dotenv: &HashSet<String>) -> Result<Self> {
+        let file = match &args.config {
+            Some(path) => FileConfig::load(path)?,
+            None => FileConfig::default(),
+        };
+        let mut layers = Layers {
+            command: Args::command(),
+            matches,
+            dotenv,
+            file_path: args.config.as_deref(),
+            sources: Vec::new(),
+        };
+        Ok(Self {
+            server: layers.pick("server", args.server.clone(), file.server),
+            port: layers.pick("port", args.port, file.port),
+            loglevel: layers.pick("loglevel", args.loglevel.clone(), file.loglevel),
+            log_format: layers.pick("log_format", args.log_format, file.log_format),
+            access_log_format: layers.pick(
+                "access_log_format",
+                args.access_log_format.clone(),
+                file.access_log_format.map(Some),
+            ),
+            tls_cert: layers.pick("tls_cert", args.tls_cert.clone(), file.tls_cert.map(Some)),
+            tls_key: layers.pick("tls_key", args.tls_key.clone(), file.tls_key.map(Some)),
+            workers: layers.pick("workers", args.workers, file.workers.map(Some)),
+            data_path: layers.pick("data_path", args.data_path.clone(), file.data_path),
+            cors_origins: layers.pick("cors_origins", args.cors_origins.clone(), file.cors_origins),
+            cors_methods: layers.pick("cors_methods", args.cors_methods.clone(), file.cors_methods),
+            cors_headers: layers.pick("cors_headers", args.cors_headers.clone(), file.cors_headers),
+            cors_permissive: layers.pick(
+                "cors_permissive",
+                args.cors_permissive,
+                file.cors_permissive,
+            ),
+            max_batch_size: layers.pick("max_batch_size", args.max_batch_size, file.max_batch_size),
+            max_alignment_cells: layers.pick(
+                "max_alignment_cells",
+                args.max_alignment_cells,
+                file.max_alignment_cells,
+            ),
+            cache_max_age: layers.pick("cache_max_age", args.cache_max_age, file.cache_max_age),
+            max_body_size: layers.pick("max_body_size", args.max_body_size, file.max_body_size),
+            rate_limit: layers.pick("rate_limit", args.rate_limit, file.rate_limit),
+            rate_limit_burst: layers.pick(
+                "rate_limit_burst",
+                args.rate_limit_burst,
+                file.rate_limit_burst,
+            ),
+            request_timeout: layers.pick(
+                "request_timeout",
+                args.request_timeout,
+                file.request_timeout,
+            ),
+            job_workers: layers.pick("job_workers", args.job_workers, file.job_workers),
+            max_jobs: layers.pick("max_jobs", args.max_jobs, file.max_jobs),
+            max_jobs_per_client: layers.pick(
+                "max_jobs_per_client",
+                args.max_jobs_per_client,
+                file.max_jobs_per_client,
+            ),
+            job_ttl: layers.pick("job_ttl", args.job_ttl, file.job_ttl),
+            max_upload_size: layers.pick(
+                "max_upload_size",
+                args.max_upload_size,
+                file.max_upload_size,
+            ),
+            public_lookups: layers.pick("public_lookups", args.public_lookups, file.public_lookups),
+            graphql_playground: layers.pick(
+                "graphql_playground",
+                args.graphql_playground,
+                file.graphql_playground,
+            ),
+            api_keys: layers.pick_file("api_keys", file.api_keys),
+            sources: layers.sources,
+        })
+        .and_then(Self::validate)
+    }
+    fn validate(self) -> Result<Self> {
+        ensure!(
+            self.tls_cert.is_some() == self.tls_key.is_some(),
+            "tls_cert and tls_key must be set together"
+        );
+        ensure!(
+            self.rate_limit.is_finite() && self.rate_limit >= 0.0,
+            "rate_limit must be a non-negative number"
+        );
+        ensure!(
+            self.job_workers > 0 && self.max_jobs > 0 && self.max_jobs_per_client > 0,
+            "job_workers, max_jobs and max_jobs_per_client must be at least 1"
+        );
+        Ok(self)
+    }
+    /// The settings as TOML, each annotated with its source.
+    pub fn describe(&self) -> Result<String> {
+        let table = toml::Value::try_from(self)?;
+        let mut text = String::new();
+        for (key, source) in &self.sources {
+            // Writing to a String cannot fail.
+            let _ = match table.get(key) {
+                Some(value) => writeln!(text, "{key} = {value} # {source}"),
+                None => writeln!(text, "# {key} is not set # {source}"),
+            };
+        }
+        Ok(text)
+    }
+}
+
+struct Layers<'a> {
+    command: clap::Command,
+    matches: &'a ArgMatches,
+    dotenv: &'a HashSet<String>,
+    file_path: Option<&'a Path>,
+    sources: Vec<(&'static str, Source)>,
+}
+
+impl Layers<'_> {
+    /// Takes the parsed argument unless clap only fell back to its default, in which case a
+    /// value from the config file wins.
+    fn pick<T>(&mut self, id: &'static str, arg: T, file: Option<T>) -> T {
+        let (value, source) = match (self.matches.value_source(id), file, self.file_path) {
+            (Some(ValueSource::CommandLine), _, _) => (arg, Source::CommandLine),
+            (Some(ValueSource::EnvVariable), _, _) => {
+                let var = self.env_name(id);
+                if self.dotenv.contains(&var) {
+                    (arg, Source::DotEnv(var))
+                } else {
+                    (arg, Source::Environment(var))
+                }
+            }
+            (_, Some(file), Some(path)) => (file, Source::File(path.to_path_buf())),
+            _ => (arg, Source::Default),
+        };
+        self.sources.push((id, source));
+        value
+    }
+    /// For settings with no command line option.
+    fn pick_file<T: Default>(&mut self, id: &'static str, file: Option<T>) -> T {
+        let (value, source) = match (file, self.file_path) {
+            (Some(file), Some(path)) => (file, Source::File(path.to_path_buf())),
+            _ => (T::default(), Source::Default),
+        };
+        self.sources.push((id, source));
+        value
+    }
+    fn env_name(&self, id: &str) -> String {
+        self.command
+            .get_arguments()
+            .find(|arg| arg.get_id() == id)
+            .and_then(|arg| arg.get_env())
+            .map(|var| var.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+}
+
+/// Sets variables from `.env` in the working directory, if there is one, without overriding the
+/// real environment. Returns the names of the variables it set.
+pub fn load_dotenv() -> Result<HashSet<String>> {
+    let iter = match dotenvy::dotenv_iter() {
+        Ok(iter) => iter,
+        Err(err) if err.not_found() => return Ok(HashSet::new()),
+        Err(err) => return Err(err).context("Could not read .env"),
+    };
+    let mut keys = HashSet::new();
+    for item in iter {
+        let (key, value) = item.context("Could not parse .env")?;
+        if std::env::var_os(&key).is_none() {
+            std::env::set_var(&key, value);
+            keys.insert(key);
+        }
+    }
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::FromArgMatches;
+
+    fn resolve(argv: &[&str], dotenv: &HashSet<String>) -> Result<Settings> {
+        let matches = Args::command().try_get_matches_from(argv)?;
+        let args = Args::from_arg_matches(&matches)?;
+        Settings::resolve(&args, &matches, dotenv)
+    }
+
+    fn source<'a>(settings: &'a Settings, key: &str) -> Option<&'a Source> {
+        settings
+            .sources
+            .iter()
+            .find(|(name, _)| *name == key)
+            .map(|(_, source)| source)
+    }
+
+    fn config_file(name: &str, contents: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("distaance-{name}-{}.toml", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_resolve_layers() {
+        let path = config_file(
+            "layers",
+            "port = 9000\nmax_batch_size = 5\nworkers = 2\ncors_origins = [\"https://example.org\"]\n",
+        );
+        let settings = resolve(
+            &[
+                "distance_aa_cli",
+                "--config",
+                path.to_str().unwrap(),
+                "--max-batch-size",
+                "7",
+            ],
+            &HashSet::new(),
+        )
+        .unwrap();
+        assert_eq!(settings.port, 9000);
+        assert_eq!(source(&settings, "port"), Some(&Source::File(path.clone())));
+        assert_eq!(settings.max_batch_size, 7);
+        assert_eq!(
+            source(&settings, "max_batch_size"),
+            Some(&Source::CommandLine)
+        );
+        assert_eq!(settings.workers, Some(2));
+        assert_eq!(settings.cors_origins, vec!["https://example.org"]);
+        assert_eq!(settings.server, "0.0.0.0");
+        assert_eq!(source(&settings, "server"), Some(&Source::Default));
+
+        let description = settings.describe().unwrap();
+        assert!(description.contains("max_batch_size = 7 # command line\n"));
+        assert!(description.contains("# access_log_format is not set # default\n"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_rejects_unknown_keys() {
+        let path = config_file("unknown", "prot = 9000\n");
+        let error = resolve(
+            &["distance_aa_cli", "--config", path.to_str().unwrap()],
+            &HashSet::new(),
+        )
+        .unwrap_err();
+        assert!(format!("{error:#}").contains("unknown field `prot`"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_requires_tls_pair() {
+        let path = config_file("tls", "tls_cert = \"server.crt\"\n");
+        let error = resolve(
+            &["distance_aa_cli", "--config", path.to_str().unwrap()],
+            &HashSet::new(),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("must be set together"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_without_file() {
+        let settings = resolve(&["distance_aa_cli"], &HashSet::new()).unwrap();
+        assert_eq!(settings.port, 8080);
+        assert_eq!(source(&settings, "port"), Some(&Source::Default));
+    }
+}
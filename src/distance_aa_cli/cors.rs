@@ -0,0 +1,222 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use crate::logging::REQUEST_ID_HEADER;
+use actix_cors::Cors;
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::http::Method;
+use anyhow::{anyhow, ensure, Result};
+
+/// How long browsers may cache a preflight response, in seconds.
+const PREFLIGHT_MAX_AGE: usize = 3600;
+
+/// Validated CORS settings, from which each worker builds its middleware.
+#[derive(Debug, Clone)]
+pub struct CorsConfig {
+    /// `None` allows any origin.
+    origins: Option<Vec<String>>,
+    methods: Vec<Method>,
+    headers: Vec<HeaderName>,
+    permissive: bool,
+}
+
+impl CorsConfig {
+    /// # Errors
+    ///
+    /// Returns an error for an origin that is not `*` or an `http(s)://` origin, or an invalid
+    /// method or header name.
+    pub fn new(
+        origins: &[String],
+        methods: &[String],
+        headers: &[String],
+        permissive: bool,
+    ) -> Result<Self> {
+        let origins = if origins.iter().any(|origin| origin.trim() == "*") {
+            None
+        } else {
+            let mut valid = Vec::with_capacity(origins.len());
+            for origin in origins {
+                let origin = origin.trim().trim_end_matches('/');
+                ensure!(
+                    (origin.starts_with("http://") || origin.starts_with("https://"))
+                        && HeaderValue::from_str(origin).is_ok(),
+                    "Invalid CORS origin: {origin}"
+                );
+                valid.push(origin.to_string());
+            }
+            Some(valid)
+        };
+        let methods = methods
+            .iter()
+            .map(|method| {
+                method
+                    .trim()
+                    .to_ascii_uppercase()
+                    .parse::<Method>()
+                    .map_err(|_| anyhow!("Invalid CORS method: {method}"))
+            })
+            .collect::<Result<_>>()?;
+        let headers = headers
+            .iter()
+            .map(|header| {
+                HeaderName::from_bytes(header.trim().as_bytes())
+                    .map_err(|_| anyhow!("Invalid CORS header: {header}"))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            origins,
+            methods,
+            headers,
+            permissive,
+        })
+    }
+    pub const fn is_permissive(&self) -> bool {
+        self.permissive
+    }
+    pub fn middleware(&self) -> Cors {
+        if self.permissive {
+            return Cors::permissive();
+        }
+        let mut cors = Cors::default()
+            .allowed_methods(self.methods.clone())
+            .allowed_headers(self.headers.clone())
+            .expose_headers([HeaderName::from_static(REQUEST_ID_HEADER)])
+            .max_age(PREFLIGHT_MAX_AGE);
+        match &self.origins {
+            None => cors = cors.allow_any_origin(),
+            Some(origins) => {
+                for origin in origins {
+                    cors = cors.allowed_origin(origin);
+                }
+            }
+        }
+        cors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{test as actix_test, web, App, HttpResponse};
+
+    fn config(origins: &[&str], permissive: bool) -> CorsConfig {
+        let origins: Vec<String> = origins.iter().map(ToString::to_string).collect();
+        CorsConfig::new(
+            &origins,
+            &["GET".into(), "post".into()],
+            &["content-type".into()],
+            permissive,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_new_rejects_invalid_settings() {
+        assert!(CorsConfig::new(&["example.org".into()], &[], &[], false).is_err());
+        assert!(CorsConfig::new(&[], &["G ET".into()], &[], false).is_err());
+        assert!(CorsConfig::new(&[], &[], &["bad header".into()], false).is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_middleware() {
+        let app = actix_test::init_service(
+            App::new()
+                .wrap(config(&["https://distaance.example.org/"], false).middleware())
+                .route("/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let req = actix_test::TestRequest::default()
+            .method(Method::OPTIONS)
+            .insert_header((header::ORIGIN, "https://distaance.example.org"))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "POST"))
+            .to_request();
+        let resp = actix_test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers()
+                .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                .unwrap(),
+            "https://distaance.example.org"
+        );
+
+        let req = actix_test::TestRequest::default()
+            .method(Method::OPTIONS)
+            .insert_header((header::ORIGIN, "https://distaance.example.org"))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "DELETE"))
+            .to_request();
+        let resp = actix_test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let req = actix_test::TestRequest::get()
+            .insert_header((header::ORIGIN, "https://elsewhere.example.org"))
+            .to_request();
+        let resp = actix_test::call_service(&app, req).await;
+        assert!(resp
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_middleware_permissive() {
+        let app = actix_test::init_service(
+            App::new()
+                .wrap(config(&[], true).middleware())
+                .route("/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let req = actix_test::TestRequest::get()
+            .insert_header((header::ORIGIN, "http://localhost:3000"))
+            .to_request();
+        let resp = actix_test::call_service(&app, req).await;
+        assert_eq!(
+            resp.headers()
+                .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                .unwrap(),
+            "http://localhost:3000"
+        );
+    }
+}
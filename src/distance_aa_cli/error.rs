@@ -0,0 +1,330 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use crate::logging::current_request_id;
+use actix_web::http::{header, StatusCode};
+use actix_web::{HttpResponse, ResponseError};
+use distance_aa_lib::AminoAcid;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use std::borrow::Cow;
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+use utoipa::openapi::schema::{ArrayBuilder, ObjectBuilder, Schema, Type};
+use utoipa::openapi::RefOr;
+use utoipa::{PartialSchema, ToSchema};
+
+/// The error type for every handler, rendered as a JSON body with a matching status code.
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+    input: Option<String>,
+    suggestions: Vec<String>,
+    retry_after: Option<Duration>,
+    source: Option<anyhow::Error>,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, code: &'static str, message: String) -> Self {
+        Self {
+            status,
+            code,
+            message,
+            input: None,
+            suggestions: Vec::new(),
+            retry_after: None,
+            source: None,
+        }
+    }
+    pub fn not_found(message: impl Display) -> Self {
+        Self::new(StatusCode::NOT_FOUND, "not_found", message.to_string())
+    }
+    pub fn bad_request(message: impl Display) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "bad_request", message.to_string())
+    }
+    /// Sent with `WWW-Authenticate: Bearer`.
+    pub fn unauthorized(message: impl Display) -> Self {
+        Self::new(
+            StatusCode::UNAUTHORIZED,
+            "unauthorized",
+            message.to_string(),
+        )
+    }
+    pub fn forbidden(message: impl Display) -> Self {
+        Self::new(StatusCode::FORBIDDEN, "forbidden", message.to_string())
+    }
+    pub fn conflict(message: impl Display) -> Self {
+        Self::new(StatusCode::CONFLICT, "conflict", message.to_string())
+    }
+    pub fn unsupported_media_type(message: impl Display) -> Self {
+        Self::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "unsupported_media_type",
+            message.to_string(),
+        )
+    }
+    pub fn unavailable(message: impl Display) -> Self {
+        Self::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "unavailable",
+            message.to_string(),
+        )
+    }
+    pub fn not_acceptable(message: impl Display) -> Self {
+        Self::new(
+            StatusCode::NOT_ACCEPTABLE,
+            "not_acceptable",
+            message.to_string(),
+        )
+    }
+    /// Sent with `Retry-After`, rounded up to whole seconds.
+    pub fn too_many_requests(retry_after: Duration) -> Self {
+        let mut error = Self::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            "rate_limited",
+            "Too many requests, slow down".to_string(),
+        );
+        error.retry_after = Some(retry_after);
+        error
+    }
+    pub fn timeout(after: Duration) -> Self {
+        Self::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "timeout",
+            format!("The request took longer than {}s", after.as_secs_f64()),
+        )
+    }
+    pub fn payload_too_large(message: impl Display) -> Self {
+        Self::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "payload_too_large",
+            message.to_string(),
+        )
+    }
+    pub fn internal(source: anyhow::Error) -> Self {
+        let mut error = Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal_error",
+            "Internal server error".to_string(),
+        );
+        error.source = Some(source);
+        error
+    }
+
+    /// A 404 for a residue that matched nothing, suggesting the closest names in `library`.
+    pub fn unknown_amino_acid(input: &str, library: &[AminoAcid]) -> Self {
+        let mut error = Self::new(
+            StatusCode::NOT_FOUND,
+            "unknown_amino_acid",
+            format!("Unknown amino acid: {input}"),
+        )
+        .with_input(input);
+        error.suggestions = suggest(input, library);
+        error
+    }
+    #[must_use]
+    pub fn with_input(mut self, input: &str) -> Self {
+        self.input = Some(input.to_string());
+        self
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match &self.source {
+            Some(source) => write!(f, "{}: {source:#}", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+    /// Also logs the cause of an internal error, which the client is not shown, with the ID of
+    /// the request so that the two can be matched up.
+    fn error_response(&self) -> HttpResponse {
+        if let Some(source) = &self.source {
+            let id = current_request_id().unwrap_or_else(|| "-".to_string());
+            log::error!("Request {id} failed: {source:#}");
+        }
+        let mut response = HttpResponse::build(self.status);
+        if let Some(retry_after) = self.retry_after {
+            let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            response.insert_header((header::RETRY_AFTER, seconds));
+        }
+        if self.status == StatusCode::UNAUTHORIZED {
+            response.insert_header((header::WWW_AUTHENTICATE, "Bearer"));
+        }
+        response.json(self)
+    }
+}
+
+/// The JSON body: `code`, `message`, and `input` and `suggestions` when present.
+impl Serialize for ApiError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut body = serializer.serialize_struct("ApiError", 4)?;
+        body.serialize_field("code", self.code)?;
+        body.serialize_field("message", &self.message)?;
+        match &self.input {
+            Some(input) => body.serialize_field("input", input)?,
+            None => body.skip_field("input")?,
+        }
+        if self.suggestions.is_empty() {
+            body.skip_field("suggestions")?;
+        } else {
+            body.serialize_field("suggestions", &self.suggestions)?;
+        }
+        body.end()
+    }
+}
+
+/// Describes the body written by the `Serialize` impl above.
+impl PartialSchema for ApiError {
+    fn schema() -> RefOr<Schema> {
+        let string = || ObjectBuilder::new().schema_type(Type::String);
+        ObjectBuilder::new()
+            .property(
+                "code",
+                string().description(Some("Stable, machine-readable error code")),
+            )
+            .property("message", string())
+            .property(
+                "input",
+                string().description(Some("The part of the request that was rejected")),
+            )
+            .property("suggestions", ArrayBuilder::new().items(string()))
+            .required("code")
+            .required("message")
+            .into()
+    }
+}
+
+impl ToSchema for ApiError {
+    fn name() -> Cow<'static, str> {
+        Cow::Borrowed("Error")
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(source: anyhow::Error) -> Self {
+        Self::internal(source)
+    }
+}
+
+/// Up to three residue names whose name or codes are within a small edit distance of
+/// `input`, closest first.
+fn suggest(input: &str, library: &[AminoAcid]) -> Vec<String> {
+    let input = input.trim().to_ascii_lowercase();
+    let threshold = (input.len() / 3).max(1);
+    let mut scored: Vec<(usize, String)> = library
+        .iter()
+        .filter_map(|aa| {
+            let score = [aa.get_name(), aa.get_short_name(), aa.get_abbreviation()]
+                .iter()
+                .map(|candidate| {
+                    let candidate = candidate.to_ascii_lowercase();
+                    if input.len() > 1 && candidate.starts_with(&input) {
+                        0
+                    } else {
+                        edit_distance(&input, &candidate)
+                    }
+                })
+                .min()?;
+            (score <= threshold).then(|| (score, aa.get_name()))
+        })
+        .collect();
+    scored.sort();
+    scored.into_iter().take(3).map(|(_, name)| name).collect()
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn library() -> Vec<AminoAcid> {
+        vec![
+            AminoAcid::new("Alanine", "Ala", "A", "Nonpolar", 89.09, &["GCT"]),
+            AminoAcid::new("Arginine", "Arg", "R", "Basic", 174.2, &["CGT"]),
+            AminoAcid::new("Lysine", "Lys", "K", "Basic", 146.19, &["AAA"]),
+        ]
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("alanine", "alanine"), 0);
+        assert_eq!(edit_distance("alanin", "alanine"), 1);
+        assert_eq!(edit_distance("lysne", "lysine"), 1);
+        assert_eq!(edit_distance("", "ala"), 3);
+    }
+    #[test]
+    fn test_suggest() {
+        assert_eq!(suggest("Alanin", &library()), vec!["Alanine"]);
+        assert_eq!(suggest("Ar", &library())[0], "Arginine");
+        assert!(suggest("Tryptophan", &library()).is_empty());
+    }
+    #[test]
+    fn test_error_response() {
+        let error = ApiError::unknown_amino_acid("Lysne", &library());
+        assert_eq!(error.status_code(), StatusCode::NOT_FOUND);
+        assert_eq!(error.to_string(), "Unknown amino acid: Lysne");
+    }
+}
@@ -0,0 +1,475 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use crate::limits::timeout;
+use crate::state::AppState;
+use actix_web::middleware::from_fn;
+use actix_web::{get, post, web, HttpResponse};
+use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, Enum, Object, Request, Response, Result, Schema,
+};
+use distance_aa_lib::{
+    AminoAcid, ExtinctionCoefficient, GranthamDistance, HydropathyPoint, ProteinSequence,
+};
+use std::sync::Arc;
+
+/// Deepest selection accepted. Introspection from the playground needs about a dozen levels;
+/// a residue, its neighbours and their neighbours take five.
+pub const MAX_DEPTH: usize = 16;
+
+/// Highest cost accepted, counting one per field and multiplying by the size of lists and the
+/// length of sequences.
+pub const MAX_COMPLEXITY: usize = 2000;
+
+pub type DistanceSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+pub fn schema() -> DistanceSchema {
+    Schema::build(Query, EmptyMutation, EmptySubscription)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+#[graphql(remote = "distance_aa_lib::DistanceMetric")]
+pub enum DistanceMetric {
+    Grantham,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+#[graphql(remote = "distance_aa_lib::HydropathyScale")]
+pub enum HydropathyScale {
+    KyteDoolittle,
+    HoppWoods,
+    Eisenberg,
+    EngelmanGes,
+}
+
+fn state<'a>(ctx: &Context<'a>) -> Result<&'a web::Data<AppState>> {
+    ctx.data::<web::Data<AppState>>()
+}
+
+/// Runs `f` on the blocking pool, as the REST handlers do with work that grows with the input.
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> anyhow::Result<T> + Send + 'static,
+) -> Result<T> {
+    Ok(web::block(f).await??)
+}
+
+/// The cost of scoring `input`: a child selection per record and another per hundred
+/// residues, since parsing, scoring and profiles all grow with the length.
+fn sequences_complexity(input: &str, child_complexity: usize) -> usize {
+    let records = input.matches('>').count().max(1);
+    let residues: usize = input
+        .lines()
+        .filter(|line| !line.starts_with('>'))
+        .map(|line| line.trim().len())
+        .sum();
+    (records + residues / 100).saturating_mul(child_complexity)
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// Looks an amino acid up by name, three-letter or one-letter code.
+    async fn amino_acid(&self, ctx: &Context<'_>, id: String) -> Result<Residue> {
+        Ok(Residue(state(ctx)?.find(&id)?.clone()))
+    }
+    /// Every amino acid in the library.
+    #[graphql(complexity = "25 * child_complexity")]
+    async fn amino_acids(&self, ctx: &Context<'_>) -> Result<Vec<Residue>> {
+        Ok(state(ctx)?
+            .get_library()
+            .iter()
+            .cloned()
+            .map(Residue)
+            .collect())
+    }
+    /// The distance between two amino acids.
+    async fn distance(
+        &self,
+        ctx: &Context<'_>,
+        first: String,
+        second: String,
+        #[graphql(default_with = "DistanceMetric::Grantham")] metric: DistanceMetric,
+    ) -> Result<Option<Distance>> {
+        let state = state(ctx)?;
+        let first = state.find(&first)?;
+        let second = state.find(&second)?;
+        Ok(state.distance(first, second, metric.into()).map(Distance))
+    }
+    /// Scores a raw sequence or each record of a FASTA file.
+    #[graphql(complexity = "sequences_complexity(&input, child_complexity)")]
+    async fn sequences(&self, input: String) -> Result<Vec<Sequence>> {
+        let sequences = blocking(move || ProteinSequence::parse(&input)).await?;
+        Ok(sequences
+            .into_iter()
+            .map(|sequence| Sequence(Arc::new(sequence)))
+            .collect())
+    }
+}
+
+/// An amino acid, with its distances to the others.
+pub struct Residue(AminoAcid);
+
+#[Object(name = "AminoAcid")]
+impl Residue {
+    async fn name(&self) -> String {
+        self.0.get_name()
+    }
+    async fn short_name(&self) -> String {
+        self.0.get_short_name()
+    }
+    async fn abbreviation(&self) -> String {
+        self.0.get_abbreviation()
+    }
+    async fn side_chain(&self) -> String {
+        self.0.get_side_chain()
+    }
+    async fn molecular_weight(&self) -> f64 {
+        self.0.get_molecular_weight()
+    }
+    async fn codons(&self) -> Vec<String> {
+        self.0.get_codon()
+    }
+    /// `null` for residues the scale has no value for.
+    async fn hydropathy(
+        &self,
+        #[graphql(default_with = "HydropathyScale::KyteDoolittle")] scale: HydropathyScale,
+    ) -> Option<f64> {
+        self.0.get_hydropathy(scale.into())
+    }
+    /// The distance to another amino acid.
+    async fn distance(
+        &self,
+        ctx: &Context<'_>,
+        to: String,
+        #[graphql(default_with = "DistanceMetric::Grantham")] metric: DistanceMetric,
+    ) -> Result<Option<Distance>> {
+        let state = state(ctx)?;
+        let to = state.find(&to)?;
+        Ok(state.distance(&self.0, to, metric.into()).map(Distance))
+    }
+    /// The closest other amino acids, nearest first. Ties keep the library order.
+    #[graphql(complexity = "limit.max(0) as usize * child_complexity")]
+    async fn neighbours(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 5, validator(minimum = 0))] limit: i32,
+        #[graphql(default_with = "DistanceMetric::Grantham")] metric: DistanceMetric,
+    ) -> Result<Vec<Distance>> {
+        let state = state(ctx)?;
+        let mut distances: Vec<GranthamDistance> = state
+            .get_library()
+            .iter()
+            .filter(|other| **other != self.0)
+            .filter_map(|other| state.distance(&self.0, other, metric.into()))
+            .collect();
+        distances.sort_by_key(GranthamDistance::get_distance);
+        let limit = usize::try_from(limit).unwrap_or_default();
+        Ok(distances.into_iter().take(limit).map(Distance).collect())
+    }
+}
+
+pub struct Distance(GranthamDistance);
+
+#[Object(name = "GranthamDistance")]
+impl Distance {
+    async fn first(&self) -> Residue {
+        Residue(self.0.get_first())
+    }
+    async fn second(&self) -> Residue {
+        Residue(self.0.get_second())
+    }
+    async fn distance(&self) -> usize {
+        self.0.get_distance()
+    }
+}
+
+pub struct Sequence(Arc<ProteinSequence>);
+
+#[Object(name = "SequenceScore")]
+impl Sequence {
+    async fn id(&self) -> Option<String> {
+        self.0.get_id()
+    }
+    async fn residues(&self) -> String {
+        self.0.get_residues()
+    }
+    async fn length(&self) -> usize {
+        self.0.len()
+    }
+    /// In daltons.
+    async fn molecular_weight(&self, ctx: &Context<'_>) -> Result<f64> {
+        let (sequence, state) = (Arc::clone(&self.0), state(ctx)?.clone());
+        blocking(move || sequence.molecular_weight(state.get_library())).await
+    }
+    /// At 280 nm, by the Pace method.
+    async fn extinction_coefficient(&self, ctx: &Context<'_>) -> Result<Extinction> {
+        let (sequence, state) = (Arc::clone(&self.0), state(ctx)?.clone());
+        blocking(move || sequence.extinction_coefficient(state.get_library()))
+            .await
+            .map(Extinction)
+    }
+    /// Grand average of hydropathy.
+    async fn gravy(
+        &self,
+        #[graphql(default_with = "HydropathyScale::KyteDoolittle")] scale: HydropathyScale,
+    ) -> Result<f64> {
+        let sequence = Arc::clone(&self.0);
+        blocking(move || Ok(sequence.gravy(scale.into()))).await
+    }
+    /// Sliding-window hydropathy, reported at the central residue of each window.
+    #[graphql(complexity = "10 * child_complexity")]
+    async fn hydropathy_profile(
+        &self,
+        #[graphql(default_with = "HydropathyScale::KyteDoolittle")] scale: HydropathyScale,
+        #[graphql(default = 9)] window: usize,
+    ) -> Result<Vec<Point>> {
+        let sequence = Arc::clone(&self.0);
+        let profile = blocking(move || sequence.hydropathy_profile(scale.into(), window)).await?;
+        Ok(profile.get_points().into_iter().map(Point).collect())
+    }
+}
+
+pub struct Extinction(ExtinctionCoefficient);
+
+#[Object(name = "ExtinctionCoefficient")]
+impl Extinction {
+    /// With every cysteine reduced, in M⁻¹ cm⁻¹.
+    async fn reduced(&self) -> f64 {
+        self.0.get_reduced()
+    }
+    /// With every cysteine pair forming a cystine, in M⁻¹ cm⁻¹.
+    async fn oxidised(&self) -> f64 {
+        self.0.get_oxidised()
+    }
+    async fn molecular_weight(&self) -> f64 {
+        self.0.get_molecular_weight()
+    }
+}
+
+pub struct Point(HydropathyPoint);
+
+#[Object(name = "HydropathyPoint")]
+impl Point {
+    /// One-based.
+    async fn position(&self) -> usize {
+        self.0.get_position()
+    }
+    async fn residue(&self) -> String {
+        self.0.get_residue().to_string()
+    }
+    async fn score(&self) -> f64 {
+        self.0.get_score()
+    }
+}
+
+/// Runs a GraphQL query over residues, distances and sequences.
+#[post("/graphql", name = "graphql", wrap = "from_fn(timeout)")]
+pub async fn execute(
+    schema: web::Data<DistanceSchema>,
+    state: web::Data<AppState>,
+    request: web::Json<Request>,
+) -> web::Json<Response> {
+    web::Json(schema.execute(request.into_inner().data(state)).await)
+}
+
+/// A GraphQL playground pointed at the API, registered only when `graphql_playground` is set.
+/// Unlike the Swagger UI it is not bundled: the page pulls its assets from the jsDelivr CDN and
+/// Google Fonts, so browsers using it need to reach both.
+#[get("/graphql")]
+pub async fn playground() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(playground_source(GraphQLPlaygroundConfig::new(
+            "/api/v1/graphql",
+        )))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::configure;
+    use crate::testing::{app, state};
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App};
+    use serde_json::{json, Value};
+
+    const INTROSPECTION: &str = "query {
+        __schema {
+            queryType { name }
+            types {
+                kind name description
+                fields(includeDeprecated: true) {
+                    name description isDeprecated deprecationReason
+                    args { name description defaultValue type { ...TypeRef } }
+                    type { ...TypeRef }
+                }
+                inputFields { name description defaultValue type { ...TypeRef } }
+                interfaces { ...TypeRef }
+                enumValues(includeDeprecated: true) { name description }
+                possibleTypes { ...TypeRef }
+            }
+            directives { name description locations args { name type { ...TypeRef } } }
+        }
+    }
+    fragment TypeRef on __Type {
+        kind name
+        ofType { kind name ofType { kind name ofType { kind name ofType { kind name
+            ofType { kind name ofType { kind name ofType { kind name } } } } } } }
+    }";
+
+    async fn query(query: &str) -> Value {
+        let app = test::init_service(app()).await;
+        let req = test::TestRequest::post()
+            .uri("/api/v1/graphql")
+            .set_json(json!({ "query": query }))
+            .to_request();
+        test::call_and_read_body_json(&app, req).await
+    }
+
+    #[actix_web::test]
+    async fn test_residue_with_neighbours() {
+        let body = query(
+            "{ aminoAcid(id: \"Leu\") {
+                name codons
+                distance(to: \"I\") { distance }
+                neighbours(limit: 2) { distance second { abbreviation } }
+            } }",
+        )
+        .await;
+        let residue = &body["data"]["aminoAcid"];
+        assert_eq!(residue["name"], "Leucine");
+        assert!(residue["codons"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("CTG")));
+        assert_eq!(residue["distance"]["distance"], 5);
+        assert_eq!(residue["neighbours"][0]["second"]["abbreviation"], "I");
+        assert_eq!(residue["neighbours"].as_array().unwrap().len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn test_distance_and_sequences() {
+        let body = query(
+            "{ distance(first: \"W\", second: \"R\") { distance }
+               sequences(input: \">P1\\nMKWVTF\") {
+                   id length gravy(scale: HOPP_WOODS)
+                   extinctionCoefficient { reduced }
+                   hydropathyProfile(window: 3) { position residue }
+               } }",
+        )
+        .await;
+        assert_eq!(body["data"]["distance"]["distance"], 101, "{body}");
+        let sequence = &body["data"]["sequences"][0];
+        assert_eq!(sequence["id"], "P1");
+        assert_eq!(sequence["length"], 6);
+        assert_eq!(sequence["extinctionCoefficient"]["reduced"], 5500.0);
+        assert_eq!(sequence["hydropathyProfile"][0]["position"], 2);
+
+        let body = query("{ aminoAcid(id: \"Xyz\") { name } }").await;
+        assert!(body["data"].is_null());
+        assert!(body["errors"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("Xyz"));
+    }
+
+    #[actix_web::test]
+    async fn test_limits() {
+        let body = query(INTROSPECTION).await;
+        assert!(body.get("errors").is_none(), "{body}");
+        assert!(body["data"]["__schema"]["types"].is_array());
+
+        let mut nested = "name".to_string();
+        for _ in 0..8 {
+            nested = format!("neighbours(limit: 1) {{ second {{ {nested} }} }}");
+        }
+        let body = query(&format!("{{ aminoAcid(id: \"A\") {{ {nested} }} }}")).await;
+        assert_eq!(body["errors"][0]["message"], "Query is nested too deep.");
+
+        let body = query(
+            "{ aminoAcids { neighbours(limit: 19) { second { neighbours(limit: 19) { distance } } } } }",
+        )
+        .await;
+        assert_eq!(body["errors"][0]["message"], "Query is too complex.");
+
+        let input = format!(">P1\n{}", "MKWVTF".repeat(10_000));
+        let body = query(&format!(
+            "{{ sequences(input: \"{}\") {{ hydropathyProfile(window: 1) {{ score }} }} }}",
+            input.replace('\n', "\\n")
+        ))
+        .await;
+        assert_eq!(body["errors"][0]["message"], "Query is too complex.");
+        let body = query(&format!(
+            "{{ sequences(input: \"{}\") {{ length }} }}",
+            input.replace('\n', "\\n")
+        ))
+        .await;
+        assert_eq!(body["data"]["sequences"][0]["length"], 60_000, "{body}");
+    }
+
+    #[actix_web::test]
+    async fn test_playground() {
+        let service = test::init_service(app()).await;
+        let req = test::TestRequest::get().uri("/graphql").to_request();
+        let resp = test::call_service(&service, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let service = test::init_service(
+            App::new()
+                .app_data(state())
+                .service(super::playground)
+                .configure(configure),
+        )
+        .await;
+        let req = test::TestRequest::get().uri("/graphql").to_request();
+        let body = test::call_and_read_body(&service, req).await;
+        assert!(std::str::from_utf8(&body)
+            .unwrap()
+            .contains("/api/v1/graphql"));
+    }
+}
@@ -0,0 +1,583 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+mod events;
+mod queue;
+mod work;
+
+use events::{JobEvents, MAX_RESULTS_PER_CHUNK};
+pub use queue::{sweep_expired, Job, JobConfig, JobQueue, JobState, JobStatus};
+pub use work::{JobInput, JobRecord};
+
+use crate::auth::{batch_scope, Caller};
+use crate::batch::is_ndjson;
+use crate::error::ApiError;
+use crate::limits;
+use crate::state::AppState;
+use actix_web::http::header::{self, Header};
+use actix_web::middleware::from_fn;
+use actix_web::web::Bytes;
+use actix_web::{delete, get, post, web, HttpMessage, HttpRequest, HttpResponse};
+use distance_aa_lib::{DistanceMetric, HydropathyScale};
+use futures_util::stream::{self, Stream};
+use serde::Deserialize;
+use std::sync::Arc;
+use utoipa::IntoParams;
+
+/// Content types read as FASTA rather than as variant items.
+const FASTA_TYPES: [&str; 4] = [
+    "text/x-fasta",
+    "application/x-fasta",
+    "chemical/seq-aa-fasta",
+    "text/plain",
+];
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct JobQuery {
+    /// For variant jobs. Defaults to `grantham`.
+    #[serde(default)]
+    pub metric: DistanceMetric,
+    /// For sequence jobs. Defaults to `kyte_doolittle`.
+    #[serde(default)]
+    pub scale: HydropathyScale,
+}
+
+/// The API key name a request was made with, if any.
+pub fn caller(req: &HttpRequest) -> Option<String> {
+    req.extensions()
+        .get::<Caller>()
+        .map(|caller| caller.get_name().to_string())
+}
+
+/// The job, or a 404 if it does not exist, has expired or belongs to another key.
+pub fn find_job(req: &HttpRequest, queue: &JobQueue, id: &str) -> Result<Arc<Job>, ApiError> {
+    queue
+        .get(id, caller(req).as_deref())
+        .ok_or_else(|| ApiError::not_found(format!("No job {id}; it may have expired")))
+}
+
+/// A `202` pointing at the status of a job that was just submitted.
+pub fn accepted(req: &HttpRequest, job: &Job) -> Result<HttpResponse, ApiError> {
+    let location = req
+        .url_for("job_status", [job.get_id()])
+        .map_err(|err| ApiError::internal(err.into()))?;
+    Ok(HttpResponse::Accepted()
+        .insert_header((header::LOCATION, location.path()))
+        .json(job.status()))
+}
+
+/// Starts a background job: variant items in any format the batch endpoint takes, or FASTA
+/// records sent as `text/x-fasta`. Poll the URL in `Location` for progress.
+#[utoipa::path(
+    context_path = "/api/v1",
+    params(JobQuery),
+    request_body(
+        description = "Variant items as a JSON array or NDJSON, or protein sequences as FASTA",
+        content(
+            (Vec<crate::batch::BatchItem> = "application/json"),
+            (String = "text/x-fasta"),
+        )
+    ),
+    responses(
+        (status = 202, description = "The job was queued", body = JobStatus),
+        (status = 400, description = "The body could not be read", body = ApiError),
+        (status = 413, description = "The body is over the upload limit", body = ApiError),
+        (status = 415, description = "Unsupported content type", body = ApiError),
+        (status = 503, description = "Too many jobs are held", body = ApiError),
+    )
+)]
+#[post("/jobs", name = "submit_job", wrap = "from_fn(batch_scope)")]
+pub async fn submit_job(
+    req: HttpRequest,
+    payload: web::Payload,
+    query: web::Query<JobQuery>,
+    state: web::Data<AppState>,
+    queue: web::Data<JobQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let ndjson = is_ndjson(&req);
+    let fasta = FASTA_TYPES.contains(&req.content_type());
+    if !ndjson && !fasta && req.content_type() != "application/json" {
+        return Err(ApiError::unsupported_media_type(
+            "Send variant items as application/json or application/x-ndjson, or sequences as \
+             text/x-fasta",
+        ));
+    }
+    let limit = queue.get_config().get_max_upload_size();
+    let body = payload
+        .to_bytes_limited(limit)
+        .await
+        .map_err(|_| ApiError::payload_too_large(format!("Uploads are limited to {limit} bytes")))?
+        .map_err(ApiError::bad_request)?;
+    let query = query.into_inner();
+    let input = web::block(move || {
+        let body = std::str::from_utf8(&body).map_err(ApiError::bad_request)?;
+        if fasta {
+            Ok(JobInput::sequences(body, query.scale))
+        } else {
+            JobInput::variants(body, ndjson, query.metric)
+        }
+    })
+    .await
+    .map_err(|err| ApiError::internal(err.into()))??;
+
+    let job = queue.submit(input, caller(&req), limits::client(&req), state)?;
+    accepted(&req, &job)
+}
+
+/// The state and progress of a job.
+#[utoipa::path(
+    context_path = "/api/v1",
+    params(("id" = String, Path, description = "Job ID")),
+    responses(
+        (status = 200, body = JobStatus),
+        (status = 404, description = "Unknown or expired job", body = ApiError),
+    )
+)]
+#[get("/jobs/{id}", name = "job_status")]
+pub async fn job_status(
+    req: HttpRequest,
+    id: web::Path<(String,)>,
+    queue: web::Data<JobQueue>,
+) -> Result<web::Json<JobStatus>, ApiError> {
+    find_job(&req, &queue, &id.0).map(|job| web::Json(job.status()))
+}
+
+/// Every result of a finished job in item order, as a JSON array or as NDJSON when that is
+/// what `Accept` asks for.
+#[utoipa::path(
+    context_path = "/api/v1",
+    params(("id" = String, Path, description = "Job ID")),
+    responses(
+        (
+            status = 200,
+            description = "One result per item",
+            content(
+                (Vec<JobRecord> = "application/json"),
+                (JobRecord = "application/x-ndjson"),
+            )
+        ),
+        (status = 404, description = "Unknown or expired job", body = ApiError),
+        (status = 409, description = "The job has not succeeded", body = ApiError),
+    )
+)]
+#[get("/jobs/{id}/results", name = "job_results")]
+pub async fn job_results(
+    req: HttpRequest,
+    id: web::Path<(String,)>,
+    queue: web::Data<JobQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let job = find_job(&req, &queue, &id.0)?;
+    match job.status().get_state() {
+        JobState::Succeeded => {}
+        JobState::Queued | JobState::Running => {
+            return Err(ApiError::conflict(
+                "The job has not finished yet; poll its status until it has",
+            ))
+        }
+        JobState::Failed | JobState::Cancelled => {
+            return Err(ApiError::conflict(
+                "The job did not succeed, so it has no results",
+            ))
+        }
+    }
+
+    let ndjson = header::Accept::parse(&req).is_ok_and(|accept| {
+        matches!(
+            accept.preference().essence_str(),
+            "application/x-ndjson" | "application/ndjson" | "application/jsonl"
+        )
+    });
+    let (content_type, extension) = if ndjson {
+        ("application/x-ndjson", "ndjson")
+    } else {
+        ("application/json", "json")
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}.{extension}\"", job.get_id()),
+        ))
+        .streaming(results_stream(job, ndjson)))
+}
+
+/// The results of a finished job, serialized a chunk at a time so that a large job holds its
+/// lock, and the worker, only briefly for each.
+fn results_stream(
+    job: Arc<Job>,
+    ndjson: bool,
+) -> impl Stream<Item = Result<Bytes, serde_json::Error>> {
+    stream::unfold(Some(0), move |start| {
+        let job = Arc::clone(&job);
+        async move {
+            let start = start?;
+            let mut chunk = Vec::new();
+            let written = job.with_results(|results| {
+                let end = results.len().min(start + MAX_RESULTS_PER_CHUNK);
+                if !ndjson && start == 0 {
+                    chunk.push(b'[');
+                }
+                for (index, result) in results[start..end].iter().enumerate() {
+                    if !ndjson && start + index > 0 {
+                        chunk.push(b',');
+                    }
+                    serde_json::to_writer(&mut chunk, result)?;
+                    if ndjson {
+                        chunk.push(b'\n');
+                    }
+                }
+                Ok((end, end == results.len()))
+            });
+            match written {
+                Ok((_, true)) => {
+                    if !ndjson {
+                        chunk.push(b']');
+                    }
+                    Some((Ok(Bytes::from(chunk)), None))
+                }
+                Ok((end, false)) => Some((Ok(Bytes::from(chunk)), Some(end))),
+                Err(err) => Some((Err(err), None)),
+            }
+        }
+    })
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EventsQuery {
+    /// Whether to send a `result` event for each item, or only `status` events.
+    #[serde(default = "default_results")]
+    pub results: bool,
+}
+
+const fn default_results() -> bool {
+    true
+}
+
+/// The progress of a job as server-sent events: `status` whenever its state or progress
+/// changes, `result` for each item as it is processed, and a last `status` once it has
+/// finished, after which the stream ends. Each `result` has the number of results sent so
+/// far as its ID, so a client that reconnects with `Last-Event-ID` carries on where it was.
+#[utoipa::path(
+    context_path = "/api/v1",
+    params(
+        ("id" = String, Path, description = "Job ID"),
+        EventsQuery,
+        ("Last-Event-ID" = Option<usize>, Header, description = "Results already received"),
+    ),
+    responses(
+        (status = 200, description = "A stream of events", body = String, content_type = "text/event-stream"),
+        (status = 404, description = "Unknown or expired job", body = ApiError),
+    )
+)]
+#[get("/jobs/{id}/events", name = "job_events")]
+pub async fn job_events(
+    req: HttpRequest,
+    id: web::Path<(String,)>,
+    query: web::Query<EventsQuery>,
+    queue: web::Data<JobQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let job = find_job(&req, &queue, &id.0)?;
+    let from = req
+        .headers()
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(0);
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        // Compressing would hold events back until a block fills up.
+        .insert_header((header::CONTENT_ENCODING, "identity"))
+        .insert_header(("x-accel-buffering", "no"))
+        .streaming(JobEvents::new(job, from, query.results).into_stream()))
+}
+
+/// Cancels a job that is still queued or running, and deletes it and its results.
+#[utoipa::path(
+    context_path = "/api/v1",
+    params(("id" = String, Path, description = "Job ID")),
+    responses(
+        (status = 204, description = "The job was deleted"),
+        (status = 404, description = "Unknown or expired job", body = ApiError),
+    )
+)]
+#[delete("/jobs/{id}", name = "cancel_job")]
+pub async fn cancel_job(
+    req: HttpRequest,
+    id: web::Path<(String,)>,
+    queue: web::Data<JobQueue>,
+) -> Result<HttpResponse, ApiError> {
+    queue
+        .cancel(&id.0, caller(&req).as_deref())
+        .ok_or_else(|| ApiError::not_found(format!("No job {}; it may have expired", id.0)))?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{app, finished};
+    use actix_web::http::StatusCode;
+    use actix_web::test;
+    use std::time::Duration;
+
+    #[actix_web::test]
+    async fn test_variant_job() {
+        let queue = web::Data::new(JobQueue::new(JobConfig::default()));
+        let app = test::init_service(app().app_data(queue.clone())).await;
+        let req = test::TestRequest::post()
+            .uri("/api/v1/jobs")
+            .set_json(serde_json::json!(["R97C", {"first": "A", "second": "K"}, "W12"]))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        let location = resp
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        let status: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(status["kind"], "variants");
+        assert_eq!(status["total"], 3);
+        assert_eq!(
+            location,
+            format!("/api/v1/jobs/{}", status["id"].as_str().unwrap())
+        );
+
+        let status = finished(&queue, &location).await.status();
+        assert_eq!(status.get_state(), JobState::Succeeded);
+        assert_eq!(status.get_done(), 3);
+        let req = test::TestRequest::get()
+            .uri(&format!("{location}/results"))
+            .to_request();
+        let results: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(results[0]["result"]["distance"], 180);
+        assert_eq!(results[1]["result"]["distance"], 106);
+        assert_eq!(results[2]["error"]["code"], "bad_request");
+
+        let req = test::TestRequest::delete().uri(&location).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        let req = test::TestRequest::get().uri(&location).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_results_span_chunks() {
+        let queue = web::Data::new(JobQueue::new(JobConfig::default()));
+        let app = test::init_service(app().app_data(queue.clone())).await;
+        let count = MAX_RESULTS_PER_CHUNK * 2 + 1;
+        let req = test::TestRequest::post()
+            .uri("/api/v1/jobs")
+            .set_json(vec!["R97C"; count])
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let location = resp
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()
+            .unwrap();
+        let location = location.to_string();
+        assert_eq!(
+            finished(&queue, &location).await.status().get_state(),
+            JobState::Succeeded
+        );
+
+        let req = test::TestRequest::get()
+            .uri(&format!("{location}/results"))
+            .to_request();
+        let results: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(results.len(), count);
+        assert_eq!(results[count - 1]["result"]["distance"], 180);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("{location}/results"))
+            .insert_header((header::ACCEPT, "application/x-ndjson"))
+            .to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        assert_eq!(std::str::from_utf8(&body).unwrap().lines().count(), count);
+    }
+
+    #[actix_web::test]
+    async fn test_sequence_job() {
+        let queue = web::Data::new(JobQueue::new(JobConfig::default()));
+        let app = test::init_service(app().app_data(queue.clone())).await;
+        let req = test::TestRequest::post()
+            .uri("/api/v1/jobs?scale=hopp_woods")
+            .insert_header((header::CONTENT_TYPE, "text/x-fasta"))
+            .set_payload(">P1 first\nMKWVTF\n>P2\nMKZ\n")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        let location = resp
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(
+            finished(&queue, &location).await.status().get_state(),
+            JobState::Succeeded
+        );
+
+        let req = test::TestRequest::get()
+            .uri(&format!("{location}/results"))
+            .insert_header((header::ACCEPT, "application/x-ndjson"))
+            .to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        let lines: Vec<serde_json::Value> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines[0]["id"], "P1");
+        assert_eq!(lines[0]["length"], 6);
+        assert!(
+            lines[0]["extinction_coefficient"]["reduced"]
+                .as_f64()
+                .unwrap()
+                > 0.0
+        );
+        assert_eq!(
+            lines[1]["error"]["message"],
+            "P2: Unknown residue 'Z' at position 3"
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_submit_rejects() {
+        let app = test::init_service(app().app_data(web::Data::new(JobQueue::new(
+            JobConfig::new(1, 10, 10, Duration::from_secs(60), 8),
+        ))))
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/api/v1/jobs")
+            .insert_header((header::CONTENT_TYPE, "image/png"))
+            .set_payload("R97C")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let req = test::TestRequest::post()
+            .uri("/api/v1/jobs")
+            .set_json(serde_json::json!(["R97C", "R98C"]))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let req = test::TestRequest::get()
+            .uri("/api/v1/jobs/missing/results")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_job_events() {
+        let queue = web::Data::new(JobQueue::new(JobConfig::default()));
+        let app = test::init_service(app().app_data(queue.clone())).await;
+        let req = test::TestRequest::post()
+            .uri("/api/v1/jobs")
+            .set_json(serde_json::json!(["R97C", "A1K", "W12"]))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let location = resp
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let req = test::TestRequest::get()
+            .uri(&format!("{location}/events"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers().get(header::CONTENT_TYPE).unwrap(),
+            "text/event-stream"
+        );
+        let body = test::read_body(resp).await;
+        let events: Vec<&str> = std::str::from_utf8(&body)
+            .unwrap()
+            .split_terminator("\n\n")
+            .collect();
+        let results: Vec<&str> = events
+            .iter()
+            .filter(|event| event.starts_with("event: result"))
+            .copied()
+            .collect();
+        assert_eq!(results.len(), 3);
+        assert!(results[2].starts_with("event: result\nid: 3\ndata: {"));
+        let last = events.last().unwrap();
+        assert!(last.starts_with("event: status\ndata: "), "{last}");
+        let status: serde_json::Value =
+            serde_json::from_str(last.split_once("data: ").unwrap().1).unwrap();
+        assert_eq!(status["state"], "succeeded");
+        assert_eq!(status["done"], 3);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("{location}/events"))
+            .insert_header(("Last-Event-ID", "2"))
+            .to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        let body = std::str::from_utf8(&body).unwrap();
+        assert_eq!(body.matches("event: result").count(), 1);
+        assert!(body.contains("id: 3\n"));
+
+        let req = test::TestRequest::get()
+            .uri(&format!("{location}/events?results=false"))
+            .to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(!body.contains("event: result"));
+        assert_eq!(body.matches("event: status").count(), 1);
+    }
+}
@@ -0,0 +1,157 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use super::queue::{Job, JobState};
+use actix_web::rt::time::{sleep, timeout};
+use actix_web::web::Bytes;
+use futures_util::stream::{self, Stream};
+use serde::Serialize;
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// How long a quiet stream waits before sending a comment to keep proxies from closing it.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// Shortest time between chunks, so that a fast job sends its progress a few times a second
+/// rather than once per item.
+const MIN_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Results sent in one go, so that a large finished job does not go out as a single chunk.
+pub const MAX_RESULTS_PER_CHUNK: usize = 1000;
+
+/// The server-sent events of one job: a `status` event whenever its state or progress
+/// changes, a `result` event for each item as it is processed (with the number of results
+/// sent so far as its ID), and a last `status` event once it has finished.
+#[derive(Debug)]
+pub struct JobEvents {
+    job: Arc<Job>,
+    changed: watch::Receiver<()>,
+    /// Results already sent, or skipped with `Last-Event-ID`.
+    sent: usize,
+    results: bool,
+    last: Option<(JobState, usize)>,
+    last_sent: Option<Instant>,
+    finished: bool,
+}
+
+impl JobEvents {
+    /// Events from the start of the job's results, or after the first `from`.
+    pub fn new(job: Arc<Job>, from: usize, results: bool) -> Self {
+        Self {
+            changed: job.subscribe(),
+            job,
+            sent: from,
+            results,
+            last: None,
+            last_sent: None,
+            finished: false,
+        }
+    }
+    pub fn into_stream(self) -> impl Stream<Item = Result<Bytes, Infallible>> {
+        stream::unfold(self, |mut events| async move {
+            let chunk = events.next().await?;
+            Some((Ok(chunk), events))
+        })
+    }
+    async fn next(&mut self) -> Option<Bytes> {
+        if self.finished {
+            return None;
+        }
+        if let Some(last_sent) = self.last_sent {
+            sleep(MIN_INTERVAL.saturating_sub(last_sent.elapsed())).await;
+        }
+        loop {
+            self.changed.borrow_and_update();
+            let chunk = self.poll();
+            if !chunk.is_empty() {
+                self.last_sent = Some(Instant::now());
+                return Some(Bytes::from(chunk));
+            }
+            match timeout(KEEP_ALIVE, self.changed.changed()).await {
+                Ok(Ok(())) => {}
+                // The job keeps the sender, and this holds the job.
+                Ok(Err(_)) => return None,
+                Err(_) => return Some(Bytes::from_static(b": keep-alive\n\n")),
+            }
+        }
+    }
+    /// The events for whatever changed since the last call.
+    fn poll(&mut self) -> String {
+        let status = self.job.status();
+        let mut chunk = String::new();
+        if self.results {
+            self.job.with_results(|records| {
+                let start = self.sent.min(records.len());
+                let end = records.len().min(start + MAX_RESULTS_PER_CHUNK);
+                for (index, record) in records[start..end].iter().enumerate() {
+                    event(&mut chunk, "result", Some(start + index + 1), record);
+                }
+                self.sent = self.sent.max(end);
+            });
+        }
+        let progress = (status.get_state(), status.get_done());
+        let pending = self.results && self.sent < progress.1;
+        let running = matches!(progress.0, JobState::Queued | JobState::Running);
+        // The last status waits for the results before it, so that it can end the stream.
+        if self.last != Some(progress) && (running || !pending) {
+            event(&mut chunk, "status", None, &status);
+            self.last = Some(progress);
+            self.finished = !running;
+        }
+        chunk
+    }
+}
+
+fn event(chunk: &mut String, name: &str, id: Option<usize>, data: &impl Serialize) {
+    // Neither type has a field that can fail to serialize.
+    let data = serde_json::to_string(data).unwrap_or_default();
+    let _ = writeln!(chunk, "event: {name}");
+    if let Some(id) = id {
+        let _ = writeln!(chunk, "id: {id}");
+    }
+    let _ = writeln!(chunk, "data: {data}\n");
+}
@@ -0,0 +1,499 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use super::work::{JobInput, JobRecord};
+use crate::error::ApiError;
+use crate::state::AppState;
+use actix_web::web;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{watch, Semaphore};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// How often expired jobs are removed when nothing else touches the queue.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy)]
+pub struct JobConfig {
+    workers: usize,
+    max_jobs: usize,
+    max_jobs_per_client: usize,
+    ttl: Duration,
+    max_upload_size: usize,
+}
+
+impl JobConfig {
+    /// `workers` jobs run at once; up to `max_jobs` are kept, and up to `max_jobs_per_client`
+    /// for any one client, counting finished ones until they expire `ttl` after finishing.
+    pub const fn new(
+        workers: usize,
+        max_jobs: usize,
+        max_jobs_per_client: usize,
+        ttl: Duration,
+        max_upload_size: usize,
+    ) -> Self {
+        Self {
+            workers,
+            max_jobs,
+            max_jobs_per_client,
+            ttl,
+            max_upload_size,
+        }
+    }
+    pub const fn get_max_upload_size(&self) -> usize {
+        self.max_upload_size
+    }
+}
+
+impl Default for JobConfig {
+    fn default() -> Self {
+        Self::new(2, 100, 10, Duration::from_secs(3600), 64 * 1024 * 1024)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    /// Residue pairs and protein variants, scored like a batch.
+    Variants,
+    /// FASTA records, each summarized.
+    Sequences,
+    /// A plate-reader export, quantified against a layout when one is given.
+    Plate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+/// A job as seen when it is submitted or polled. Times are Unix timestamps in seconds.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct JobStatus {
+    id: String,
+    kind: JobKind,
+    state: JobState,
+    /// Items processed so far, each with a result or an error of its own.
+    done: usize,
+    total: usize,
+    created_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    started_at: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    finished_at: Option<u64>,
+    /// When the job and its results will be removed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+    /// Why the job as a whole failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl JobStatus {
+    pub const fn get_state(&self) -> JobState {
+        self.state
+    }
+    pub const fn get_done(&self) -> usize {
+        self.done
+    }
+}
+
+#[derive(Debug)]
+struct Progress {
+    state: JobState,
+    started: Option<SystemTime>,
+    finished: Option<SystemTime>,
+    results: Vec<JobRecord>,
+    error: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Job {
+    id: String,
+    kind: JobKind,
+    /// The API key that submitted the job, which is then the only one that may see it.
+    owner: Option<String>,
+    /// The API key or client address that submitted the job, which it counts against.
+    client: String,
+    total: usize,
+    created: SystemTime,
+    ttl: Duration,
+    cancelled: AtomicBool,
+    progress: Mutex<Progress>,
+    /// Bumped whenever the status or the results change.
+    changed: watch::Sender<()>,
+}
+
+impl Job {
+    pub fn get_id(&self) -> &str {
+        &self.id
+    }
+    fn progress(&self) -> MutexGuard<'_, Progress> {
+        match self.progress.lock() {
+            Ok(progress) => progress,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+    pub fn status(&self) -> JobStatus {
+        let progress = self.progress();
+        JobStatus {
+            id: self.id.clone(),
+            kind: self.kind,
+            state: progress.state,
+            done: progress.results.len(),
+            total: self.total,
+            created_at: unix_time(self.created),
+            started_at: progress.started.map(unix_time),
+            finished_at: progress.finished.map(unix_time),
+            expires_at: progress
+                .finished
+                .map(|finished| unix_time(finished + self.ttl)),
+            error: progress.error.clone(),
+        }
+    }
+    /// Runs `f` on the results so far without copying them.
+    pub fn with_results<T>(&self, f: impl FnOnce(&[JobRecord]) -> T) -> T {
+        f(&self.progress().results)
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+    /// Wakes up whenever the status or the results change.
+    pub fn subscribe(&self) -> watch::Receiver<()> {
+        self.changed.subscribe()
+    }
+    /// Adds the result for the next item.
+    pub fn push(&self, record: JobRecord) {
+        self.progress().results.push(record);
+        self.changed.send_replace(());
+    }
+    /// Marks the job as running, unless it was cancelled while queued.
+    fn start(&self) -> bool {
+        let mut progress = self.progress();
+        if self.is_cancelled() {
+            return false;
+        }
+        progress.state = JobState::Running;
+        progress.started = Some(SystemTime::now());
+        drop(progress);
+        self.changed.send_replace(());
+        true
+    }
+    /// Asks the job to stop; a queued job is cancelled at once, a running one after its
+    /// current item.
+    fn cancel(&self) {
+        let mut progress = self.progress();
+        self.cancelled.store(true, Ordering::Relaxed);
+        if progress.state == JobState::Queued {
+            progress.state = JobState::Cancelled;
+            progress.finished = Some(SystemTime::now());
+            drop(progress);
+            self.changed.send_replace(());
+        }
+    }
+    fn finish(&self, outcome: Result<(), String>) {
+        let mut progress = self.progress();
+        progress.state = match (&outcome, self.is_cancelled()) {
+            (_, true) => JobState::Cancelled,
+            (Ok(()), false) => JobState::Succeeded,
+            (Err(_), false) => JobState::Failed,
+        };
+        progress.error = outcome.err();
+        progress.finished = Some(SystemTime::now());
+        drop(progress);
+        self.changed.send_replace(());
+    }
+    fn is_expired(&self, now: SystemTime) -> bool {
+        self.progress()
+            .finished
+            .is_some_and(|finished| finished + self.ttl <= now)
+    }
+    fn is_visible_to(&self, caller: Option<&str>) -> bool {
+        self.owner.is_none() || self.owner.as_deref() == caller
+    }
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// Jobs by ID, run in the background with at most `workers` at a time.
+#[derive(Debug)]
+pub struct JobQueue {
+    config: JobConfig,
+    jobs: Mutex<HashMap<String, Arc<Job>>>,
+    permits: Arc<Semaphore>,
+}
+
+impl JobQueue {
+    pub fn new(config: JobConfig) -> Self {
+        Self {
+            config,
+            jobs: Mutex::new(HashMap::new()),
+            permits: Arc::new(Semaphore::new(config.workers.max(1))),
+        }
+    }
+    pub const fn get_config(&self) -> JobConfig {
+        self.config
+    }
+    /// The jobs that have not expired.
+    fn jobs(&self) -> MutexGuard<'_, HashMap<String, Arc<Job>>> {
+        let mut jobs = match self.jobs.lock() {
+            Ok(jobs) => jobs,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let now = SystemTime::now();
+        jobs.retain(|_, job| !job.is_expired(now));
+        jobs
+    }
+    /// Removes expired jobs and frees their results.
+    pub fn sweep(&self) {
+        drop(self.jobs());
+    }
+    /// Queues `input` to run on the blocking thread pool once a worker is free.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `max_jobs` jobs are already kept, or `max_jobs_per_client` for
+    /// `client`.
+    pub fn submit(
+        &self,
+        input: JobInput,
+        owner: Option<String>,
+        client: String,
+        state: web::Data<AppState>,
+    ) -> Result<Arc<Job>, ApiError> {
+        let job = Arc::new(Job {
+            id: Uuid::new_v4().to_string(),
+            kind: input.kind(),
+            owner,
+            client,
+            total: input.total(),
+            created: SystemTime::now(),
+            ttl: self.config.ttl,
+            cancelled: AtomicBool::new(false),
+            progress: Mutex::new(Progress {
+                state: JobState::Queued,
+                started: None,
+                finished: None,
+                results: Vec::new(),
+                error: None,
+            }),
+            changed: watch::channel(()).0,
+        });
+        {
+            let mut jobs = self.jobs();
+            if jobs.len() >= self.config.max_jobs {
+                return Err(ApiError::unavailable(format!(
+                    "The server is holding its limit of {} jobs; try again once some expire",
+                    self.config.max_jobs
+                )));
+            }
+            let held = jobs
+                .values()
+                .filter(|held| held.client == job.client)
+                .count();
+            if held >= self.config.max_jobs_per_client {
+                return Err(ApiError::unavailable(format!(
+                    "You are holding your limit of {} jobs; try again once some expire",
+                    self.config.max_jobs_per_client
+                )));
+            }
+            jobs.insert(job.id.clone(), Arc::clone(&job));
+        }
+
+        let permits = Arc::clone(&self.permits);
+        let worker = Arc::clone(&job);
+        actix_web::rt::spawn(async move {
+            // The semaphore is never closed, so this only waits for a free worker.
+            let _permit = permits.acquire_owned().await;
+            if !worker.start() {
+                return;
+            }
+            let running = Arc::clone(&worker);
+            let outcome = web::block(move || input.run(&running, &state))
+                .await
+                .map_err(|err| format!("The job stopped unexpectedly: {err}"));
+            worker.finish(outcome);
+        });
+        Ok(job)
+    }
+    /// The job with this ID, if it exists and `caller` may see it.
+    pub fn get(&self, id: &str, caller: Option<&str>) -> Option<Arc<Job>> {
+        self.jobs()
+            .get(id)
+            .filter(|job| job.is_visible_to(caller))
+            .cloned()
+    }
+    /// Stops the job if it is queued or running, and removes it with its results.
+    pub fn cancel(&self, id: &str, caller: Option<&str>) -> Option<Arc<Job>> {
+        let mut jobs = self.jobs();
+        if !jobs.get(id)?.is_visible_to(caller) {
+            return None;
+        }
+        let job = jobs.remove(id)?;
+        job.cancel();
+        Some(job)
+    }
+}
+
+/// Sweeps `queue` every minute for as long as the server runs, so that finished jobs are freed
+/// once they expire even if no requests come in.
+pub async fn sweep_expired(queue: web::Data<JobQueue>) {
+    let mut interval = actix_web::rt::time::interval(SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        queue.sweep();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{state, wait};
+    use distance_aa_lib::DistanceMetric;
+
+    fn variants(count: usize) -> JobInput {
+        let body = serde_json::to_string(&vec!["R97C"; count]).unwrap();
+        JobInput::variants(&body, false, DistanceMetric::Grantham).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_submit_runs_job() {
+        let queue = JobQueue::new(JobConfig::default());
+        let job = queue
+            .submit(variants(3), None, "client".to_string(), state())
+            .unwrap();
+        let status = wait(&job).await;
+        assert_eq!(status.state, JobState::Succeeded);
+        assert_eq!((status.done, status.total), (3, 3));
+        assert!(status.expires_at.is_some());
+        assert!(queue.get(job.get_id(), None).is_some());
+    }
+
+    #[actix_web::test]
+    async fn test_max_jobs() {
+        let queue = JobQueue::new(JobConfig::new(1, 1, 1, Duration::from_secs(60), 1024));
+        queue
+            .submit(variants(1), None, "client".to_string(), state())
+            .unwrap();
+        let error = queue
+            .submit(variants(1), None, "client".to_string(), state())
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The server is holding its limit of 1 jobs; try again once some expire"
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_max_jobs_per_client() {
+        let queue = JobQueue::new(JobConfig::new(1, 10, 1, Duration::from_secs(60), 1024));
+        queue
+            .submit(variants(1), None, "a".to_string(), state())
+            .unwrap();
+        let error = queue
+            .submit(variants(1), None, "a".to_string(), state())
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "You are holding your limit of 1 jobs; try again once some expire"
+        );
+        assert!(queue
+            .submit(variants(1), None, "b".to_string(), state())
+            .is_ok());
+    }
+
+    #[actix_web::test]
+    async fn test_sweep() {
+        let queue = JobQueue::new(JobConfig::new(1, 10, 10, Duration::ZERO, 1024));
+        let job = queue
+            .submit(variants(1), None, "client".to_string(), state())
+            .unwrap();
+        wait(&job).await;
+        assert_eq!(queue.jobs.lock().unwrap().len(), 1);
+        queue.sweep();
+        assert!(queue.jobs.lock().unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_expiry() {
+        let queue = JobQueue::new(JobConfig::new(1, 10, 10, Duration::ZERO, 1024));
+        let job = queue
+            .submit(variants(1), None, "client".to_string(), state())
+            .unwrap();
+        wait(&job).await;
+        assert!(queue.get(job.get_id(), None).is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_owner_and_cancel() {
+        let queue = JobQueue::new(JobConfig::default());
+        let job = queue
+            .submit(
+                variants(1),
+                Some("lab".to_string()),
+                "client".to_string(),
+                state(),
+            )
+            .unwrap();
+        assert!(queue.get(job.get_id(), None).is_none());
+        assert!(queue.get(job.get_id(), Some("other")).is_none());
+        assert!(queue.cancel(job.get_id(), Some("other")).is_none());
+        assert!(queue.cancel(job.get_id(), Some("lab")).is_some());
+        assert!(queue.get(job.get_id(), Some("lab")).is_none());
+        assert!(job.is_cancelled());
+        assert_eq!(job.status().get_state(), JobState::Cancelled);
+    }
+}
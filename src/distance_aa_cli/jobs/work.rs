@@ -0,0 +1,221 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use super::queue::{Job, JobKind};
+use crate::batch::{parse_items, score, BatchItem, BatchResult};
+use crate::error::ApiError;
+use crate::state::AppState;
+use distance_aa_lib::{
+    CurveModel, DistanceMetric, ExtinctionCoefficient, HydropathyScale, PlateAnalysis, PlateLayout,
+    PlateReading, ProteinSequence,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// The parsed items of a job, waiting for a worker.
+#[derive(Debug)]
+pub enum JobInput {
+    Variants {
+        items: Vec<Result<BatchItem, ApiError>>,
+        metric: DistanceMetric,
+    },
+    Sequences {
+        records: Vec<anyhow::Result<ProteinSequence>>,
+        scale: HydropathyScale,
+    },
+    Plate {
+        reading: PlateReading,
+        layout: Option<PlateLayout>,
+        model: CurveModel,
+        cv_threshold: f64,
+    },
+}
+
+impl JobInput {
+    /// Items in the same formats as a batch request, without its limit on the number of items;
+    /// a job is bounded by the upload size instead.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a body that should be a JSON array is not one.
+    pub fn variants(body: &str, ndjson: bool, metric: DistanceMetric) -> Result<Self, ApiError> {
+        Ok(Self::Variants {
+            items: parse_items(body, ndjson, usize::MAX)?,
+            metric,
+        })
+    }
+    /// FASTA records, or a single raw sequence.
+    pub fn sequences(body: &str, scale: HydropathyScale) -> Self {
+        Self::Sequences {
+            records: ProteinSequence::parse_records(body),
+            scale,
+        }
+    }
+    pub const fn kind(&self) -> JobKind {
+        match self {
+            Self::Variants { .. } => JobKind::Variants,
+            Self::Sequences { .. } => JobKind::Sequences,
+            Self::Plate { .. } => JobKind::Plate,
+        }
+    }
+    pub fn total(&self) -> usize {
+        match self {
+            Self::Variants { items, .. } => items.len(),
+            Self::Sequences { records, .. } => records.len(),
+            Self::Plate { .. } => 1,
+        }
+    }
+    /// Processes every item in order, stopping early if the job is cancelled.
+    pub fn run(self, job: &Job, state: &AppState) {
+        match self {
+            Self::Variants { items, metric } => {
+                for (index, item) in items.into_iter().enumerate() {
+                    if job.is_cancelled() {
+                        return;
+                    }
+                    job.push(JobRecord::Variant(Box::new(score(
+                        index, item, state, metric,
+                    ))));
+                }
+            }
+            Self::Sequences { records, scale } => {
+                for (index, record) in records.into_iter().enumerate() {
+                    if job.is_cancelled() {
+                        return;
+                    }
+                    job.push(JobRecord::Sequence(Box::new(summarize(
+                        index, record, state, scale,
+                    ))));
+                }
+            }
+            Self::Plate {
+                reading,
+                layout,
+                model,
+                cv_threshold,
+            } => {
+                let mut result = PlateResult {
+                    reading,
+                    analysis: None,
+                    error: None,
+                };
+                if let Some(layout) = layout {
+                    match PlateAnalysis::new(&layout, &result.reading, model, cv_threshold) {
+                        Ok(analysis) => result.analysis = Some(analysis),
+                        Err(error) => result.error = Some(ApiError::bad_request(error)),
+                    }
+                }
+                job.push(JobRecord::Plate(Box::new(result)));
+            }
+        }
+    }
+}
+
+/// The result for one item of a job.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum JobRecord {
+    Variant(Box<BatchResult>),
+    Sequence(Box<SequenceSummary>),
+    Plate(Box<PlateResult>),
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SequenceSummary {
+    index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    length: Option<usize>,
+    /// Including the molecular weight, in Da.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extinction_coefficient: Option<ExtinctionCoefficient>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gravy: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ApiError>,
+}
+
+/// The wells read from a plate export and, given a layout, the standard curve and samples.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PlateResult {
+    #[schema(value_type = Object)]
+    reading: PlateReading,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    analysis: Option<PlateAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ApiError>,
+}
+
+fn summarize(
+    index: usize,
+    record: anyhow::Result<ProteinSequence>,
+    state: &AppState,
+    scale: HydropathyScale,
+) -> SequenceSummary {
+    let mut summary = SequenceSummary {
+        index,
+        id: None,
+        length: None,
+        extinction_coefficient: None,
+        gravy: None,
+        error: None,
+    };
+    let sequence = match record {
+        Ok(sequence) => sequence,
+        Err(error) => {
+            summary.error = Some(ApiError::bad_request(error));
+            return summary;
+        }
+    };
+    summary.id = sequence.get_id();
+    summary.length = Some(sequence.len());
+    summary.gravy = Some(sequence.gravy(scale));
+    match sequence.extinction_coefficient(state.get_library()) {
+        Ok(coefficient) => summary.extinction_coefficient = Some(coefficient),
+        Err(error) => summary.error = Some(ApiError::internal(error)),
+    }
+    summary
+}
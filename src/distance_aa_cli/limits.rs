@@ -0,0 +1,500 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use crate::auth::Caller;
+use crate::error::ApiError;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::JsonPayloadError;
+use actix_web::middleware::Next;
+use actix_web::{web, HttpMessage, HttpRequest, ResponseError};
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv6Addr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Body size used when no limit is configured, in bytes.
+pub const DEFAULT_BODY_LIMIT: usize = 1024 * 1024;
+
+/// Buckets kept at most; the least recently used one makes way for a new client.
+const MAX_BUCKETS: usize = 100_000;
+
+/// How often buckets that have refilled are dropped.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+
+/// JSON bodies up to `limit` bytes, with parse errors reported as `ApiError`s.
+pub fn json_config(limit: usize) -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(limit)
+        .error_handler(|err, _| match err {
+            JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => {
+                ApiError::payload_too_large(err).into()
+            }
+            err => ApiError::bad_request(err).into(),
+        })
+}
+
+/// A sustained request rate with room for short bursts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    per_second: f64,
+    burst: f64,
+}
+
+impl Rate {
+    pub fn new(per_second: f64, burst: u32) -> Self {
+        Self {
+            per_second,
+            burst: f64::from(burst.max(1)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// When the bucket will have refilled at its own rate, after which it can be dropped.
+    full_at: Instant,
+    /// Position in `Buckets::used`.
+    used: u64,
+}
+
+#[derive(Debug)]
+struct Buckets {
+    by_key: HashMap<String, Bucket>,
+    /// Keys from least to most recently used.
+    used: BTreeMap<u64, String>,
+    next_use: u64,
+    next_sweep: Instant,
+}
+
+impl Buckets {
+    fn sweep(&mut self, now: Instant) {
+        let used = &mut self.used;
+        self.by_key.retain(|_, bucket| {
+            let keep = bucket.full_at > now;
+            if !keep {
+                used.remove(&bucket.used);
+            }
+            keep
+        });
+        self.next_sweep = now + SWEEP_INTERVAL;
+    }
+    fn evict_least_recently_used(&mut self) {
+        let oldest = self.used.keys().next().copied();
+        if let Some(key) = oldest.and_then(|oldest| self.used.remove(&oldest)) {
+            self.by_key.remove(&key);
+        }
+    }
+}
+
+/// Token buckets per client: each request takes a token, and tokens refill at the sustained
+/// rate up to the burst size.
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: Option<Rate>,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    /// `rate` applies to clients by address; API keys may bring their own.
+    pub fn new(rate: Option<Rate>) -> Self {
+        Self {
+            rate,
+            buckets: Mutex::new(Buckets {
+                by_key: HashMap::new(),
+                used: BTreeMap::new(),
+                next_use: 0,
+                next_sweep: Instant::now() + SWEEP_INTERVAL,
+            }),
+        }
+    }
+    pub const fn get_rate(&self) -> Option<Rate> {
+        self.rate
+    }
+    /// Takes a token from the bucket for `key`, or returns how long until one is available.
+    pub fn check(&self, key: &str, rate: Rate, now: Instant) -> Result<(), Duration> {
+        let mut buckets = match self.buckets.lock() {
+            Ok(buckets) => buckets,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if now >= buckets.next_sweep {
+            buckets.sweep(now);
+        }
+        if !buckets.by_key.contains_key(key) && buckets.by_key.len() >= MAX_BUCKETS {
+            buckets.evict_least_recently_used();
+        }
+
+        let used = buckets.next_use;
+        buckets.next_use += 1;
+        let mut bucket = buckets.by_key.get(key).copied().unwrap_or(Bucket {
+            tokens: rate.burst,
+            updated: now,
+            full_at: now,
+            used,
+        });
+        buckets.used.remove(&bucket.used);
+        buckets.used.insert(used, key.to_string());
+
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate.per_second).min(rate.burst);
+        bucket.updated = now;
+        bucket.used = used;
+        let outcome = if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else if rate.per_second > 0.0 {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / rate.per_second,
+            ))
+        } else {
+            Err(Duration::from_secs(60))
+        };
+        bucket.full_at = now + refill_time(rate.burst - bucket.tokens, rate);
+        buckets.by_key.insert(key.to_string(), bucket);
+        outcome
+    }
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.buckets.lock().unwrap().by_key.len()
+    }
+}
+
+/// How long `tokens` take to come back at `rate`, capped so that a zero rate stays finite.
+fn refill_time(tokens: f64, rate: Rate) -> Duration {
+    let day = Duration::from_secs(24 * 60 * 60);
+    if tokens <= 0.0 {
+        Duration::ZERO
+    } else if rate.per_second > 0.0 {
+        Duration::from_secs_f64(tokens / rate.per_second).min(day)
+    } else {
+        day
+    }
+}
+
+/// The address to limit a client by. IPv6 clients are usually given a whole /64, so they are
+/// limited by that rather than by each address in it.
+fn client_address(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => ip.to_string(),
+            None => {
+                let prefix = Ipv6Addr::from(u128::from(ip) & !u128::from(u64::MAX));
+                format!("{prefix}/64")
+            }
+        },
+        IpAddr::V4(ip) => ip.to_string(),
+    }
+}
+
+/// Who a request counts against: its API key, or its client address when it has none.
+pub fn client(req: &HttpRequest) -> String {
+    match req.extensions().get::<Caller>() {
+        Some(caller) => format!("key:{}", caller.get_name()),
+        None => req
+            .peer_addr()
+            .map_or_else(|| "unknown".to_string(), |addr| client_address(addr.ip())),
+    }
+}
+
+/// Middleware for `from_fn`: limits each API key, or each client address without one, with the
+/// registered `web::Data<RateLimiter>`, and does nothing when there is none.
+pub async fn rate_limit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    if let Some(limiter) = req.app_data::<web::Data<RateLimiter>>() {
+        let client = client(req.request());
+        let rate = req
+            .extensions()
+            .get::<Caller>()
+            .and_then(Caller::get_rate)
+            .or_else(|| limiter.get_rate());
+        let checked = rate.map(|rate| limiter.check(&client, rate, Instant::now()));
+        if let Some(Err(wait)) = checked {
+            let response = ApiError::too_many_requests(wait).error_response();
+            return Ok(req.into_response(response).map_into_right_body());
+        }
+    }
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+/// The longest a handler may take before the client is sent a `503`.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestTimeout(pub Duration);
+
+/// Middleware for `from_fn`: applies the registered `web::Data<RequestTimeout>`, if any.
+///
+/// This keeps a copy of the request to answer with, so it must wrap resources rather than
+/// the app or a scope, whose routing needs the request to itself. Timing out drops the
+/// handler, but not a closure it passed to `web::block`; see `Cancellation`.
+pub async fn timeout(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let limit = match req.app_data::<web::Data<RequestTimeout>>() {
+        Some(limit) => limit.0,
+        None => {
+            return next
+                .call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        }
+    };
+    // Keep a handle on the request so the timeout response can still be built from it.
+    let request = req.request().clone();
+    match actix_web::rt::time::timeout(limit, next.call(req)).await {
+        Ok(response) => response.map(ServiceResponse::map_into_left_body),
+        Err(_) => {
+            let response = ApiError::timeout(limit).error_response();
+            Ok(ServiceResponse::new(request, response).map_into_right_body())
+        }
+    }
+}
+
+/// Set when dropped along with the handler that holds it, whether by `timeout` or because the
+/// client went away, so that blocking work the handler started can stop early rather than
+/// keep a thread of the blocking pool that background jobs also run on.
+#[derive(Debug, Default)]
+pub struct Cancellation(Arc<AtomicBool>);
+
+impl Cancellation {
+    pub fn flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.0)
+    }
+}
+
+impl Drop for Cancellation {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::middleware::from_fn;
+    use actix_web::{test as actix_test, App, HttpResponse};
+
+    #[test]
+    fn test_rate_limiter() {
+        let rate = Rate::new(2.0, 2);
+        let limiter = RateLimiter::new(Some(rate));
+        let start = Instant::now();
+        assert!(limiter.check("a", rate, start).is_ok());
+        assert!(limiter.check("a", rate, start).is_ok());
+        let wait = limiter.check("a", rate, start).unwrap_err();
+        assert!((wait.as_secs_f64() - 0.5).abs() < 1e-9);
+        assert!(limiter.check("b", rate, start).is_ok());
+        assert!(limiter
+            .check("a", rate, start + Duration::from_millis(500))
+            .is_ok());
+    }
+    #[test]
+    fn test_rate_limiter_sweeps_by_each_bucket_rate() {
+        let limiter = RateLimiter::new(None);
+        let start = Instant::now();
+        let fast = Rate::new(100.0, 1);
+        let slow = Rate::new(0.01, 1);
+        assert!(limiter.check("fast", fast, start).is_ok());
+        assert!(limiter.check("slow", slow, start).is_ok());
+        // A request at the fast rate must not count the slow bucket as refilled.
+        assert!(limiter.check("other", fast, start + SWEEP_INTERVAL).is_ok());
+        assert_eq!(limiter.len(), 2);
+        assert!(limiter.check("slow", slow, start + SWEEP_INTERVAL).is_err());
+    }
+    #[test]
+    fn test_rate_limiter_evicts_least_recently_used() {
+        let limiter = RateLimiter::new(None);
+        let rate = Rate::new(0.0, 1);
+        let start = Instant::now();
+        for index in 0..MAX_BUCKETS {
+            assert!(limiter.check(&index.to_string(), rate, start).is_ok());
+        }
+        assert!(limiter.check("0", rate, start).is_err());
+        assert!(limiter.check("new", rate, start).is_ok());
+        assert_eq!(limiter.len(), MAX_BUCKETS);
+        // "1" was the least recently used, so it starts again with a full bucket.
+        assert!(limiter.check("1", rate, start).is_ok());
+        assert!(limiter.check("0", rate, start).is_err());
+    }
+    #[test]
+    fn test_client_address() {
+        let address = |ip: &str| client_address(ip.parse().unwrap());
+        assert_eq!(address("192.0.2.1"), "192.0.2.1");
+        assert_eq!(address("::ffff:192.0.2.1"), "192.0.2.1");
+        assert_eq!(address("2001:db8:1:2:3:4:5:6"), "2001:db8:1:2::/64");
+        assert_eq!(
+            address("2001:db8:1:2:ffff::1"),
+            address("2001:db8:1:2:3:4:5:6")
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_rate_limit() {
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(RateLimiter::new(Some(Rate::new(0.5, 1)))))
+                .wrap(from_fn(rate_limit))
+                .route("/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let req = actix_test::TestRequest::get()
+            .peer_addr("10.0.0.1:1234".parse().unwrap())
+            .to_request();
+        assert_eq!(
+            actix_test::call_service(&app, req).await.status(),
+            StatusCode::OK
+        );
+        let req = actix_test::TestRequest::get()
+            .peer_addr("10.0.0.1:1234".parse().unwrap())
+            .to_request();
+        let resp = actix_test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers().get(header::RETRY_AFTER).unwrap(), "2");
+        let body: serde_json::Value = actix_test::read_body_json(resp).await;
+        assert_eq!(body["code"], "rate_limited");
+    }
+
+    #[actix_web::test]
+    async fn test_timeout() {
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(RequestTimeout(Duration::from_millis(10))))
+                .service(
+                    web::resource("/slow")
+                        .wrap(from_fn(timeout))
+                        .route(web::get().to(|| async {
+                            actix_web::rt::time::sleep(Duration::from_secs(5)).await;
+                            HttpResponse::Ok().finish()
+                        })),
+                )
+                .service(
+                    web::resource("/fast")
+                        .wrap(from_fn(timeout))
+                        .route(web::get().to(HttpResponse::Ok)),
+                ),
+        )
+        .await;
+        let req = actix_test::TestRequest::get().uri("/slow").to_request();
+        let resp = actix_test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let req = actix_test::TestRequest::get().uri("/fast").to_request();
+        assert_eq!(
+            actix_test::call_service(&app, req).await.status(),
+            StatusCode::OK
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_timeout_cancels_blocking_work() {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let seen = Arc::clone(&stopped);
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(RequestTimeout(Duration::from_millis(10))))
+                .service(
+                    web::resource("/slow")
+                        .wrap(from_fn(timeout))
+                        .route(web::get().to(move || {
+                            let seen = Arc::clone(&seen);
+                            async move {
+                                let cancellation = Cancellation::default();
+                                let cancelled = cancellation.flag();
+                                let _ = web::block(move || {
+                                    let start = Instant::now();
+                                    while !cancelled.load(Ordering::Relaxed)
+                                        && start.elapsed() < Duration::from_secs(5)
+                                    {
+                                        std::thread::sleep(Duration::from_millis(1));
+                                    }
+                                    seen.store(
+                                        cancelled.load(Ordering::Relaxed),
+                                        Ordering::Relaxed,
+                                    );
+                                })
+                                .await;
+                                HttpResponse::Ok().finish()
+                            }
+                        })),
+                ),
+        )
+        .await;
+        let req = actix_test::TestRequest::get().uri("/slow").to_request();
+        let resp = actix_test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        for _ in 0..100 {
+            if stopped.load(Ordering::Relaxed) {
+                return;
+            }
+            actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("The blocking work was not cancelled");
+    }
+
+    #[actix_web::test]
+    async fn test_json_limit() {
+        let app = actix_test::init_service(App::new().app_data(json_config(16)).route(
+            "/",
+            web::post().to(|body: web::Json<serde_json::Value>| async move { body }),
+        ))
+        .await;
+        let req = actix_test::TestRequest::post()
+            .set_json(serde_json::json!({"sequence": "MKWVTFISLLFLFSSAYS"}))
+            .to_request();
+        let resp = actix_test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let req = actix_test::TestRequest::post()
+            .insert_header(header::ContentType::json())
+            .set_payload("{")
+            .to_request();
+        let resp = actix_test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,158 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use crate::cli::LoadingArgs;
+use crate::upload::default_cv_threshold;
+use anyhow::{anyhow, ensure, Context, Result};
+use distance_aa_lib::{
+    CurveModel, LoadingPlan, LoadingSample, PlateAnalysis, PlateLayout, PlateReading,
+};
+use std::fs;
+use std::path::Path;
+
+pub fn run(args: &LoadingArgs) -> Result<()> {
+    let mut samples = Vec::new();
+    if let Some(path) = &args.concentrations {
+        samples.extend(LoadingSample::parse_csv(&read(path)?)?);
+    }
+    if let (Some(plate), Some(layout)) = (&args.plate, &args.layout) {
+        let analysis = analyze_plate(&read(plate)?, &read(layout)?, args.curve_model)?;
+        samples.extend(quantified(&analysis)?);
+    }
+    if let Some(path) = &args.bca_results {
+        let analysis = parse_results(&read(path)?)
+            .with_context(|| format!("Invalid BCA results in {}", path.display()))?;
+        samples.extend(quantified(&analysis)?);
+    }
+    samples.extend(args.samples.iter().cloned());
+    ensure!(
+        !samples.is_empty(),
+        "No samples given; use --concentrations, --plate, --bca-results or --sample"
+    );
+
+    let plan = LoadingPlan::new(args.target, args.lane_volume, args.dye_factor, &samples)?;
+    print!("{plan}");
+    if let Some(path) = &args.output {
+        fs::write(path, plan.to_csv())
+            .with_context(|| format!("Could not write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn read(path: &Path) -> Result<String> {
+    fs::read_to_string(path).with_context(|| format!("Could not read {}", path.display()))
+}
+
+fn analyze_plate(export: &str, layout: &str, model: CurveModel) -> Result<PlateAnalysis> {
+    let layout = PlateLayout::from_toml(layout)?;
+    let reading = PlateReading::parse(export, layout.get_format())?;
+    PlateAnalysis::new(&layout, &reading, model, default_cv_threshold())
+}
+
+/// A plate analysis on its own, or the `analysis` of a plate job result.
+fn parse_results(input: &str) -> Result<PlateAnalysis> {
+    let mut value: serde_json::Value = serde_json::from_str(input)?;
+    if let Some(analysis) = value.get_mut("analysis") {
+        value = analysis.take();
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+/// The samples of an analysis, warning about the replicate groups that failed QC.
+fn quantified(analysis: &PlateAnalysis) -> Result<Vec<LoadingSample>> {
+    for replicate in analysis.get_replicates() {
+        if replicate.is_flagged() {
+            log::warn!(
+                "Wells {} failed QC (CV {:.1}%, unreadable: {:?})",
+                replicate.get_wells().join(", "),
+                replicate.get_coefficient_of_variation(),
+                replicate.get_unreadable()
+            );
+        }
+    }
+    analysis
+        .get_samples()
+        .iter()
+        .map(|result| LoadingSample::try_from(result).map_err(|error| anyhow!(error)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYOUT: &str = r#"
+        [[standard]]
+        concentration = 0
+        wells = ["A1", "A2"]
+
+        [[standard]]
+        concentration = 1000
+        wells = ["B1", "B2"]
+
+        [[sample]]
+        name = "lysate"
+        dilution_factor = 2
+        wells = ["C1", "C2"]
+    "#;
+    const EXPORT: &str = "A1,0.1\nA2,0.1\nB1,1.1\nB2,1.1\nC1,0.6\nC2,0.6\n";
+
+    #[test]
+    fn test_quantified_plate() {
+        let analysis = analyze_plate(EXPORT, LAYOUT, CurveModel::Linear).unwrap();
+        let samples = quantified(&analysis).unwrap();
+        assert_eq!(samples[0].get_name(), "lysate");
+        assert!((samples[0].get_concentration() - 1000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_parse_results() {
+        let analysis = analyze_plate(EXPORT, LAYOUT, CurveModel::Linear).unwrap();
+        let json = serde_json::to_string(&analysis).unwrap();
+        assert_eq!(parse_results(&json).unwrap(), analysis);
+        let job_result = format!(r#"{{"reading": {{}}, "analysis": {json}}}"#);
+        assert_eq!(parse_results(&job_result).unwrap(), analysis);
+        assert!(parse_results(r#"{"reading": {}}"#).is_err());
+    }
+}
@@ -0,0 +1,278 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Logger;
+use anyhow::{anyhow, Context, Result};
+use env_logger::fmt::Formatter;
+use log::{LevelFilter, Record};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+use std::future::Future;
+use std::io::{self, Write};
+use std::str::FromStr;
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// The target actix's `Logger` middleware logs under.
+const ACCESS_LOG_TARGET: &str = "actix_web::middleware::logger";
+
+/// actix's default access log format, prefixed with the request ID.
+const TEXT_ACCESS_FORMAT: &str =
+    r#"%{x-request-id}i %a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T"#;
+
+/// An access log line as a JSON object. Only the request ID, address and numbers are written
+/// in place; anything the client controls goes through a replacement that escapes it.
+const JSON_ACCESS_FORMAT: &str = r#"{"request_id":"%{x-request-id}i","remote_addr":"%a","method":%{method}xi,"path":%{path}xi,"status":%s,"bytes":%b,"duration_ms":%D,"user_agent":%{user_agent}xi}"#;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// env_logger's human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line, with the request ID of the request being handled.
+    Json,
+}
+
+impl LogFormat {
+    pub const ALL: [Self; 2] = [Self::Text, Self::Json];
+
+    pub const fn get_name(self) -> &'static str {
+        match self {
+            Self::Text => "text",
+            Self::Json => "json",
+        }
+    }
+}
+
+impl Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.get_name())
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|format| format.get_name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| anyhow!("Unknown log format: {s}"))
+    }
+}
+
+/// Installs the global logger at `level`. `RUST_LOG`, when set, can still adjust individual
+/// modules on top of it.
+pub fn init(level: &str, format: LogFormat) -> Result<()> {
+    let level = parse_level(level)?;
+    let mut builder = env_logger::Builder::new();
+    builder.filter_level(level);
+    if let Ok(filters) = std::env::var("RUST_LOG") {
+        builder.parse_filters(&filters);
+    }
+    if format == LogFormat::Json {
+        builder.format(write_json);
+    }
+    builder.try_init().context("Could not initialise logging")
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter> {
+    if level.trim().eq_ignore_ascii_case("all") {
+        return Ok(LevelFilter::Trace);
+    }
+    level
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Unknown log level: {level}"))
+}
+
+/// The request logger, using `custom` as the actix format string if given.
+pub fn access_logger(format: LogFormat, custom: Option<&str>) -> Logger {
+    match (format, custom) {
+        (_, Some(custom)) => Logger::new(custom),
+        (LogFormat::Text, None) => Logger::new(TEXT_ACCESS_FORMAT),
+        (LogFormat::Json, None) => Logger::new(JSON_ACCESS_FORMAT)
+            .custom_request_replace("method", |req| json_string(req.method().as_str()))
+            .custom_request_replace("path", |req| json_string(&req.uri().to_string()))
+            .custom_request_replace("user_agent", |req| {
+                json_string(
+                    req.headers()
+                        .get("user-agent")
+                        .and_then(|value| value.to_str().ok())
+                        .unwrap_or_default(),
+                )
+            }),
+    }
+}
+
+fn json_string(value: &str) -> String {
+    serde_json::Value::from(value).to_string()
+}
+
+fn write_json(buf: &mut Formatter, record: &Record) -> io::Result<()> {
+    let mut line = serde_json::Map::new();
+    line.insert(
+        "timestamp".into(),
+        buf.timestamp_millis().to_string().into(),
+    );
+    line.insert("level".into(), record.level().as_str().into());
+    line.insert("target".into(), record.target().into());
+    if let Some(id) = current_request_id() {
+        line.insert("request_id".into(), id.into());
+    }
+    let message = record.args().to_string();
+    let access = if record.target() == ACCESS_LOG_TARGET {
+        serde_json::from_str::<serde_json::Map<_, _>>(&message).ok()
+    } else {
+        None
+    };
+    match access {
+        Some(mut http) => {
+            if let Some(id) = http.shift_remove("request_id") {
+                line.insert("request_id".into(), id);
+            }
+            line.insert("http".into(), http.into());
+        }
+        None => {
+            line.insert("message".into(), message.into());
+        }
+    }
+    writeln!(buf, "{}", serde_json::Value::Object(line))
+}
+
+/// The ID of the request being handled, if called while handling one.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// Only short, plain IDs from clients are trusted; anything else is replaced.
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 64
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Middleware for `wrap_fn`: tags each request with the client's `X-Request-ID` or a new UUID,
+/// echoes it on the response, and makes it available to log lines written while handling it.
+/// Register it after `access_logger` so that it runs first.
+pub fn request_id<S, B>(
+    mut req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<B>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+{
+    let id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid_request_id(id))
+        .map_or_else(|| Uuid::new_v4().to_string(), ToString::to_string);
+    let name = HeaderName::from_static(REQUEST_ID_HEADER);
+    let value = HeaderValue::from_str(&id).expect("request IDs are valid header values");
+    req.headers_mut().insert(name.clone(), value.clone());
+    let response = REQUEST_ID.scope(id, srv.call(req));
+    async move {
+        let mut response = response.await?;
+        response.headers_mut().insert(name, value);
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test as actix_test, web, App, HttpResponse};
+
+    #[test]
+    fn test_parse_level() {
+        assert_eq!(parse_level("INFO").unwrap(), LevelFilter::Info);
+        assert_eq!(parse_level("all").unwrap(), LevelFilter::Trace);
+        assert!(parse_level("LOUD").is_err());
+    }
+    #[test]
+    fn test_log_format_from_str() {
+        assert_eq!("JSON".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert!("xml".parse::<LogFormat>().is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_request_id() {
+        assert!(current_request_id().is_none());
+        let app = actix_test::init_service(App::new().wrap_fn(request_id).route(
+            "/",
+            web::get().to(|| async { HttpResponse::Ok().body(current_request_id().unwrap()) }),
+        ))
+        .await;
+
+        let req = actix_test::TestRequest::get()
+            .insert_header((REQUEST_ID_HEADER, "abc-123"))
+            .to_request();
+        let resp = actix_test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get(REQUEST_ID_HEADER).unwrap(), "abc-123");
+        assert_eq!(actix_test::read_body(resp).await, "abc-123");
+
+        let req = actix_test::TestRequest::get()
+            .insert_header((REQUEST_ID_HEADER, "not valid!"))
+            .to_request();
+        let resp = actix_test::call_service(&app, req).await;
+        let id = resp
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(Uuid::parse_str(id).is_ok());
+    }
+}
@@ -43,22 +43,171 @@
 // * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
 
 mod app;
+mod auth;
+mod batch;
+mod caching;
 mod cli;
+mod compare;
+mod config;
+mod cors;
+mod error;
+#[cfg(feature = "graphql")]
+mod graphql;
+mod jobs;
+mod limits;
+mod loading;
+mod logging;
+mod matrix;
+mod metrics;
+mod openapi;
+mod state;
+#[cfg(test)]
+mod testing;
+mod tls;
+mod upload;
 
+use actix_web::middleware::Compress;
+use actix_web::web;
 use actix_web::App;
 use actix_web::HttpServer;
-use app::amino_acid;
-use app::echo;
-use app::index;
-use clap::Parser;
-use std::io::Result;
+use anyhow::Result;
+use auth::Authenticator;
+use batch::BatchConfig;
+use caching::CachePolicy;
+use clap::{CommandFactory, FromArgMatches};
+use compare::CompareConfig;
+use config::Settings;
+use cors::CorsConfig;
+use jobs::{JobConfig, JobQueue};
+use limits::{Rate, RateLimiter, RequestTimeout};
+use metrics::Metrics;
+use state::AppState;
+use std::time::Duration;
+use tls::CertificateStore;
 // use distance_aa_lib::distance_calculator;
 
 #[actix_web::main]
 async fn main() -> Result<()> {
-    let args = cli::Args::parse();
-    HttpServer::new(|| App::new().service(index).service(echo).service(amino_acid))
-        .bind((args.server, args.port))?
-        .run()
-        .await
+    let dotenv = config::load_dotenv()?;
+    let matches = cli::Args::command().get_matches();
+    let args = cli::Args::from_arg_matches(&matches)?;
+    let settings = Settings::resolve(&args, &matches, &dotenv)?;
+    if args.print_config {
+        print!("{}", settings.describe()?);
+        return Ok(());
+    }
+    logging::init(&settings.loglevel, settings.log_format)?;
+    if let Some(cli::Command::Loading(loading)) = &args.command {
+        return loading::run(loading);
+    }
+    if let Some(cli::Command::ApiKey(api_key)) = &args.command {
+        return auth::run(api_key);
+    }
+
+    let state = web::Data::new(AppState::load(&settings.data_path)?);
+    let metrics = web::Data::new(Metrics::new()?);
+    metrics.set_data(&state);
+    let batch = BatchConfig::new(settings.max_batch_size);
+    let compare = CompareConfig::new(settings.max_alignment_cells);
+    let cache = CachePolicy::new(settings.cache_max_age);
+    let limiter = web::Data::new(RateLimiter::new(
+        (settings.rate_limit > 0.0)
+            .then(|| Rate::new(settings.rate_limit, settings.rate_limit_burst)),
+    ));
+    let authenticator = if settings.api_keys.is_empty() {
+        None
+    } else {
+        let keys = settings.api_keys.clone();
+        log::info!("Requiring API keys ({} configured)", keys.len());
+        Some(web::Data::new(Authenticator::new(
+            keys,
+            settings.public_lookups,
+        )?))
+    };
+    let timeout = (settings.request_timeout > 0).then(|| {
+        web::Data::new(RequestTimeout(Duration::from_secs(
+            settings.request_timeout,
+        )))
+    });
+    let max_body_size = settings.max_body_size;
+    let jobs = web::Data::new(JobQueue::new(JobConfig::new(
+        settings.job_workers,
+        settings.max_jobs,
+        settings.max_jobs_per_client,
+        Duration::from_secs(settings.job_ttl),
+        settings.max_upload_size,
+    )));
+    actix_web::rt::spawn(jobs::sweep_expired(jobs.clone()));
+    let cors = CorsConfig::new(
+        &settings.cors_origins,
+        &settings.cors_methods,
+        &settings.cors_headers,
+        settings.cors_permissive,
+    )?;
+    if cors.is_permissive() {
+        log::warn!("CORS is permissive: any origin may call the API");
+    }
+    #[cfg(feature = "graphql")]
+    let playground = settings.graphql_playground;
+    let log_format = settings.log_format;
+    let access_log_format = settings.access_log_format.clone();
+    let mut server = HttpServer::new(move || {
+        let mut app = App::new()
+            .wrap(logging::access_logger(
+                log_format,
+                access_log_format.as_deref(),
+            ))
+            .wrap(cors.middleware())
+            .wrap(Compress::default())
+            .wrap_fn(metrics::track)
+            .wrap_fn(logging::request_id)
+            .app_data(state.clone())
+            .app_data(metrics.clone())
+            .app_data(web::Data::new(batch))
+            .app_data(web::Data::new(compare))
+            .app_data(web::Data::new(cache))
+            .app_data(limiter.clone())
+            .app_data(jobs.clone());
+        #[cfg(feature = "graphql")]
+        if playground {
+            // Ahead of `configure`, whose legacy routes would otherwise answer `/graphql`.
+            app = app.service(graphql::playground);
+        }
+        app = app
+            .configure(app::configure)
+            .app_data(web::PayloadConfig::new(max_body_size))
+            .app_data(limits::json_config(max_body_size));
+        if let Some(authenticator) = &authenticator {
+            app = app.app_data(authenticator.clone());
+        }
+        if let Some(timeout) = &timeout {
+            app = app.app_data(timeout.clone());
+        }
+        app
+    });
+    if let Some(workers) = settings.workers {
+        server = server.workers(workers);
+    }
+    let address = (settings.server.as_str(), settings.port);
+    server = match (&settings.tls_cert, &settings.tls_key) {
+        (Some(cert), Some(key)) => {
+            let store = CertificateStore::load(cert, key)?;
+            #[cfg(unix)]
+            tls::reload_on_sighup(store.clone())?;
+            server.bind_rustls_0_23(address, store.server_config()?)?
+        }
+        _ => server.bind(address)?,
+    };
+    log::info!(
+        "Listening on {}://{}:{}",
+        if settings.tls_cert.is_some() {
+            "https"
+        } else {
+            "http"
+        },
+        settings.server,
+        settings.port
+    );
+    server.run().await?;
+    Ok(())
 }
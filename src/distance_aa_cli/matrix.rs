@@ -0,0 +1,230 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use crate::caching::conditional;
+use crate::error::ApiError;
+use crate::limits::timeout;
+use crate::state::AppState;
+use actix_web::http::header::{self, Header};
+use actix_web::middleware::from_fn;
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use distance_aa_lib::{DistanceMetric, ResidueCode, ResidueOrder};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MatrixQuery {
+    /// Defaults to `grantham`.
+    #[serde(default)]
+    metric: DistanceMetric,
+    /// `library` (the default), `alphabetical`, `grantham`, or one-letter codes such as `GAVLI`.
+    #[serde(default)]
+    order: Option<String>,
+    /// Defaults to `one_letter`.
+    #[serde(default)]
+    code: ResidueCode,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MatrixBody {
+    metric: DistanceMetric,
+    residues: Vec<String>,
+    distances: Vec<Vec<usize>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MatrixFormat {
+    Json,
+    Csv,
+    Tsv,
+}
+
+impl MatrixFormat {
+    /// Picks the first acceptable format by preference, defaulting to JSON when the client
+    /// sends no `Accept` header or accepts anything.
+    fn negotiate(req: &HttpRequest) -> Result<Self, ApiError> {
+        if !req.headers().contains_key(header::ACCEPT) {
+            return Ok(Self::Json);
+        }
+        let accept = header::Accept::parse(req).map_err(ApiError::bad_request)?;
+        accept
+            .ranked()
+            .iter()
+            .find_map(
+                |mime| match (mime.type_().as_str(), mime.subtype().as_str()) {
+                    ("*", "*") | ("application", "*" | "json") => Some(Self::Json),
+                    ("text", "*" | "csv") => Some(Self::Csv),
+                    ("text", "tab-separated-values") => Some(Self::Tsv),
+                    _ => None,
+                },
+            )
+            .ok_or_else(|| {
+                ApiError::not_acceptable(
+                    "Supported types are application/json, text/csv and text/tab-separated-values",
+                )
+            })
+    }
+}
+
+/// The full residue by residue matrix as JSON, CSV or TSV depending on `Accept`, e.g.
+/// `?order=grantham&code=three_letter`.
+#[utoipa::path(
+    context_path = "/api/v1",
+    params(MatrixQuery),
+    responses(
+        (
+            status = 200,
+            content(
+                (MatrixBody = "application/json"),
+                (String = "text/csv"),
+                (String = "text/tab-separated-values"),
+            )
+        ),
+        (status = 400, description = "Invalid order or query", body = ApiError),
+        (status = 406, description = "No supported type in `Accept`", body = ApiError),
+    )
+)]
+#[get(
+    "/distances/matrix",
+    name = "distance_matrix",
+    wrap = "from_fn(conditional)",
+    wrap = "from_fn(timeout)"
+)]
+pub async fn distance_matrix(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    query: web::Query<MatrixQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let format = MatrixFormat::negotiate(&req)?;
+    let order = match &query.order {
+        Some(order) => order
+            .parse::<ResidueOrder>()
+            .map_err(|err| ApiError::bad_request(err).with_input(order))?,
+        None => ResidueOrder::default(),
+    };
+    let matrix = state
+        .get_matrix(query.metric)
+        .ordered(&order)
+        .map_err(|err| ApiError::bad_request(err).with_input(&order.to_string()))?;
+
+    let mut response = HttpResponse::Ok();
+    response.insert_header((header::VARY, "Accept"));
+    Ok(match format {
+        MatrixFormat::Json => response.json(MatrixBody {
+            metric: matrix.get_metric(),
+            residues: matrix.get_labels(query.code),
+            distances: matrix.get_distances(),
+        }),
+        MatrixFormat::Csv => response
+            .content_type("text/csv; charset=utf-8")
+            .body(matrix.to_delimited(',', query.code)),
+        MatrixFormat::Tsv => response
+            .content_type("text/tab-separated-values; charset=utf-8")
+            .body(matrix.to_delimited('\t', query.code)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::app;
+    use actix_web::http::StatusCode;
+    use actix_web::test;
+
+    #[actix_web::test]
+    async fn test_matrix_json() {
+        let app = test::init_service(app()).await;
+        let req = test::TestRequest::get()
+            .uri("/api/v1/distances/matrix?order=grantham&code=three_letter")
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["metric"], "grantham");
+        assert_eq!(body["residues"][0], "Ser");
+        assert_eq!(body["residues"].as_array().unwrap().len(), 20);
+        assert_eq!(body["distances"][0][0], 0);
+        assert_eq!(body["distances"][0][1], 110);
+    }
+
+    #[actix_web::test]
+    async fn test_matrix_csv_and_tsv() {
+        let app = test::init_service(app()).await;
+        let req = test::TestRequest::get()
+            .uri("/api/v1/distances/matrix?order=AK")
+            .insert_header((header::ACCEPT, "text/csv"))
+            .to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        assert_eq!(body, ",A,K\nA,0,106\nK,106,0\n");
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/distances/matrix?order=AK")
+            .insert_header((header::ACCEPT, "text/csv;q=0.5, text/tab-separated-values"))
+            .to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        assert_eq!(body, "\tA\tK\nA\t0\t106\nK\t106\t0\n");
+    }
+
+    #[actix_web::test]
+    async fn test_matrix_not_acceptable() {
+        let app = test::init_service(app()).await;
+        let req = test::TestRequest::get()
+            .uri("/api/v1/distances/matrix")
+            .insert_header((header::ACCEPT, "image/png"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_ACCEPTABLE);
+    }
+
+    #[actix_web::test]
+    async fn test_matrix_bad_order() {
+        let app = test::init_service(app()).await;
+        let req = test::TestRequest::get()
+            .uri("/api/v1/distances/matrix?order=AAK")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["input"], "AAK");
+    }
+}
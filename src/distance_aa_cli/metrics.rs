@@ -0,0 +1,261 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use crate::error::ApiError;
+use crate::state::AppState;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::{get, web, HttpResponse};
+use anyhow::Result;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use serde_json::json;
+use std::future::Future;
+use std::time::Instant;
+
+/// Route label for requests that matched no route, so unknown paths cannot grow the label set.
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Prometheus collectors for the HTTP server, in their own registry.
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    errors: IntCounterVec,
+    latency: HistogramVec,
+    data: IntGaugeVec,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self> {
+        let registry = Registry::new_custom(Some("distaance".into()), None)?;
+        let requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests handled"),
+            &["method", "route", "status"],
+        )?;
+        let errors = IntCounterVec::new(
+            Opts::new(
+                "http_request_errors_total",
+                "HTTP requests answered with a 4xx or 5xx status",
+            ),
+            &["method", "route", "status"],
+        )?;
+        let latency = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time taken to produce a response",
+            ),
+            &["method", "route"],
+        )?;
+        let data = IntGaugeVec::new(
+            Opts::new("data_info", "The loaded amino acid data, always 1"),
+            &["version", "residues"],
+        )?;
+        registry.register(Box::new(requests.clone()))?;
+        registry.register(Box::new(errors.clone()))?;
+        registry.register(Box::new(latency.clone()))?;
+        registry.register(Box::new(data.clone()))?;
+        Ok(Self {
+            registry,
+            requests,
+            errors,
+            latency,
+            data,
+        })
+    }
+    pub fn set_data(&self, state: &AppState) {
+        self.data
+            .with_label_values(&[state.get_version(), &state.get_library().len().to_string()])
+            .set(1);
+    }
+    fn observe(&self, method: &str, route: &str, status: u16, seconds: f64) {
+        let status = status.to_string();
+        self.requests
+            .with_label_values(&[method, route, &status])
+            .inc();
+        if status.starts_with('4') || status.starts_with('5') {
+            self.errors
+                .with_label_values(&[method, route, &status])
+                .inc();
+        }
+        self.latency
+            .with_label_values(&[method, route])
+            .observe(seconds);
+    }
+    /// The registry in the Prometheus text exposition format.
+    pub fn render(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+/// Middleware for `wrap_fn`: records every response against the route pattern that handled it.
+/// Does nothing unless `web::Data<Metrics>` is registered.
+pub fn track<S, B>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<B>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+{
+    let metrics = req.app_data::<web::Data<Metrics>>().cloned();
+    let method = req.method().to_string();
+    let start = Instant::now();
+    let response = srv.call(req);
+    async move {
+        let response = response.await?;
+        if let Some(metrics) = metrics {
+            let route = response
+                .request()
+                .match_pattern()
+                .unwrap_or_else(|| UNMATCHED_ROUTE.to_string());
+            metrics.observe(
+                &method,
+                &route,
+                response.status().as_u16(),
+                start.elapsed().as_secs_f64(),
+            );
+        }
+        Ok(response)
+    }
+}
+
+/// Liveness: the process is up and serving requests.
+#[get("/health")]
+pub async fn health() -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "status": "ok" }))
+}
+
+/// Readiness: the amino acid data loaded and passes validation.
+#[get("/ready")]
+pub async fn ready(state: Option<web::Data<AppState>>) -> HttpResponse {
+    let state = match state {
+        Some(state) => state,
+        None => {
+            return HttpResponse::ServiceUnavailable()
+                .json(json!({ "status": "unavailable", "reason": "No data loaded" }))
+        }
+    };
+    match state.check() {
+        Ok(()) => HttpResponse::Ok().json(json!({
+            "status": "ready",
+            "data_version": state.get_version(),
+            "residues": state.get_library().len(),
+        })),
+        Err(err) => HttpResponse::ServiceUnavailable().json(json!({
+            "status": "unavailable",
+            "reason": err.to_string(),
+        })),
+    }
+}
+
+#[get("/metrics")]
+pub async fn prometheus_metrics(metrics: web::Data<Metrics>) -> Result<HttpResponse, ApiError> {
+    let body = metrics.render().map_err(ApiError::internal)?;
+    Ok(HttpResponse::Ok()
+        .content_type(TextEncoder::new().format_type())
+        .body(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::configure;
+    use crate::testing::state;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App};
+
+    #[actix_web::test]
+    async fn test_metrics() {
+        let state = state();
+        let metrics = Metrics::new().unwrap();
+        metrics.set_data(&state);
+        let app = test::init_service(
+            App::new()
+                .app_data(state)
+                .app_data(web::Data::new(metrics))
+                .wrap_fn(track)
+                .configure(configure),
+        )
+        .await;
+        for uri in [
+            "/api/v1/amino-acids/K",
+            "/api/v1/amino-acids/Q",
+            "/api/v1/x/y/z",
+        ] {
+            test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+        }
+
+        let req = test::TestRequest::get().uri("/metrics").to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(body.contains(
+            r#"distaance_http_requests_total{method="GET",route="/api/v1/amino-acids/{id}",status="200"} 2"#
+        ));
+        assert!(body.contains(
+            r#"distaance_http_request_errors_total{method="GET",route="unmatched",status="404"} 1"#
+        ));
+        assert!(body.contains("distaance_http_request_duration_seconds_bucket"));
+        assert!(body.contains(r#"distaance_data_info{residues="20""#));
+    }
+
+    #[actix_web::test]
+    async fn test_health_and_ready() {
+        let app = test::init_service(App::new().configure(configure)).await;
+        let req = test::TestRequest::get().uri("/health").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        let req = test::TestRequest::get().uri("/ready").to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+
+        let app = test::init_service(App::new().app_data(state()).configure(configure)).await;
+        let req = test::TestRequest::get().uri("/ready").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["status"], "ready");
+        assert_eq!(body["data_version"].as_str().unwrap().len(), 16);
+    }
+}
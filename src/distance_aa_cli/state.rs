@@ -0,0 +1,135 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use crate::error::ApiError;
+use anyhow::{ensure, Context, Result};
+use distance_aa_lib::{
+    load_amino_acid_library, AminoAcid, DistanceMatrix, DistanceMetric, GranthamDistance,
+    STANDARD_RESIDUES,
+};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::path::Path;
+
+/// Data shared by every worker, loaded and validated once at startup.
+#[derive(Debug)]
+pub struct AppState {
+    library: Vec<AminoAcid>,
+    matrices: Vec<DistanceMatrix>,
+    version: String,
+}
+
+impl AppState {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let library = load_amino_acid_library(path)?;
+        let matrices = DistanceMetric::ALL
+            .into_iter()
+            .map(|metric| {
+                DistanceMatrix::new(&library, metric)
+                    .with_context(|| format!("Could not build the {metric} distance matrix"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let version = data_version(&library)?;
+        Ok(Self {
+            library,
+            matrices,
+            version,
+        })
+    }
+    pub fn get_library(&self) -> &[AminoAcid] {
+        &self.library
+    }
+    /// Identifies the loaded data: the start of the SHA-256 of the validated library.
+    pub fn get_version(&self) -> &str {
+        &self.version
+    }
+    /// Checks that the shared data is complete enough to serve every route.
+    pub fn check(&self) -> Result<()> {
+        for residue in STANDARD_RESIDUES.chars() {
+            ensure!(
+                self.library.iter().any(|aa| aa.get_code() == Some(residue)),
+                "Missing standard residue {residue}"
+            );
+        }
+        for metric in DistanceMetric::ALL {
+            ensure!(
+                self.matrices.iter().any(|m| m.get_metric() == metric),
+                "Missing {metric} distance matrix"
+            );
+        }
+        Ok(())
+    }
+
+    /// Looks a residue up by name, three-letter or one-letter code.
+    pub fn find(&self, query: &str) -> Result<&AminoAcid, ApiError> {
+        self.library
+            .iter()
+            .find(|aa| aa.matches(query))
+            .ok_or_else(|| ApiError::unknown_amino_acid(query, &self.library))
+    }
+    pub fn get_matrix(&self, metric: DistanceMetric) -> &DistanceMatrix {
+        self.matrices
+            .iter()
+            .find(|matrix| matrix.get_metric() == metric)
+            .expect("a matrix is built for every metric at startup")
+    }
+    pub fn distance(
+        &self,
+        first: &AminoAcid,
+        second: &AminoAcid,
+        metric: DistanceMetric,
+    ) -> Option<GranthamDistance> {
+        self.get_matrix(metric).get(first, second)
+    }
+}
+
+fn data_version(library: &[AminoAcid]) -> Result<String> {
+    let digest = Sha256::digest(serde_json::to_vec(library)?);
+    let mut version = String::new();
+    for byte in &digest[..8] {
+        // Writing to a String cannot fail.
+        let _ = write!(version, "{byte:02x}");
+    }
+    Ok(version)
+}
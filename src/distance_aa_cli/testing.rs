@@ -0,0 +1,99 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+//! Fixtures shared by the handler tests.
+
+use crate::app::configure;
+use crate::batch::BatchConfig;
+use crate::compare::CompareConfig;
+use crate::jobs::{Job, JobQueue, JobState, JobStatus};
+use crate::state::AppState;
+use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::{web, App};
+use distance_aa_lib::AMINO_ACID_DATA_PATH;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The state loaded from the bundled amino acid data.
+pub fn state() -> web::Data<AppState> {
+    web::Data::new(AppState::load(AMINO_ACID_DATA_PATH).unwrap())
+}
+
+/// The API with the bundled data and default handler settings, but none of the middleware
+/// `main` wraps it in. Register anything else, such as a `JobQueue`, on the result.
+pub fn app() -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    App::new()
+        .app_data(state())
+        .app_data(web::Data::new(BatchConfig::default()))
+        .app_data(web::Data::new(CompareConfig::default()))
+        .configure(configure)
+}
+
+/// Polls `job` until it is no longer queued or running, and returns its last status.
+pub async fn wait(job: &Job) -> JobStatus {
+    for _ in 0..200 {
+        let status = job.status();
+        if !matches!(status.get_state(), JobState::Queued | JobState::Running) {
+            return status;
+        }
+        actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("Job did not finish: {:?}", job.status());
+}
+
+/// The job with this ID, or at this status URL, once it has finished.
+pub async fn finished(queue: &JobQueue, location: &str) -> Arc<Job> {
+    let id = location.rsplit('/').next().unwrap();
+    let job = queue.get(id, None).unwrap();
+    wait(&job).await;
+    job
+}
@@ -0,0 +1,264 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use anyhow::{anyhow, ensure, Context, Result};
+use rustls::crypto::ring::{default_provider, sign};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::ServerConfig;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// The server certificate, read from PEM files and replaceable while the server runs so that
+/// renewed certificates are picked up without a restart.
+#[derive(Debug)]
+pub struct CertificateStore {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl CertificateStore {
+    pub fn load(cert_path: impl AsRef<Path>, key_path: impl AsRef<Path>) -> Result<Arc<Self>> {
+        let cert_path = cert_path.as_ref().to_path_buf();
+        let key_path = key_path.as_ref().to_path_buf();
+        let current = RwLock::new(Arc::new(load_certified_key(&cert_path, &key_path)?));
+        Ok(Arc::new(Self {
+            cert_path,
+            key_path,
+            current,
+        }))
+    }
+    /// Reads the files again. On failure, including a key that does not belong to the
+    /// certificate (as when only one file has been replaced so far), the current certificate
+    /// is kept.
+    pub fn reload(&self) -> Result<()> {
+        let key = load_certified_key(&self.cert_path, &self.key_path)?;
+        *self
+            .current
+            .write()
+            .map_err(|_| anyhow!("Certificate lock poisoned"))? = Arc::new(key);
+        Ok(())
+    }
+    fn current(&self) -> Option<Arc<CertifiedKey>> {
+        self.current.read().ok().map(|key| Arc::clone(&key))
+    }
+    /// A rustls configuration that always presents the current certificate.
+    pub fn server_config(self: &Arc<Self>) -> Result<ServerConfig> {
+        let mut config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::clone(self) as Arc<dyn ResolvesServerCert>);
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(config)
+    }
+}
+
+impl ResolvesServerCert for CertificateStore {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        self.current()
+    }
+}
+
+fn load_certified_key(cert_path: &Path, key_path: &Path) -> Result<CertifiedKey> {
+    let open = |path: &Path| {
+        File::open(path)
+            .map(BufReader::new)
+            .with_context(|| format!("Could not read {}", path.display()))
+    };
+    let certs = rustls_pemfile::certs(&mut open(cert_path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid certificate file {}", cert_path.display()))?;
+    ensure!(
+        !certs.is_empty(),
+        "No certificates in {}",
+        cert_path.display()
+    );
+    let key = rustls_pemfile::private_key(&mut open(key_path)?)
+        .with_context(|| format!("Invalid key file {}", key_path.display()))?
+        .ok_or_else(|| anyhow!("No private key in {}", key_path.display()))?;
+    let key = sign::any_supported_type(&key)
+        .with_context(|| format!("Unsupported private key in {}", key_path.display()))?;
+    let certified = CertifiedKey::new(certs, key);
+    certified.keys_match().with_context(|| {
+        format!(
+            "The key in {} does not belong to the certificate in {}",
+            key_path.display(),
+            cert_path.display()
+        )
+    })?;
+    Ok(certified)
+}
+
+/// Reloads the certificate whenever the process receives SIGHUP.
+#[cfg(unix)]
+pub fn reload_on_sighup(store: Arc<CertificateStore>) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = signal(SignalKind::hangup()).context("Could not listen for SIGHUP")?;
+    actix_web::rt::spawn(async move {
+        while hangup.recv().await.is_some() {
+            match store.reload() {
+                Ok(()) => log::info!("Reloaded TLS certificate"),
+                Err(err) => log::error!("Keeping the current TLS certificate: {err:#}"),
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpServer};
+    use rustls::pki_types::CertificateDer;
+    use rustls::{ClientConfig, ClientConnection, RootCertStore};
+    use std::io::{ErrorKind, Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+
+    struct TestCertificate {
+        cert_path: PathBuf,
+        key_path: PathBuf,
+        der: CertificateDer<'static>,
+    }
+
+    /// Writes a new self-signed certificate for `localhost` to the temporary directory.
+    fn self_signed(name: &str) -> TestCertificate {
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let dir = std::env::temp_dir();
+        let prefix = format!("distaance-{name}-{}", std::process::id());
+        let cert_path = dir.join(format!("{prefix}.crt"));
+        let key_path = dir.join(format!("{prefix}.key"));
+        std::fs::write(&cert_path, generated.cert.pem()).unwrap();
+        std::fs::write(&key_path, generated.key_pair.serialize_pem()).unwrap();
+        TestCertificate {
+            cert_path,
+            key_path,
+            der: generated.cert.der().clone(),
+        }
+    }
+
+    fn https_get(addr: SocketAddr, trusted: CertificateDer<'static>) -> String {
+        let mut roots = RootCertStore::empty();
+        roots.add(trusted).unwrap();
+        let config = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let mut connection =
+            ClientConnection::new(Arc::new(config), "localhost".try_into().unwrap()).unwrap();
+        let mut socket = TcpStream::connect(addr).unwrap();
+        let mut stream = rustls::Stream::new(&mut connection, &mut socket);
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        if let Err(err) = stream.read_to_string(&mut response) {
+            assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        }
+        response
+    }
+
+    #[test]
+    fn test_load_and_reload() {
+        let first = self_signed("reload");
+        let store = CertificateStore::load(&first.cert_path, &first.key_path).unwrap();
+        assert_eq!(store.current().unwrap().cert[0], first.der);
+
+        let second = self_signed("reload");
+        store.reload().unwrap();
+        assert_eq!(store.current().unwrap().cert[0], second.der);
+
+        std::fs::write(&second.cert_path, "not a certificate").unwrap();
+        assert!(store.reload().is_err());
+        assert_eq!(store.current().unwrap().cert[0], second.der);
+    }
+
+    #[test]
+    fn test_load_missing_key() {
+        let certificate = self_signed("missing");
+        std::fs::write(&certificate.key_path, "").unwrap();
+        let error =
+            CertificateStore::load(&certificate.cert_path, &certificate.key_path).unwrap_err();
+        assert!(error.to_string().starts_with("No private key"));
+    }
+
+    #[test]
+    fn test_reload_mismatched_key() {
+        let first = self_signed("mismatched");
+        let store = CertificateStore::load(&first.cert_path, &first.key_path).unwrap();
+
+        let other = self_signed("mismatched-other");
+        std::fs::copy(&other.key_path, &first.key_path).unwrap();
+        let error = store.reload().unwrap_err();
+        assert!(error.to_string().starts_with("The key in"), "{error:#}");
+        assert_eq!(store.current().unwrap().cert[0], first.der);
+        assert!(CertificateStore::load(&first.cert_path, &first.key_path).is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_serves_https() {
+        let certificate = self_signed("https");
+        let store = CertificateStore::load(&certificate.cert_path, &certificate.key_path).unwrap();
+        let server = HttpServer::new(|| App::new().route("/", web::get().to(|| async { "hello" })))
+            .workers(1)
+            .bind_rustls_0_23(("127.0.0.1", 0), store.server_config().unwrap())
+            .unwrap();
+        let addr = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let trusted = certificate.der.clone();
+        let response = actix_web::rt::task::spawn_blocking(move || https_get(addr, trusted))
+            .await
+            .unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("hello"));
+        handle.stop(false).await;
+    }
+}
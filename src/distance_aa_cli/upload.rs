@@ -0,0 +1,368 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+mod parse;
+
+pub use parse::UploadFormat;
+
+use crate::auth::batch_scope;
+use crate::error::ApiError;
+use crate::jobs::{self, JobQueue, JobStatus};
+use crate::limits;
+use crate::state::AppState;
+use actix_multipart::Multipart;
+use actix_web::middleware::from_fn;
+use actix_web::{post, web, HttpMessage, HttpRequest, HttpResponse};
+use distance_aa_lib::{CurveModel, DistanceMetric, HydropathyScale, PlateFormat};
+use futures_util::StreamExt;
+use parse::{LineBuffer, UploadOptions, UploadParser};
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+
+/// Largest plate layout accepted alongside an export, in bytes.
+const MAX_LAYOUT_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UploadQuery {
+    /// What the file holds. Told from its contents, then its extension, if not given.
+    pub format: Option<UploadFormat>,
+    /// For variant tables. Defaults to `grantham`.
+    #[serde(default)]
+    pub metric: DistanceMetric,
+    /// For FASTA files. Defaults to `kyte_doolittle`.
+    #[serde(default)]
+    pub scale: HydropathyScale,
+    /// For plate-reader exports: 96 or 384 wells. Told from the layout, or else from the
+    /// wells in the export, if not given.
+    #[param(value_type = Option<u16>)]
+    pub plate_format: Option<PlateFormat>,
+    /// For plate-reader exports. Defaults to `linear`.
+    #[serde(default)]
+    #[param(value_type = String)]
+    pub model: CurveModel,
+    /// For plate-reader exports: replicate CV, in percent, above which a group is flagged.
+    #[serde(default = "default_cv_threshold")]
+    pub cv_threshold: f64,
+}
+
+pub const fn default_cv_threshold() -> f64 {
+    15.0
+}
+
+/// The multipart form taken by the upload endpoint, for the API description.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct UploadForm {
+    /// FASTA, a CSV or TSV variant table, or a plate-reader export.
+    #[schema(value_type = String, format = Binary)]
+    file: Vec<u8>,
+    /// A plate layout, as TOML or CSV, for plate-reader exports. Without one the readings
+    /// are returned as they are.
+    #[schema(value_type = Option<String>, format = Binary)]
+    layout: Option<Vec<u8>>,
+}
+
+/// Starts a background job from an uploaded file: FASTA records, a CSV or TSV table of
+/// variants, or a plate-reader export. The file is parsed on the blocking thread pool a chunk
+/// at a time as it arrives, rather than held in memory first. Poll the URL in `Location` for
+/// progress.
+#[utoipa::path(
+    context_path = "/api/v1",
+    params(UploadQuery),
+    request_body(content = UploadForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 202, description = "The job was queued", body = JobStatus),
+        (status = 400, description = "The file could not be read", body = ApiError),
+        (status = 413, description = "The upload is over the limit", body = ApiError),
+        (status = 415, description = "Not a multipart form", body = ApiError),
+        (status = 503, description = "Too many jobs are held", body = ApiError),
+    )
+)]
+#[post("/jobs/upload", name = "upload_job", wrap = "from_fn(batch_scope)")]
+pub async fn upload_job(
+    req: HttpRequest,
+    mut form: Multipart,
+    query: web::Query<UploadQuery>,
+    state: web::Data<AppState>,
+    queue: web::Data<JobQueue>,
+) -> Result<HttpResponse, ApiError> {
+    if req.content_type() != "multipart/form-data" {
+        return Err(ApiError::unsupported_media_type(
+            "Send the file as multipart/form-data",
+        ));
+    }
+    let query = query.into_inner();
+    if !(query.cv_threshold.is_finite() && query.cv_threshold >= 0.0) {
+        return Err(ApiError::bad_request(
+            "cv_threshold must be a non-negative number",
+        ));
+    }
+    let options = UploadOptions {
+        metric: query.metric,
+        scale: query.scale,
+        plate_format: query.plate_format,
+        model: query.model,
+        cv_threshold: query.cv_threshold,
+    };
+    let limit = queue.get_config().get_max_upload_size();
+    let too_large = || ApiError::payload_too_large(format!("Uploads are limited to {limit} bytes"));
+
+    let mut size = 0;
+    let mut parser = None;
+    let mut layout = None;
+    while let Some(field) = form.next().await {
+        let mut field = field.map_err(ApiError::bad_request)?;
+        match field.name() {
+            Some("file") if parser.is_none() => {
+                let hint = field
+                    .content_disposition()
+                    .and_then(|disposition| disposition.get_filename())
+                    .and_then(UploadFormat::from_file_name);
+                let mut file = UploadParser::new(query.format, hint, options);
+                let mut lines = LineBuffer::default();
+                while let Some(chunk) = field.next().await {
+                    let chunk = chunk.map_err(ApiError::bad_request)?;
+                    size += chunk.len();
+                    if size > limit {
+                        return Err(too_large());
+                    }
+                    (file, lines) = web::block(move || {
+                        lines.push(&chunk, |line| file.line(line))?;
+                        Ok::<_, ApiError>((file, lines))
+                    })
+                    .await
+                    .map_err(|err| ApiError::internal(err.into()))??;
+                }
+                parser = Some(
+                    web::block(move || {
+                        lines.finish(|line| file.line(line))?;
+                        Ok::<_, ApiError>(file)
+                    })
+                    .await
+                    .map_err(|err| ApiError::internal(err.into()))??,
+                );
+            }
+            Some("layout") if layout.is_none() => {
+                let bytes = field
+                    .bytes(MAX_LAYOUT_SIZE)
+                    .await
+                    .map_err(|_| {
+                        ApiError::payload_too_large(format!(
+                            "Layouts are limited to {MAX_LAYOUT_SIZE} bytes"
+                        ))
+                    })?
+                    .map_err(ApiError::bad_request)?;
+                size += bytes.len();
+                if size > limit {
+                    return Err(too_large());
+                }
+                let text = String::from_utf8(bytes.to_vec())
+                    .map_err(|_| ApiError::bad_request("The layout must be UTF-8 text"))?;
+                layout = Some(text);
+            }
+            Some(name @ ("file" | "layout")) => {
+                return Err(ApiError::bad_request(format!("Send only one {name} field")));
+            }
+            name => {
+                return Err(ApiError::bad_request(format!(
+                    "Unexpected form field {}; send file and, for plate-reader exports, layout",
+                    name.unwrap_or("without a name")
+                )));
+            }
+        }
+    }
+    let parser = parser.ok_or_else(|| ApiError::bad_request("The form has no file field"))?;
+    let input = web::block(move || parser.finish(layout.as_deref()))
+        .await
+        .map_err(|err| ApiError::internal(err.into()))??;
+
+    let job = queue.submit(input, jobs::caller(&req), limits::client(&req), state)?;
+    jobs::accepted(&req, &job)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jobs::JobConfig;
+    use crate::testing::{app, finished};
+    use actix_web::dev::ServiceResponse;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::test;
+    use std::time::Duration;
+
+    const BOUNDARY: &str = "XyZboundary";
+
+    fn form(fields: &[(&str, Option<&str>, &str)]) -> String {
+        let mut body = String::new();
+        for (name, file_name, content) in fields {
+            body.push_str(&format!("--{BOUNDARY}\r\n"));
+            match file_name {
+                Some(file_name) => body.push_str(&format!(
+                    "Content-Disposition: form-data; name=\"{name}\"; filename=\"{file_name}\"\r\n\
+                     Content-Type: application/octet-stream\r\n"
+                )),
+                None => body.push_str(&format!(
+                    "Content-Disposition: form-data; name=\"{name}\"\r\n"
+                )),
+            }
+            body.push_str(&format!("\r\n{content}\r\n"));
+        }
+        body.push_str(&format!("--{BOUNDARY}--\r\n"));
+        body
+    }
+
+    fn upload(query: &str, body: String) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri(&format!("/api/v1/jobs/upload{query}"))
+            .insert_header((
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={BOUNDARY}"),
+            ))
+            .set_payload(body)
+    }
+
+    /// The results of the job that `resp` accepted, once it has finished.
+    async fn job_results(queue: &JobQueue, resp: ServiceResponse) -> serde_json::Value {
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        let status: serde_json::Value = test::read_body_json(resp).await;
+        finished(queue, status["id"].as_str().unwrap())
+            .await
+            .with_results(|results| serde_json::to_value(results).unwrap())
+    }
+
+    fn queue(max_upload_size: usize) -> web::Data<JobQueue> {
+        web::Data::new(JobQueue::new(JobConfig::new(
+            1,
+            10,
+            10,
+            Duration::from_secs(60),
+            max_upload_size,
+        )))
+    }
+
+    #[actix_web::test]
+    async fn test_upload_fasta_and_variants() {
+        let queue = queue(1024 * 1024);
+        let app = test::init_service(app().app_data(queue.clone())).await;
+
+        let body = form(&[("file", Some("proteins.txt"), ">P1\nMKWVTF\n>P2\nMKZ\n")]);
+        let results = job_results(
+            &queue,
+            test::call_service(&app, upload("", body).to_request()).await,
+        )
+        .await;
+        assert_eq!(results[0]["id"], "P1");
+        assert_eq!(
+            results[1]["error"]["message"],
+            "P2: Unknown residue 'Z' at position 3"
+        );
+
+        let body = form(&[("file", Some("variants.tsv"), "gene\tvariant\nTP53\tR97C\n")]);
+        let results = job_results(
+            &queue,
+            test::call_service(&app, upload("", body).to_request()).await,
+        )
+        .await;
+        assert_eq!(results[0]["result"]["distance"], 180);
+
+        let body = form(&[("file", Some("pairs.csv"), "A,K\n")]);
+        let results = job_results(
+            &queue,
+            test::call_service(&app, upload("?format=variants", body).to_request()).await,
+        )
+        .await;
+        assert_eq!(results[0]["result"]["distance"], 106);
+    }
+
+    #[actix_web::test]
+    async fn test_upload_plate_with_layout() {
+        let queue = queue(1024 * 1024);
+        let app = test::init_service(app().app_data(queue.clone())).await;
+        let export = "Wavelength:\t562\n\
+                      A01\t0.100\nA02\t0.102\n\
+                      B01\t0.300\nB02\t0.500\nB03\t0.900\n\
+                      C01\t0.400\nC02\t0.410\n";
+        let layout = "well,role,name,concentration,dilution_factor\n\
+                      A1,blank,,,\nA2,blank,,,\n\
+                      B1,standard,,250,\nB2,standard,,500,\nB3,standard,,1000,\n\
+                      C1,sample,lysate,,2\nC2,sample,lysate,,2\n";
+        let body = form(&[
+            ("file", Some("plate.txt"), export),
+            ("layout", None, layout),
+        ]);
+        let results = job_results(
+            &queue,
+            test::call_service(&app, upload("", body).to_request()).await,
+        )
+        .await;
+        assert_eq!(results[0]["reading"]["absorbances"]["B3"], 0.9);
+        assert!(results[0]["analysis"].is_object(), "{results}");
+    }
+
+    #[actix_web::test]
+    async fn test_upload_rejects() {
+        let queue = queue(64);
+        let app = test::init_service(app().app_data(queue.clone())).await;
+
+        let body = form(&[("file", Some("big.fa"), &">P1\nMKWVTF\n".repeat(20))]);
+        let resp = test::call_service(&app, upload("", body).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let body = form(&[("file", Some("notes.txt"), "hello\nworld\n")]);
+        let resp = test::call_service(&app, upload("", body).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let body = form(&[("data", None, "MKWVTF")]);
+        let resp = test::call_service(&app, upload("", body).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/jobs/upload")
+            .set_payload(">P1\nMKWVTF\n")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+}
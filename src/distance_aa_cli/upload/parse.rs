@@ -0,0 +1,700 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use crate::batch::BatchItem;
+use crate::error::ApiError;
+use crate::jobs::JobInput;
+use anyhow::anyhow;
+use distance_aa_lib::{
+    CurveModel, DistanceMetric, HydropathyScale, PlateFormat, PlateLayout, PlateReading,
+    ProteinSequence, ProteinVariant, STANDARD_RESIDUES,
+};
+use serde::Deserialize;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+/// Longest line kept while waiting for its end, in bytes.
+const MAX_LINE: usize = 1024 * 1024;
+
+/// Lines read before giving up on telling the format apart.
+const DETECT_LINES: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadFormat {
+    /// FASTA records, or a single raw sequence.
+    Fasta,
+    /// A CSV or TSV table with a `variant` column, or `first` and `second` columns.
+    Variants,
+    /// A plate-reader export, in grid or `well,absorbance` layout.
+    Plate,
+}
+
+impl UploadFormat {
+    pub const ALL: [Self; 3] = [Self::Fasta, Self::Variants, Self::Plate];
+
+    pub const fn get_name(self) -> &'static str {
+        match self {
+            Self::Fasta => "fasta",
+            Self::Variants => "variants",
+            Self::Plate => "plate",
+        }
+    }
+
+    /// Guesses from a file extension, for files whose contents are not conclusive.
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        let extension = name.rsplit_once('.')?.1.to_ascii_lowercase();
+        match extension.as_str() {
+            "fa" | "fas" | "fasta" | "faa" => Some(Self::Fasta),
+            "csv" | "tsv" => Some(Self::Variants),
+            _ => None,
+        }
+    }
+
+    /// Tells the format from one line, or `None` if the line does not settle it.
+    fn detect(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        if line.starts_with('>') {
+            return Some(Self::Fasta);
+        }
+        let cells = split(line, delimiter(line));
+        let first = cells.first().copied().unwrap_or_default();
+        if let Some(Columns::Variant(_) | Columns::Pair(..)) = Columns::from_header(&cells) {
+            return Some(Self::Variants);
+        }
+        if ProteinVariant::parse(first).is_ok() {
+            return Some(Self::Variants);
+        }
+        if let [first, second, ..] = cells.as_slice() {
+            if ProteinVariant::parse(&format!("{first}1{second}")).is_ok() {
+                return Some(Self::Variants);
+            }
+        }
+        let mut reading = PlateReading::default();
+        reading.read_line(line, PlateFormat::Wells384);
+        if !reading.is_empty() {
+            return Some(Self::Plate);
+        }
+        reading.read_line(line, PlateFormat::Wells96);
+        if !reading.is_empty() {
+            return Some(Self::Plate);
+        }
+        let residues = line.len() >= 10
+            && line
+                .chars()
+                .all(|c| STANDARD_RESIDUES.contains(c.to_ascii_uppercase()));
+        residues.then_some(Self::Fasta)
+    }
+}
+
+impl Display for UploadFormat {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.get_name())
+    }
+}
+
+impl FromStr for UploadFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|format| format.get_name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| anyhow!("Unknown upload format: {s}"))
+    }
+}
+
+/// Settings that apply to only some of the formats.
+#[derive(Debug, Clone, Copy)]
+pub struct UploadOptions {
+    pub metric: DistanceMetric,
+    pub scale: HydropathyScale,
+    /// Told from the layout, or from the wells in the export, when not given.
+    pub plate_format: Option<PlateFormat>,
+    pub model: CurveModel,
+    pub cv_threshold: f64,
+}
+
+/// Splits bytes into lines as they arrive, holding on to the unfinished last line only.
+#[derive(Debug, Default)]
+pub struct LineBuffer {
+    pending: Vec<u8>,
+    started: bool,
+}
+
+impl LineBuffer {
+    /// Calls `f` with every line completed by `chunk`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a line is too long or not UTF-8, or if `f` fails.
+    pub fn push(
+        &mut self,
+        chunk: &[u8],
+        mut f: impl FnMut(&str) -> Result<(), ApiError>,
+    ) -> Result<(), ApiError> {
+        let mut rest = chunk;
+        while let Some(end) = rest.iter().position(|&byte| byte == b'\n') {
+            self.pending.extend_from_slice(&rest[..end]);
+            rest = &rest[end + 1..];
+            self.emit(&mut f)?;
+        }
+        self.pending.extend_from_slice(rest);
+        if self.pending.len() > MAX_LINE {
+            return Err(ApiError::bad_request(format!(
+                "Lines are limited to {MAX_LINE} bytes"
+            )));
+        }
+        Ok(())
+    }
+    /// Calls `f` with the last line, if it did not end in a newline.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the line is not UTF-8 or if `f` fails.
+    pub fn finish(
+        mut self,
+        mut f: impl FnMut(&str) -> Result<(), ApiError>,
+    ) -> Result<(), ApiError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        self.emit(&mut f)
+    }
+    fn emit(&mut self, f: &mut impl FnMut(&str) -> Result<(), ApiError>) -> Result<(), ApiError> {
+        let line = std::str::from_utf8(&self.pending)
+            .map_err(|_| ApiError::bad_request("Uploads must be UTF-8 text"))?;
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let line = if self.started {
+            line
+        } else {
+            self.started = true;
+            line.strip_prefix('\u{feff}').unwrap_or(line)
+        };
+        f(line)?;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+/// Reads an uploaded file line by line, holding the first few lines back until they show
+/// which format it is.
+#[derive(Debug)]
+pub struct UploadParser {
+    options: UploadOptions,
+    hint: Option<UploadFormat>,
+    pending: Vec<String>,
+    parser: Option<Parser>,
+}
+
+impl UploadParser {
+    /// `format` skips detection; `hint` is used when detection is not conclusive.
+    pub fn new(
+        format: Option<UploadFormat>,
+        hint: Option<UploadFormat>,
+        options: UploadOptions,
+    ) -> Self {
+        Self {
+            options,
+            hint,
+            pending: Vec::new(),
+            parser: format.map(|format| Parser::new(format, options)),
+        }
+    }
+    /// # Errors
+    ///
+    /// Returns an error if the format is still unknown after the first lines.
+    pub fn line(&mut self, line: &str) -> Result<(), ApiError> {
+        if let Some(parser) = &mut self.parser {
+            parser.line(line);
+            return Ok(());
+        }
+        match UploadFormat::detect(line) {
+            Some(format) => {
+                let mut parser = Parser::new(format, self.options);
+                for pending in self.pending.drain(..) {
+                    parser.line(&pending);
+                }
+                parser.line(line);
+                self.parser = Some(parser);
+            }
+            None if self.pending.len() < DETECT_LINES => self.pending.push(line.to_string()),
+            None => match self.hint {
+                Some(format) => {
+                    self.parser = Some(Parser::new(format, self.options));
+                    let pending = std::mem::take(&mut self.pending);
+                    for pending in pending.iter().map(String::as_str).chain([line]) {
+                        self.line(pending)?;
+                    }
+                }
+                None => return Err(undetected()),
+            },
+        }
+        Ok(())
+    }
+    /// The parsed job, with `layout` (TOML or CSV) applied to plate-reader exports.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the format could not be told, the file has nothing in it, or the
+    /// layout is invalid.
+    pub fn finish(mut self, layout: Option<&str>) -> Result<JobInput, ApiError> {
+        if self.parser.is_none() {
+            let format = self.hint.ok_or_else(undetected)?;
+            let mut parser = Parser::new(format, self.options);
+            for pending in self.pending.drain(..) {
+                parser.line(&pending);
+            }
+            self.parser = Some(parser);
+        }
+        match self.parser {
+            Some(parser) => parser.finish(layout, self.options),
+            None => Err(undetected()),
+        }
+    }
+}
+
+fn undetected() -> ApiError {
+    ApiError::bad_request(
+        "Could not tell whether the file is FASTA, a variant table or a plate-reader export; \
+         set format to say which",
+    )
+}
+
+#[derive(Debug)]
+enum Parser {
+    Fasta(FastaParser),
+    Variants(TableParser),
+    Plate(PlateParser),
+}
+
+impl Parser {
+    fn new(format: UploadFormat, options: UploadOptions) -> Self {
+        match format {
+            UploadFormat::Fasta => Self::Fasta(FastaParser::default()),
+            UploadFormat::Variants => Self::Variants(TableParser::default()),
+            UploadFormat::Plate => Self::Plate(PlateParser::new(options.plate_format)),
+        }
+    }
+    fn line(&mut self, line: &str) {
+        if line.trim().is_empty() {
+            return;
+        }
+        match self {
+            Self::Fasta(parser) => parser.line(line),
+            Self::Variants(parser) => parser.line(line),
+            Self::Plate(parser) => parser.line(line),
+        }
+    }
+    fn finish(self, layout: Option<&str>, options: UploadOptions) -> Result<JobInput, ApiError> {
+        let input = match self {
+            Self::Fasta(parser) => JobInput::Sequences {
+                records: parser.finish(),
+                scale: options.scale,
+            },
+            Self::Variants(parser) => JobInput::Variants {
+                items: parser.items,
+                metric: options.metric,
+            },
+            Self::Plate(parser) => {
+                let layout = layout
+                    .map(|layout| {
+                        let toml = layout
+                            .lines()
+                            .map(str::trim)
+                            .find(|line| !line.is_empty() && !line.starts_with('#'))
+                            .is_some_and(|line| line.starts_with('[') || line.contains('='));
+                        if toml {
+                            PlateLayout::from_toml(layout)
+                        } else {
+                            PlateLayout::from_csv(layout)
+                        }
+                    })
+                    .transpose()
+                    .map_err(|err| ApiError::bad_request(format!("{err:#}")))?;
+                let reading = parser.finish(layout.as_ref().map(PlateLayout::get_format));
+                if reading.is_empty() {
+                    return Err(ApiError::bad_request(
+                        "No well readings found in plate-reader export",
+                    ));
+                }
+                JobInput::Plate {
+                    reading,
+                    layout,
+                    model: options.model,
+                    cv_threshold: options.cv_threshold,
+                }
+            }
+        };
+        if input.total() == 0 {
+            return Err(ApiError::bad_request("The file has nothing to process"));
+        }
+        Ok(input)
+    }
+}
+
+/// Plate readings, taken as every format that might apply until the end of the export shows
+/// which one it is.
+#[derive(Debug)]
+struct PlateParser {
+    readings: Vec<(PlateFormat, PlateReading)>,
+}
+
+impl PlateParser {
+    fn new(format: Option<PlateFormat>) -> Self {
+        let formats = match format {
+            Some(format) => vec![format],
+            None => vec![PlateFormat::Wells96, PlateFormat::Wells384],
+        };
+        Self {
+            readings: formats
+                .into_iter()
+                .map(|format| (format, PlateReading::default()))
+                .collect(),
+        }
+    }
+    fn line(&mut self, line: &str) {
+        for (format, reading) in &mut self.readings {
+            reading.read_line(line, *format);
+        }
+    }
+    /// The reading for the layout's format if there is one, or else for the smallest format
+    /// that holds every well found: a 384-well export has wells past H12, or rows too wide for
+    /// a 96-well grid, that only the larger format keeps.
+    fn finish(mut self, layout_format: Option<PlateFormat>) -> PlateReading {
+        let for_layout = self
+            .readings
+            .iter()
+            .position(|(format, _)| Some(*format) == layout_format);
+        if let Some(index) = for_layout {
+            return self.readings.swap_remove(index).1;
+        }
+        self.readings
+            .into_iter()
+            .map(|(_, reading)| reading)
+            .reduce(|smaller, larger| {
+                if larger.get_absorbances().len() > smaller.get_absorbances().len() {
+                    larger
+                } else {
+                    smaller
+                }
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// FASTA records, one at a time; a file without headers is a single raw sequence.
+#[derive(Debug, Default)]
+struct FastaParser {
+    id: Option<String>,
+    header: bool,
+    residues: String,
+    records: Vec<anyhow::Result<ProteinSequence>>,
+}
+
+impl FastaParser {
+    fn line(&mut self, line: &str) {
+        match line.trim().strip_prefix('>') {
+            Some(header) => {
+                self.flush();
+                self.id = header.split_whitespace().next().map(ToString::to_string);
+                self.header = true;
+            }
+            None => self.residues.push_str(line.trim()),
+        }
+    }
+    fn flush(&mut self) {
+        if !self.header && self.residues.is_empty() {
+            return;
+        }
+        let residues = std::mem::take(&mut self.residues);
+        let record = ProteinSequence::new(self.id.as_deref(), &residues);
+        self.records.push(if self.header {
+            let id = self.id.as_deref().unwrap_or("unnamed record");
+            record.map_err(|error| anyhow!("{id}: {error}"))
+        } else {
+            record
+        });
+    }
+    fn finish(mut self) -> Vec<anyhow::Result<ProteinSequence>> {
+        self.flush();
+        self.records
+    }
+}
+
+/// Which cells of a variant table row to read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Columns {
+    Variant(usize),
+    Pair(usize, usize),
+}
+
+impl Columns {
+    fn from_header(cells: &[&str]) -> Option<Self> {
+        let find = |name: &str| {
+            cells
+                .iter()
+                .position(|cell| cell.eq_ignore_ascii_case(name))
+        };
+        if let Some(variant) = find("variant") {
+            return Some(Self::Variant(variant));
+        }
+        Some(Self::Pair(find("first")?, find("second")?))
+    }
+    /// Without a header: pairs if the first cell of the first row is not a variant.
+    fn guess(cells: &[&str]) -> Self {
+        match cells {
+            [first, _, ..] if ProteinVariant::parse(first).is_err() => Self::Pair(0, 1),
+            _ => Self::Variant(0),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct TableParser {
+    delimiter: Option<char>,
+    columns: Option<Columns>,
+    items: Vec<Result<BatchItem, ApiError>>,
+}
+
+impl TableParser {
+    fn line(&mut self, line: &str) {
+        if line.trim_start().starts_with('#') {
+            return;
+        }
+        let delimiter = *self.delimiter.get_or_insert_with(|| delimiter(line));
+        let cells = split(line, delimiter);
+        let columns = match self.columns {
+            Some(columns) => columns,
+            None => match Columns::from_header(&cells) {
+                Some(columns) => {
+                    self.columns = Some(columns);
+                    return;
+                }
+                None => *self.columns.insert(Columns::guess(&cells)),
+            },
+        };
+        let cell = |index: usize| {
+            cells
+                .get(index)
+                .filter(|cell| !cell.is_empty())
+                .map(|cell| (*cell).to_string())
+        };
+        let item = match columns {
+            Columns::Variant(index) => cell(index).map(BatchItem::Notation),
+            Columns::Pair(first, second) => cell(first)
+                .zip(cell(second))
+                .map(|(first, second)| BatchItem::Pair { first, second }),
+        };
+        self.items.push(item.ok_or_else(|| {
+            ApiError::bad_request("The row is missing a variant or residue column")
+                .with_input(line.trim())
+        }));
+    }
+}
+
+/// Tab if the line has one, then semicolon if it has no comma, else comma.
+fn delimiter(line: &str) -> char {
+    if line.contains('\t') {
+        '\t'
+    } else if line.contains(';') && !line.contains(',') {
+        ';'
+    } else {
+        ','
+    }
+}
+
+fn split(line: &str, delimiter: char) -> Vec<&str> {
+    line.split(delimiter)
+        .map(|cell| cell.trim().trim_matches('"').trim())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> UploadOptions {
+        UploadOptions {
+            metric: DistanceMetric::default(),
+            scale: HydropathyScale::default(),
+            plate_format: None,
+            model: CurveModel::default(),
+            cv_threshold: 15.0,
+        }
+    }
+
+    fn parse(chunks: &[&str], hint: Option<UploadFormat>) -> Result<JobInput, ApiError> {
+        let mut parser = UploadParser::new(None, hint, options());
+        let mut lines = LineBuffer::default();
+        for chunk in chunks {
+            lines.push(chunk.as_bytes(), |line| parser.line(line))?;
+        }
+        lines.finish(|line| parser.line(line))?;
+        parser.finish(None)
+    }
+
+    #[test]
+    fn test_line_buffer() {
+        let mut seen = Vec::new();
+        let mut lines = LineBuffer::default();
+        for chunk in ["\u{feff}ab", "c\r\nd", "e\n", "f"] {
+            lines
+                .push(chunk.as_bytes(), |line| {
+                    seen.push(line.to_string());
+                    Ok(())
+                })
+                .unwrap();
+        }
+        lines
+            .finish(|line| {
+                seen.push(line.to_string());
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(seen, ["abc", "de", "f"]);
+    }
+    #[test]
+    fn test_detect() {
+        assert_eq!(UploadFormat::detect(">sp|P1"), Some(UploadFormat::Fasta));
+        assert_eq!(
+            UploadFormat::detect("MKWVTFISLLFLF"),
+            Some(UploadFormat::Fasta)
+        );
+        assert_eq!(
+            UploadFormat::detect("gene,variant"),
+            Some(UploadFormat::Variants)
+        );
+        assert_eq!(
+            UploadFormat::detect("first\tsecond"),
+            Some(UploadFormat::Variants)
+        );
+        assert_eq!(
+            UploadFormat::detect("p.Arg97Cys,x"),
+            Some(UploadFormat::Variants)
+        );
+        assert_eq!(UploadFormat::detect("A01;0.125"), Some(UploadFormat::Plate));
+        assert_eq!(UploadFormat::detect("Wavelength:\t562"), None);
+        assert_eq!(UploadFormat::detect(""), None);
+    }
+    #[test]
+    fn test_parse_fasta_across_chunks() {
+        let input = parse(&[">P1 first\nMKW", "VTF\n>P2\nMK", "Z\n"], None).unwrap();
+        match input {
+            JobInput::Sequences { records, .. } => {
+                assert_eq!(records.len(), 2);
+                assert_eq!(records[0].as_ref().unwrap().get_residues(), "MKWVTF");
+                assert_eq!(
+                    records[1].as_ref().unwrap_err().to_string(),
+                    "P2: Unknown residue 'Z' at position 3"
+                );
+            }
+            other => panic!("Expected sequences, got {other:?}"),
+        }
+    }
+    #[test]
+    fn test_parse_variant_table() {
+        let input = parse(&["gene\tvariant\n", "TP53\tR175H\nBRCA1\t\n"], None).unwrap();
+        match input {
+            JobInput::Variants { items, .. } => {
+                assert!(
+                    matches!(&items[0], Ok(BatchItem::Notation(variant)) if variant == "R175H")
+                );
+                assert!(items[1].is_err());
+            }
+            other => panic!("Expected variants, got {other:?}"),
+        }
+        let input = parse(&["Ala,Lys\nW,R\n"], None).unwrap();
+        assert_eq!(input.total(), 2);
+    }
+    #[test]
+    fn test_parse_plate() {
+        let export = "Plate:\tPlate1\n\t1\t2\t3\t4\t5\t6\t7\t8\t9\t10\t11\t12\n\
+                      A\t0.1\t0.2\t0.3\t0.4\t0.5\t0.6\t0.7\t0.8\t0.9\t1.0\t1.1\t1.2\n";
+        let input = parse(&[export], None).unwrap();
+        assert!(matches!(input, JobInput::Plate { layout: None, .. }));
+        assert_eq!(input.total(), 1);
+    }
+    #[test]
+    fn test_parse_plate_format() {
+        let wells = |input: JobInput| match input {
+            JobInput::Plate { reading, .. } => reading.get_absorbances(),
+            other => panic!("Expected a plate, got {other:?}"),
+        };
+        let export = "Well,Absorbance\nA01,0.1\nH12,0.2\nA13,0.3\nP24,0.4\n";
+        let absorbances = wells(parse(&[export], None).unwrap());
+        assert_eq!(absorbances.len(), 4);
+        assert_eq!(absorbances["P24"], 0.4);
+
+        let export = "Well,Absorbance\nA01,0.1\nH12,0.2\n";
+        assert_eq!(wells(parse(&[export], None).unwrap()).len(), 2);
+
+        let row: Vec<String> = (1..=24).map(|column| format!("0.{column}")).collect();
+        let export = format!("A\t{}\nI\t{}\n", row.join("\t"), row.join("\t"));
+        let absorbances = wells(parse(&[&export], None).unwrap());
+        assert_eq!(absorbances.len(), 48);
+        assert_eq!(absorbances["I24"], 0.24);
+
+        let mut parser = UploadParser::new(
+            Some(UploadFormat::Plate),
+            None,
+            UploadOptions {
+                plate_format: Some(PlateFormat::Wells96),
+                ..options()
+            },
+        );
+        parser.line(&format!("A\t{}", row.join("\t"))).unwrap();
+        assert_eq!(wells(parser.finish(None).unwrap()).len(), 12);
+    }
+    #[test]
+    fn test_parse_undetected() {
+        assert!(parse(&["hello\nworld\n"], None).is_err());
+        let input = parse(&["hello\n"], Some(UploadFormat::Variants)).unwrap();
+        assert_eq!(input.total(), 1);
+    }
+}
@@ -0,0 +1,209 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+/// Iterations `levenberg_marquardt` may take before giving up on converging.
+pub const MAX_ITERATIONS: usize = 500;
+
+/// Solves `matrix * x = rhs` by Gaussian elimination with partial pivoting, returning `None`
+/// when the system is singular. Pivots are compared with the largest entry of the matrix, so
+/// systems in small units are not mistaken for singular ones.
+pub fn solve(mut matrix: Vec<Vec<f64>>, mut rhs: Vec<f64>) -> Option<Vec<f64>> {
+    let n = rhs.len();
+    let scale = matrix
+        .iter()
+        .flatten()
+        .fold(0.0_f64, |scale, value| scale.max(value.abs()));
+    #[allow(clippy::cast_precision_loss)]
+    let tolerance = scale * n as f64 * f64::EPSILON;
+    for column in 0..n {
+        let pivot = (column..n)
+            .max_by(|&i, &j| matrix[i][column].abs().total_cmp(&matrix[j][column].abs()))?;
+        if matrix[pivot][column].abs() <= tolerance {
+            return None;
+        }
+        matrix.swap(column, pivot);
+        rhs.swap(column, pivot);
+        let (upper, lower) = matrix.split_at_mut(column + 1);
+        let pivot_row = &upper[column];
+        for (offset, row) in lower.iter_mut().enumerate() {
+            let factor = row[column] / pivot_row[column];
+            for (value, pivot_value) in row[column..].iter_mut().zip(&pivot_row[column..]) {
+                *value -= factor * pivot_value;
+            }
+            rhs[column + 1 + offset] -= factor * rhs[column];
+        }
+    }
+
+    let mut solution = vec![0.0; n];
+    for row in (0..n).rev() {
+        let known: f64 = (row + 1..n).map(|k| matrix[row][k] * solution[k]).sum();
+        solution[row] = (rhs[row] - known) / matrix[row][row];
+    }
+    Some(solution)
+}
+
+/// Ordinary least squares polynomial fit of the given degree, lowest order coefficient first.
+pub fn polynomial(x: &[f64], y: &[f64], degree: usize) -> Option<Vec<f64>> {
+    let terms = degree + 1;
+    let mut normal = vec![vec![0.0; terms]; terms];
+    let mut rhs = vec![0.0; terms];
+    for (&xi, &yi) in x.iter().zip(y) {
+        let powers: Vec<f64> = (0..terms).map(|p| xi.powi(p as i32)).collect();
+        for i in 0..terms {
+            for j in 0..terms {
+                normal[i][j] += powers[i] * powers[j];
+            }
+            rhs[i] += powers[i] * yi;
+        }
+    }
+    solve(normal, rhs)
+}
+
+/// Levenberg–Marquardt minimisation of the squared residuals of `model`, whose second return
+/// value is the gradient with respect to the parameters. Returns `None` if the fit is still
+/// improving after `MAX_ITERATIONS` iterations.
+pub fn levenberg_marquardt<F>(x: &[f64], y: &[f64], initial: Vec<f64>, model: F) -> Option<Vec<f64>>
+where
+    F: Fn(f64, &[f64]) -> (f64, Vec<f64>),
+{
+    let sse = |params: &[f64]| -> f64 {
+        x.iter()
+            .zip(y)
+            .map(|(&xi, &yi)| (yi - model(xi, params).0).powi(2))
+            .sum()
+    };
+
+    let terms = initial.len();
+    let mut params = initial;
+    let mut error = sse(&params);
+    let mut lambda = 1e-3;
+    for _ in 0..MAX_ITERATIONS {
+        let mut jtj = vec![vec![0.0; terms]; terms];
+        let mut jtr = vec![0.0; terms];
+        for (&xi, &yi) in x.iter().zip(y) {
+            let (value, gradient) = model(xi, &params);
+            for i in 0..terms {
+                for j in 0..terms {
+                    jtj[i][j] += gradient[i] * gradient[j];
+                }
+                jtr[i] += gradient[i] * (yi - value);
+            }
+        }
+        for (i, row) in jtj.iter_mut().enumerate() {
+            row[i] *= 1.0 + lambda;
+        }
+
+        let step = match solve(jtj, jtr) {
+            Some(step) => step,
+            None => {
+                lambda *= 10.0;
+                continue;
+            }
+        };
+        let candidate: Vec<f64> = params.iter().zip(&step).map(|(p, s)| p + s).collect();
+        let candidate_error = sse(&candidate);
+        if candidate_error.is_finite() && candidate_error < error {
+            let converged = (error - candidate_error) <= 1e-12 * error.max(f64::MIN_POSITIVE);
+            params = candidate;
+            error = candidate_error;
+            lambda = (lambda / 10.0).max(1e-12);
+            if converged {
+                return Some(params);
+            }
+        } else {
+            lambda *= 10.0;
+            // No step downhill is left, so this is the minimum.
+            if lambda > 1e12 {
+                return Some(params);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solve() {
+        let solution = solve(vec![vec![2.0, 1.0], vec![1.0, 3.0]], vec![3.0, 5.0]).unwrap();
+        assert!((solution[0] - 0.8).abs() < 1e-12);
+        assert!((solution[1] - 1.4).abs() < 1e-12);
+    }
+    #[test]
+    fn test_solve_singular() {
+        assert!(solve(vec![vec![1.0, 2.0], vec![2.0, 4.0]], vec![1.0, 2.0]).is_none());
+        let nearly = vec![vec![1e20, 2e20], vec![2e20, 4e20 + 1.0]];
+        assert!(solve(nearly, vec![1.0, 2.0]).is_none());
+    }
+    #[test]
+    fn test_solve_small_scale() {
+        let solution = solve(vec![vec![2e-20, 0.0], vec![0.0, 4e-20]], vec![2e-20, 2e-20]).unwrap();
+        assert!((solution[0] - 1.0).abs() < 1e-12);
+        assert!((solution[1] - 0.5).abs() < 1e-12);
+    }
+    #[test]
+    fn test_levenberg_marquardt() {
+        let x = [0.0, 1.0, 2.0, 3.0];
+        let y = [1.0, 3.0, 5.0, 7.0];
+        let line = |xi: f64, p: &[f64]| (p[0] + p[1] * xi, vec![1.0, xi]);
+        let params = levenberg_marquardt(&x, &y, vec![0.0, 0.0], line).unwrap();
+        assert!((params[0] - 1.0).abs() < 1e-6);
+        assert!((params[1] - 2.0).abs() < 1e-6);
+
+        // 1 / p only approaches zero as p grows without bound, so this never settles.
+        let reciprocal = |_: f64, p: &[f64]| (1.0 / p[0], vec![-1.0 / (p[0] * p[0])]);
+        assert!(levenberg_marquardt(&[0.0], &[0.0], vec![1.0], reciprocal).is_none());
+    }
+    #[test]
+    fn test_polynomial() {
+        let x = [0.0, 1.0, 2.0, 3.0];
+        let y = [1.0, 2.0, 5.0, 10.0];
+        let coefficients = polynomial(&x, &y, 2).unwrap();
+        assert!((coefficients[0] - 1.0).abs() < 1e-9);
+        assert!(coefficients[1].abs() < 1e-9);
+        assert!((coefficients[2] - 1.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,431 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use crate::bca::SampleResult;
+use anyhow::{bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt::{self, Display, Formatter, Write};
+
+/// A sample stock to be loaded, at a concentration in µg/mL.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct LoadingSample {
+    name: String,
+    concentration: f64,
+}
+
+impl LoadingSample {
+    #[must_use]
+    pub fn new(name: &str, concentration: f64) -> Self {
+        Self {
+            name: name.to_string(),
+            concentration,
+        }
+    }
+
+    /// Reads `name,concentration` lines. Blank lines and `#` comments are skipped, the first
+    /// remaining line may be a header, and a name may be quoted as `to_csv` writes it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a line does not have a name and a finite numeric concentration.
+    pub fn parse_csv(input: &str) -> Result<Vec<Self>> {
+        let mut samples = Vec::new();
+        let mut header_allowed = true;
+        for (number, line) in input.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let first = std::mem::replace(&mut header_allowed, false);
+            let (name, concentration) = match line.rsplit_once([',', '\t', ';']) {
+                Some((name, concentration)) => (name.trim(), concentration.trim()),
+                None => bail!("Line {}: expected 'name,concentration'", number + 1),
+            };
+            match concentration.parse::<f64>() {
+                Ok(value) if value.is_finite() => samples.push(Self::new(&unquote(name), value)),
+                Ok(_) => bail!(
+                    "Line {}: concentration must be a finite number, got '{concentration}'",
+                    number + 1
+                ),
+                Err(_) if first => continue,
+                Err(error) => {
+                    return Err(error).with_context(|| {
+                        format!(
+                            "Line {}: invalid concentration '{concentration}'",
+                            number + 1
+                        )
+                    })
+                }
+            }
+        }
+        Ok(samples)
+    }
+    #[must_use]
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+    #[must_use]
+    pub const fn get_concentration(&self) -> f64 {
+        self.concentration
+    }
+}
+
+/// Only quantified samples can be loaded; one outside the standard curve, or with no
+/// readings, has no concentration to plan with.
+impl TryFrom<&SampleResult> for LoadingSample {
+    type Error = String;
+
+    fn try_from(result: &SampleResult) -> Result<Self, Self::Error> {
+        match result.get_concentration() {
+            Some(concentration) => Ok(Self::new(&result.get_name(), concentration)),
+            None => Err(format!(
+                "Sample {} has no concentration to load",
+                result.get_name()
+            )),
+        }
+    }
+}
+
+/// Volumes in µL for one lane. `too_dilute` lanes use all the room left after the dye and
+/// carry less than the target protein.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct LaneLoading {
+    name: String,
+    concentration: f64,
+    sample_volume: f64,
+    buffer_volume: f64,
+    dye_volume: f64,
+    protein: f64,
+    too_dilute: bool,
+}
+
+impl LaneLoading {
+    #[must_use]
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+    #[must_use]
+    pub const fn get_concentration(&self) -> f64 {
+        self.concentration
+    }
+    #[must_use]
+    pub const fn get_sample_volume(&self) -> f64 {
+        self.sample_volume
+    }
+    #[must_use]
+    pub const fn get_buffer_volume(&self) -> f64 {
+        self.buffer_volume
+    }
+    #[must_use]
+    pub const fn get_dye_volume(&self) -> f64 {
+        self.dye_volume
+    }
+    /// Protein actually loaded, in µg.
+    #[must_use]
+    pub const fn get_protein(&self) -> f64 {
+        self.protein
+    }
+    #[must_use]
+    pub const fn is_too_dilute(&self) -> bool {
+        self.too_dilute
+    }
+}
+
+/// Equal-loading volumes for a western blot: every lane is filled to the same total volume
+/// with the same amount of protein, topped up with buffer, plus loading dye at `dye_factor`x.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct LoadingPlan {
+    target_protein: f64,
+    lane_volume: f64,
+    dye_factor: f64,
+    lanes: Vec<LaneLoading>,
+}
+
+impl LoadingPlan {
+    /// # Errors
+    ///
+    /// Returns an error if the target or lane volume is not positive or the dye factor is
+    /// not greater than one.
+    pub fn new(
+        target_protein: f64,
+        lane_volume: f64,
+        dye_factor: f64,
+        samples: &[LoadingSample],
+    ) -> Result<Self> {
+        ensure!(
+            target_protein > 0.0,
+            "Target protein per lane must be positive, got {target_protein}"
+        );
+        ensure!(
+            lane_volume > 0.0,
+            "Lane volume must be positive, got {lane_volume}"
+        );
+        ensure!(
+            dye_factor > 1.0,
+            "Loading dye factor must be greater than 1, got {dye_factor}"
+        );
+
+        let dye_volume = lane_volume / dye_factor;
+        let available = lane_volume - dye_volume;
+        let lanes = samples
+            .iter()
+            .map(|sample| {
+                // µg/mL is numerically ng/µL.
+                let per_microlitre = sample.concentration / 1000.0;
+                let needed = if per_microlitre > 0.0 {
+                    target_protein / per_microlitre
+                } else {
+                    f64::INFINITY
+                };
+                let too_dilute = needed > available;
+                let sample_volume = needed.min(available);
+                LaneLoading {
+                    name: sample.name.clone(),
+                    concentration: sample.concentration,
+                    sample_volume,
+                    buffer_volume: available - sample_volume,
+                    dye_volume,
+                    protein: sample_volume * per_microlitre.max(0.0),
+                    too_dilute,
+                }
+            })
+            .collect();
+        Ok(Self {
+            target_protein,
+            lane_volume,
+            dye_factor,
+            lanes,
+        })
+    }
+    #[must_use]
+    pub const fn get_target_protein(&self) -> f64 {
+        self.target_protein
+    }
+    #[must_use]
+    pub const fn get_lane_volume(&self) -> f64 {
+        self.lane_volume
+    }
+    #[must_use]
+    pub const fn get_dye_factor(&self) -> f64 {
+        self.dye_factor
+    }
+    #[must_use]
+    pub fn get_lanes(&self) -> Vec<LaneLoading> {
+        self.lanes.clone()
+    }
+    #[must_use]
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "name,concentration_ug_per_ml,sample_ul,buffer_ul,dye_ul,protein_ug,too_dilute\n",
+        );
+        for lane in &self.lanes {
+            // Writing to a String cannot fail.
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{},{}",
+                quote(&lane.name),
+                lane.concentration,
+                lane.sample_volume,
+                lane.buffer_volume,
+                lane.dye_volume,
+                lane.protein,
+                lane.too_dilute
+            );
+        }
+        csv
+    }
+}
+
+/// A CSV field, quoted if it has a separator, a quote or a line break in it.
+fn quote(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// The reverse of `quote`, leaving unquoted fields as they are.
+fn unquote(field: &str) -> Cow<'_, str> {
+    match field
+        .strip_prefix('"')
+        .and_then(|field| field.strip_suffix('"'))
+    {
+        Some(inner) => Cow::Owned(inner.replace("\"\"", "\"")),
+        None => Cow::Borrowed(field),
+    }
+}
+
+impl Display for LoadingPlan {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let width = self
+            .lanes
+            .iter()
+            .map(|lane| lane.name.chars().count())
+            .chain(std::iter::once(6))
+            .max()
+            .unwrap_or(6);
+        writeln!(
+            f,
+            "{} µg per lane in {} µL ({}x loading dye)",
+            self.target_protein, self.lane_volume, self.dye_factor
+        )?;
+        writeln!(
+            f,
+            "{:<width$}  {:>10}  {:>10}  {:>10}  {:>8}  {:>12}",
+            "Sample", "µg/mL", "Sample µL", "Buffer µL", "Dye µL", "Protein µg"
+        )?;
+        for lane in &self.lanes {
+            write!(
+                f,
+                "{:<width$}  {:>10.1}  {:>10.1}  {:>10.1}  {:>8.1}  {:>12.2}",
+                lane.name,
+                lane.concentration,
+                lane.sample_volume,
+                lane.buffer_volume,
+                lane.dye_volume,
+                lane.protein
+            )?;
+            if lane.too_dilute {
+                write!(f, "  TOO DILUTE")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bca::Sample;
+
+    #[test]
+    fn test_parse_csv() {
+        let samples =
+            LoadingSample::parse_csv("name,concentration\nctrl, 2000\nko 1,1500.5\n").unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[1].get_name(), "ko 1");
+        assert!((samples[1].get_concentration() - 1500.5).abs() < 1e-12);
+        assert!(LoadingSample::parse_csv("a,1\nb,lots\n").is_err());
+        let samples =
+            LoadingSample::parse_csv("# exported 2024-03-01\n\nname,concentration\nctrl,2000\n")
+                .unwrap();
+        assert_eq!(samples, vec![LoadingSample::new("ctrl", 2000.0)]);
+        let error = LoadingSample::parse_csv("ctrl,2000\nko,NaN\n").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Line 2: concentration must be a finite number, got 'NaN'"
+        );
+    }
+    #[test]
+    fn test_try_from_sample_result() {
+        let sample = Sample::new("lysate", 0.6, 10.0);
+        let quantified = SampleResult::new(&sample, Some(2000.0), true);
+        assert_eq!(
+            LoadingSample::try_from(&quantified).unwrap(),
+            LoadingSample::new("lysate", 2000.0)
+        );
+        let unquantified = SampleResult::new(&sample, None, false);
+        assert_eq!(
+            LoadingSample::try_from(&unquantified).unwrap_err(),
+            "Sample lysate has no concentration to load"
+        );
+    }
+    #[test]
+    fn test_new() {
+        let samples = [
+            LoadingSample::new("ctrl", 2000.0),
+            LoadingSample::new("dilute", 500.0),
+        ];
+        let plan = LoadingPlan::new(20.0, 20.0, 4.0, &samples).unwrap();
+        let lanes = plan.get_lanes();
+        assert!((lanes[0].get_sample_volume() - 10.0).abs() < 1e-12);
+        assert!((lanes[0].get_buffer_volume() - 5.0).abs() < 1e-12);
+        assert!((lanes[0].get_dye_volume() - 5.0).abs() < 1e-12);
+        assert!((lanes[0].get_protein() - 20.0).abs() < 1e-12);
+        assert!(!lanes[0].is_too_dilute());
+        assert!(lanes[1].is_too_dilute());
+        assert!((lanes[1].get_sample_volume() - 15.0).abs() < 1e-12);
+        assert!((lanes[1].get_protein() - 7.5).abs() < 1e-12);
+    }
+    #[test]
+    fn test_new_rejects_bad_dye_factor() {
+        assert!(LoadingPlan::new(20.0, 20.0, 1.0, &[]).is_err());
+    }
+    #[test]
+    fn test_to_csv() {
+        let plan =
+            LoadingPlan::new(10.0, 20.0, 4.0, &[LoadingSample::new("ctrl", 1000.0)]).unwrap();
+        assert_eq!(
+            plan.to_csv(),
+            "name,concentration_ug_per_ml,sample_ul,buffer_ul,dye_ul,protein_ug,too_dilute\n\
+             ctrl,1000,10,5,5,10,false\n"
+        );
+    }
+    #[test]
+    fn test_to_csv_quotes_names() {
+        let samples = [
+            LoadingSample::new("ko, day 2", 1000.0),
+            LoadingSample::new("\"wt\"", 1000.0),
+        ];
+        let csv = LoadingPlan::new(10.0, 20.0, 4.0, &samples)
+            .unwrap()
+            .to_csv();
+        let mut lines = csv.lines().skip(1);
+        assert_eq!(lines.next().unwrap(), "\"ko, day 2\",1000,10,5,5,10,false");
+        assert_eq!(lines.next().unwrap(), "\"\"\"wt\"\"\",1000,10,5,5,10,false");
+
+        let parsed = LoadingSample::parse_csv(&csv.replace(",10,5,5,10,false", "")).unwrap();
+        assert_eq!(parsed, samples);
+    }
+    #[test]
+    fn test_fmt() {
+        let plan = LoadingPlan::new(10.0, 20.0, 4.0, &[LoadingSample::new("ctrl", 100.0)]).unwrap();
+        let table = format!("{plan}");
+        assert!(table.starts_with("10 µg per lane in 20 µL (4x loading dye)\n"));
+        assert!(table.trim_end().ends_with("TOO DILUTE"));
+    }
+}
@@ -0,0 +1,342 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use crate::bca::{
+    CurveModel, PlateLayout, PlateReading, Sample, SampleResult, Standard, StandardCurve, WellRole,
+};
+use anyhow::{ensure, Result};
+use serde::{Deserialize, Serialize};
+
+/// Replicate statistics for one well group, on raw (not blank-corrected) absorbances.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct ReplicateSummary {
+    role: WellRole,
+    wells: Vec<String>,
+    absorbances: Vec<f64>,
+    mean: f64,
+    standard_deviation: f64,
+    coefficient_of_variation: f64,
+    flagged: bool,
+    outlier: Option<String>,
+    #[serde(default)]
+    unreadable: Vec<String>,
+}
+
+impl ReplicateSummary {
+    #[must_use]
+    pub fn new(
+        role: WellRole,
+        wells: Vec<String>,
+        absorbances: Vec<f64>,
+        cv_threshold: f64,
+    ) -> Self {
+        #[allow(clippy::cast_precision_loss)]
+        let count = absorbances.len() as f64;
+        let mean = absorbances.iter().sum::<f64>() / count;
+        let standard_deviation = if absorbances.len() > 1 {
+            (absorbances.iter().map(|a| (a - mean).powi(2)).sum::<f64>() / (count - 1.0)).sqrt()
+        } else {
+            0.0
+        };
+        let coefficient_of_variation = if mean.abs() > f64::EPSILON {
+            standard_deviation / mean.abs() * 100.0
+        } else {
+            0.0
+        };
+        let flagged = coefficient_of_variation > cv_threshold;
+        let outlier = if flagged {
+            wells
+                .iter()
+                .zip(&absorbances)
+                .max_by(|(_, a), (_, b)| (*a - mean).abs().total_cmp(&(*b - mean).abs()))
+                .map(|(well, _)| well.clone())
+        } else {
+            None
+        };
+        Self {
+            role,
+            wells,
+            absorbances,
+            mean,
+            standard_deviation,
+            coefficient_of_variation,
+            flagged,
+            outlier,
+            unreadable: Vec::new(),
+        }
+    }
+    /// Records wells of the group that the plate reader could not read, which flags the group.
+    /// The statistics only cover the wells that were read.
+    #[must_use]
+    pub fn with_unreadable(mut self, unreadable: Vec<String>) -> Self {
+        self.flagged |= !unreadable.is_empty();
+        self.unreadable = unreadable;
+        self
+    }
+    #[must_use]
+    pub fn get_role(&self) -> WellRole {
+        self.role.clone()
+    }
+    #[must_use]
+    pub fn get_wells(&self) -> Vec<String> {
+        self.wells.clone()
+    }
+    #[must_use]
+    pub fn get_absorbances(&self) -> Vec<f64> {
+        self.absorbances.clone()
+    }
+    #[must_use]
+    pub const fn get_mean(&self) -> f64 {
+        self.mean
+    }
+    #[must_use]
+    pub const fn get_standard_deviation(&self) -> f64 {
+        self.standard_deviation
+    }
+    #[must_use]
+    pub const fn get_coefficient_of_variation(&self) -> f64 {
+        self.coefficient_of_variation
+    }
+    /// Whether the replicate CV exceeded the QC threshold or a well could not be read.
+    #[must_use]
+    pub const fn is_flagged(&self) -> bool {
+        self.flagged
+    }
+    /// The well furthest from the replicate mean when the group is flagged.
+    #[must_use]
+    pub fn get_outlier(&self) -> Option<String> {
+        self.outlier.clone()
+    }
+    /// Wells of the group left out because the plate reader could not read them.
+    #[must_use]
+    pub fn get_unreadable(&self) -> Vec<String> {
+        self.unreadable.clone()
+    }
+}
+
+/// A complete BCA plate: QC per well group, the fitted standard curve and quantified samples.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct PlateAnalysis {
+    blank: f64,
+    replicates: Vec<ReplicateSummary>,
+    curve: StandardCurve,
+    samples: Vec<SampleResult>,
+}
+
+impl PlateAnalysis {
+    /// Subtracts the mean blank, averages replicates, fits the standards and quantifies the
+    /// samples. Groups whose CV exceeds `cv_threshold` (in percent) are flagged but still
+    /// used, so the caller can decide whether to re-run them. Wells the reader could not read,
+    /// such as saturated ones, are left out of their group and also flag it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a layout well has no reading, every well of a group is unreadable
+    /// or the curve cannot be fitted.
+    pub fn new(
+        layout: &PlateLayout,
+        reading: &PlateReading,
+        model: CurveModel,
+        cv_threshold: f64,
+    ) -> Result<Self> {
+        let mut replicates = Vec::new();
+        for group in layout.get_groups() {
+            let (unreadable, wells): (Vec<String>, Vec<String>) = group
+                .get_wells()
+                .into_iter()
+                .partition(|well| reading.is_unreadable(well));
+            ensure!(
+                !wells.is_empty(),
+                "No well of {} could be read",
+                unreadable.join(", ")
+            );
+            let absorbances = wells
+                .iter()
+                .map(|well| reading.get(well))
+                .collect::<Result<Vec<f64>>>()?;
+            replicates.push(
+                ReplicateSummary::new(group.get_role(), wells, absorbances, cv_threshold)
+                    .with_unreadable(unreadable),
+            );
+        }
+
+        let blanks: Vec<f64> = replicates
+            .iter()
+            .filter(|summary| summary.role == WellRole::Blank)
+            .flat_map(|summary| summary.absorbances.iter().copied())
+            .collect();
+        #[allow(clippy::cast_precision_loss)]
+        let blank = if blanks.is_empty() {
+            0.0
+        } else {
+            blanks.iter().sum::<f64>() / blanks.len() as f64
+        };
+
+        let mut standards = Vec::new();
+        let mut samples = Vec::new();
+        for summary in &replicates {
+            match &summary.role {
+                WellRole::Blank => {}
+                WellRole::Standard { concentration } => {
+                    standards.push(Standard::new(*concentration, summary.mean - blank));
+                }
+                WellRole::Sample {
+                    name,
+                    dilution_factor,
+                } => samples.push(Sample::new(name, summary.mean - blank, *dilution_factor)),
+            }
+        }
+        ensure!(!standards.is_empty(), "Plate layout has no standards");
+
+        let curve = StandardCurve::fit(model, &standards)?;
+        let samples = curve.quantify(&samples);
+        Ok(Self {
+            blank,
+            replicates,
+            curve,
+            samples,
+        })
+    }
+    #[must_use]
+    pub const fn get_blank(&self) -> f64 {
+        self.blank
+    }
+    #[must_use]
+    pub fn get_replicates(&self) -> Vec<ReplicateSummary> {
+        self.replicates.clone()
+    }
+    #[must_use]
+    pub fn get_curve(&self) -> StandardCurve {
+        self.curve.clone()
+    }
+    #[must_use]
+    pub fn get_samples(&self) -> Vec<SampleResult> {
+        self.samples.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bca::PlateFormat;
+
+    const LAYOUT: &str = r#"
+        [[blank]]
+        wells = ["A1", "A2"]
+
+        [[standard]]
+        concentration = 0
+        wells = ["B1", "B2"]
+
+        [[standard]]
+        concentration = 500
+        wells = ["C1", "C2"]
+
+        [[standard]]
+        concentration = 1000
+        wells = ["D1", "D2"]
+
+        [[sample]]
+        name = "lysate"
+        dilution_factor = 4
+        wells = ["E1", "E2"]
+    "#;
+
+    #[test]
+    fn test_replicate_summary() {
+        let summary = ReplicateSummary::new(
+            WellRole::Blank,
+            vec!["A1".to_string(), "A2".to_string(), "A3".to_string()],
+            vec![1.0, 1.0, 1.6],
+            10.0,
+        );
+        assert!((summary.get_mean() - 1.2).abs() < 1e-12);
+        assert!((summary.get_standard_deviation() - 0.346_410_161_5).abs() < 1e-9);
+        assert!(summary.is_flagged());
+        assert_eq!(summary.get_outlier(), Some("A3".to_string()));
+    }
+    #[test]
+    fn test_analysis() {
+        let layout = PlateLayout::from_toml(LAYOUT).unwrap();
+        let export = "A1,0.1\nA2,0.1\nB1,0.1\nB2,0.1\nC1,0.6\nC2,0.6\n\
+                      D1,1.1\nD2,1.1\nE1,0.35\nE2,0.35\n";
+        let reading = PlateReading::parse(export, PlateFormat::Wells96).unwrap();
+        let analysis = PlateAnalysis::new(&layout, &reading, CurveModel::Linear, 10.0).unwrap();
+        assert!((analysis.get_blank() - 0.1).abs() < 1e-12);
+        assert!(analysis.get_replicates().iter().all(|r| !r.is_flagged()));
+        let samples = analysis.get_samples();
+        assert_eq!(samples[0].get_name(), "lysate");
+        assert!((samples[0].get_concentration().unwrap() - 1000.0).abs() < 1e-6);
+    }
+    #[test]
+    fn test_analysis_missing_well() {
+        let layout = PlateLayout::from_toml(LAYOUT).unwrap();
+        let reading = PlateReading::parse("A1,0.1\n", PlateFormat::Wells96).unwrap();
+        let error = PlateAnalysis::new(&layout, &reading, CurveModel::Linear, 10.0).unwrap_err();
+        assert_eq!(error.to_string(), "No reading for well A2");
+    }
+    #[test]
+    fn test_analysis_unreadable_well() {
+        let layout = PlateLayout::from_toml(LAYOUT).unwrap();
+        let export = "A1,0.1\nA2,0.1\nB1,0.1\nB2,0.1\nC1,0.6\nC2,0.6\n\
+                      D1,1.1\nD2,OVRFLW\nE1,0.35\nE2,0.35\n";
+        let reading = PlateReading::parse(export, PlateFormat::Wells96).unwrap();
+        let analysis = PlateAnalysis::new(&layout, &reading, CurveModel::Linear, 10.0).unwrap();
+        let flagged: Vec<ReplicateSummary> = analysis
+            .get_replicates()
+            .into_iter()
+            .filter(ReplicateSummary::is_flagged)
+            .collect();
+        assert_eq!(flagged.len(), 1);
+        assert_eq!(flagged[0].get_wells(), vec!["D1"]);
+        assert_eq!(flagged[0].get_unreadable(), vec!["D2"]);
+        assert!((analysis.get_samples()[0].get_concentration().unwrap() - 1000.0).abs() < 1e-6);
+
+        let reading =
+            PlateReading::parse(&export.replace("D1,1.1", "D1,****"), PlateFormat::Wells96)
+                .unwrap();
+        let error = PlateAnalysis::new(&layout, &reading, CurveModel::Linear, 10.0).unwrap_err();
+        assert_eq!(error.to_string(), "No well of D1, D2 could be read");
+    }
+}
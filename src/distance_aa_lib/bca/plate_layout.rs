@@ -0,0 +1,466 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(try_from = "usize", into = "usize")]
+pub enum PlateFormat {
+    #[default]
+    Wells96,
+    Wells384,
+}
+
+impl PlateFormat {
+    #[must_use]
+    pub const fn get_rows(self) -> usize {
+        match self {
+            Self::Wells96 => 8,
+            Self::Wells384 => 16,
+        }
+    }
+    #[must_use]
+    pub const fn get_columns(self) -> usize {
+        match self {
+            Self::Wells96 => 12,
+            Self::Wells384 => 24,
+        }
+    }
+
+    /// Normalises a well address such as `b03` to `B3`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address is malformed or falls outside the plate.
+    pub fn parse_well(self, well: &str) -> Result<String> {
+        let well = well.trim().to_ascii_uppercase();
+        let mut chars = well.chars();
+        let row = chars
+            .next()
+            .filter(char::is_ascii_uppercase)
+            .ok_or_else(|| anyhow!("Invalid well address: '{well}'"))?;
+        let column: usize = chars
+            .as_str()
+            .parse()
+            .map_err(|_| anyhow!("Invalid well address: '{well}'"))?;
+        let row_index = (row as u8 - b'A') as usize;
+        ensure!(
+            row_index < self.get_rows() && (1..=self.get_columns()).contains(&column),
+            "Well {well} is outside a {self} plate"
+        );
+        Ok(format!("{row}{column}"))
+    }
+}
+
+impl TryFrom<usize> for PlateFormat {
+    type Error = String;
+
+    fn try_from(wells: usize) -> Result<Self, Self::Error> {
+        match wells {
+            96 => Ok(Self::Wells96),
+            384 => Ok(Self::Wells384),
+            _ => Err(format!("Unsupported plate format: {wells} wells")),
+        }
+    }
+}
+
+impl From<PlateFormat> for usize {
+    fn from(format: PlateFormat) -> Self {
+        format.get_rows() * format.get_columns()
+    }
+}
+
+impl Display for PlateFormat {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}-well", usize::from(*self))
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(tag = "role", rename_all = "snake_case")]
+pub enum WellRole {
+    Blank,
+    /// A BSA standard at a known concentration in µg/mL.
+    Standard {
+        concentration: f64,
+    },
+    Sample {
+        name: String,
+        dilution_factor: f64,
+    },
+}
+
+impl Display for WellRole {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Blank => write!(f, "blank"),
+            Self::Standard { concentration } => write!(f, "standard {concentration}"),
+            Self::Sample { name, .. } => write!(f, "{name}"),
+        }
+    }
+}
+
+/// A set of replicate wells sharing one role.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct WellGroup {
+    #[serde(flatten)]
+    role: WellRole,
+    wells: Vec<String>,
+}
+
+impl WellGroup {
+    #[must_use]
+    pub fn new(role: WellRole, wells: &[&str]) -> Self {
+        Self {
+            role,
+            wells: wells.iter().map(ToString::to_string).collect(),
+        }
+    }
+    #[must_use]
+    pub fn get_role(&self) -> WellRole {
+        self.role.clone()
+    }
+    #[must_use]
+    pub fn get_wells(&self) -> Vec<String> {
+        self.wells.clone()
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Default)]
+pub struct PlateLayout {
+    format: PlateFormat,
+    groups: Vec<WellGroup>,
+}
+
+/// The TOML layout file, with one array of tables per role.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LayoutFile {
+    #[serde(default)]
+    format: PlateFormat,
+    #[serde(default)]
+    blank: Vec<BlankEntry>,
+    #[serde(default)]
+    standard: Vec<StandardEntry>,
+    #[serde(default)]
+    sample: Vec<SampleEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BlankEntry {
+    wells: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StandardEntry {
+    concentration: f64,
+    wells: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SampleEntry {
+    name: String,
+    #[serde(default = "default_dilution_factor")]
+    dilution_factor: f64,
+    wells: Vec<String>,
+}
+
+const fn default_dilution_factor() -> f64 {
+    1.0
+}
+
+impl PlateLayout {
+    /// # Errors
+    ///
+    /// Returns an error if a well is malformed, outside the plate, or assigned twice, or a
+    /// sample's dilution factor is not a positive number.
+    pub fn new(format: PlateFormat, groups: Vec<WellGroup>) -> Result<Self> {
+        let mut seen = HashSet::new();
+        let mut normalised = Vec::with_capacity(groups.len());
+        for group in groups {
+            ensure!(!group.wells.is_empty(), "{} has no wells", group.role);
+            if let WellRole::Sample {
+                name,
+                dilution_factor,
+            } = &group.role
+            {
+                ensure!(
+                    *dilution_factor > 0.0 && dilution_factor.is_finite(),
+                    "Sample {name} has a dilution factor of {dilution_factor}; it must be a positive number"
+                );
+            }
+            let mut wells = Vec::with_capacity(group.wells.len());
+            for well in &group.wells {
+                let well = format.parse_well(well)?;
+                ensure!(seen.insert(well.clone()), "Well {well} is assigned twice");
+                wells.push(well);
+            }
+            normalised.push(WellGroup {
+                role: group.role,
+                wells,
+            });
+        }
+        Ok(Self {
+            format,
+            groups: normalised,
+        })
+    }
+
+    /// Reads a layout from TOML with `[[blank]]`, `[[standard]]` and `[[sample]]` tables.
+    ///
+    /// ```toml
+    /// format = 96
+    ///
+    /// [[standard]]
+    /// concentration = 2000
+    /// wells = ["A1", "A2"]
+    ///
+    /// [[sample]]
+    /// name = "lysate"
+    /// dilution_factor = 10
+    /// wells = ["C1", "C2"]
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the TOML is invalid or the layout is inconsistent.
+    pub fn from_toml(input: &str) -> Result<Self> {
+        let file: LayoutFile = toml::from_str(input).context("Invalid plate layout")?;
+        let blanks = file
+            .blank
+            .into_iter()
+            .map(|entry| (WellRole::Blank, entry.wells));
+        let standards = file.standard.into_iter().map(|entry| {
+            (
+                WellRole::Standard {
+                    concentration: entry.concentration,
+                },
+                entry.wells,
+            )
+        });
+        let samples = file.sample.into_iter().map(|entry| {
+            (
+                WellRole::Sample {
+                    name: entry.name,
+                    dilution_factor: entry.dilution_factor,
+                },
+                entry.wells,
+            )
+        });
+        let groups = blanks
+            .chain(standards)
+            .chain(samples)
+            .map(|(role, wells)| WellGroup { role, wells })
+            .collect();
+        Self::new(file.format, groups)
+    }
+
+    /// Reads a layout from CSV with one well per line:
+    /// `well,role,name,concentration,dilution_factor`. Wells with the same role and
+    /// name or concentration are grouped as replicates. The plate is 384-well if any
+    /// address needs it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a line is malformed or the layout is inconsistent.
+    pub fn from_csv(input: &str) -> Result<Self> {
+        let mut groups: Vec<WellGroup> = Vec::new();
+        for (number, line) in input.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("well,") {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let field = |index: usize| fields.get(index).copied().unwrap_or_default();
+            let context = || format!("Line {}: '{line}'", number + 1);
+            let role = match field(1).to_ascii_lowercase().as_str() {
+                "blank" => WellRole::Blank,
+                "standard" => WellRole::Standard {
+                    concentration: field(3).parse().with_context(context)?,
+                },
+                "sample" => WellRole::Sample {
+                    name: field(2).to_string(),
+                    dilution_factor: match field(4) {
+                        "" => default_dilution_factor(),
+                        value => value.parse().with_context(context)?,
+                    },
+                },
+                role => bail!("{}: unknown role '{role}'", context()),
+            };
+            match groups.iter_mut().find(|group| group.role == role) {
+                Some(group) => group.wells.push(field(0).to_string()),
+                None => groups.push(WellGroup {
+                    role,
+                    wells: vec![field(0).to_string()],
+                }),
+            }
+        }
+        let format = if groups
+            .iter()
+            .flat_map(|group| &group.wells)
+            .all(|well| PlateFormat::Wells96.parse_well(well).is_ok())
+        {
+            PlateFormat::Wells96
+        } else {
+            PlateFormat::Wells384
+        };
+        Self::new(format, groups)
+    }
+    #[must_use]
+    pub const fn get_format(&self) -> PlateFormat {
+        self.format
+    }
+    #[must_use]
+    pub fn get_groups(&self) -> Vec<WellGroup> {
+        self.groups.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_well() {
+        assert_eq!(PlateFormat::Wells96.parse_well("b03").unwrap(), "B3");
+        assert!(PlateFormat::Wells96.parse_well("I1").is_err());
+        assert!(PlateFormat::Wells96.parse_well("A13").is_err());
+        assert_eq!(PlateFormat::Wells384.parse_well("P24").unwrap(), "P24");
+        assert!(PlateFormat::Wells96.parse_well("1A").is_err());
+    }
+    #[test]
+    fn test_from_toml() {
+        let layout = PlateLayout::from_toml(
+            r#"
+            [[blank]]
+            wells = ["A1", "A2"]
+
+            [[standard]]
+            concentration = 2000
+            wells = ["B1", "b2"]
+
+            [[sample]]
+            name = "lysate"
+            dilution_factor = 10
+            wells = ["C1"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(layout.get_format(), PlateFormat::Wells96);
+        let groups = layout.get_groups();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0].get_role(), WellRole::Blank);
+        assert_eq!(groups[1].get_wells(), vec!["B1", "B2"]);
+        assert_eq!(
+            groups[2].get_role(),
+            WellRole::Sample {
+                name: "lysate".to_string(),
+                dilution_factor: 10.0
+            }
+        );
+    }
+    #[test]
+    fn test_from_toml_rejects_bad_format() {
+        assert!(PlateLayout::from_toml("format = 48").is_err());
+    }
+    #[test]
+    fn test_from_csv() {
+        let layout = PlateLayout::from_csv(
+            "well,role,name,concentration,dilution_factor\n\
+             A1,blank,,,\n\
+             A2,blank,,,\n\
+             B1,standard,,250,\n\
+             C1,sample,lysate,,5\n\
+             C2,sample,lysate,,5\n",
+        )
+        .unwrap();
+        let groups = layout.get_groups();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0].get_wells(), vec!["A1", "A2"]);
+        assert_eq!(groups[2].get_wells(), vec!["C1", "C2"]);
+    }
+    #[test]
+    fn test_from_csv_detects_384() {
+        let layout = PlateLayout::from_csv("P24,blank,,,\n").unwrap();
+        assert_eq!(layout.get_format(), PlateFormat::Wells384);
+    }
+    #[test]
+    fn test_rejects_duplicate_well() {
+        let groups = vec![
+            WellGroup::new(WellRole::Blank, &["A1"]),
+            WellGroup::new(
+                WellRole::Standard {
+                    concentration: 25.0,
+                },
+                &["a1"],
+            ),
+        ];
+        let error = PlateLayout::new(PlateFormat::Wells96, groups).unwrap_err();
+        assert_eq!(error.to_string(), "Well A1 is assigned twice");
+    }
+    #[test]
+    fn test_rejects_bad_dilution_factor() {
+        let error = PlateLayout::from_toml(
+            r#"
+            [[sample]]
+            name = "lysate"
+            dilution_factor = 0
+            wells = ["C1"]
+            "#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Sample lysate has a dilution factor of 0; it must be a positive number"
+        );
+        assert!(PlateLayout::from_csv("C1,sample,lysate,,NaN\n").is_err());
+        assert!(PlateLayout::from_csv("C1,sample,lysate,,-2\n").is_err());
+    }
+}
@@ -0,0 +1,227 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use crate::bca::PlateFormat;
+use anyhow::{anyhow, bail, ensure, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Absorbances by well, as exported by a plate reader, and the wells whose values could not be
+/// read, such as `OVRFLW` or `****` for a saturated well.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Default)]
+pub struct PlateReading {
+    absorbances: BTreeMap<String, f64>,
+    #[serde(default)]
+    unreadable: BTreeSet<String>,
+}
+
+impl PlateReading {
+    #[must_use]
+    pub fn new(absorbances: BTreeMap<String, f64>) -> Self {
+        Self {
+            absorbances,
+            unreadable: BTreeSet::new(),
+        }
+    }
+
+    /// Parses a plate-reader text or CSV export. Both the grid layout (a row letter followed
+    /// by one value per column) and the list layout (`well,absorbance`) are recognised, with
+    /// comma, semicolon or tab delimiters. Instrument preamble and other lines are skipped.
+    /// Wells whose value is not a finite number are recorded as unreadable.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no well readings are found.
+    pub fn parse(input: &str, format: PlateFormat) -> Result<Self> {
+        let mut reading = Self::default();
+        for line in input.lines() {
+            reading.read_line(line, format);
+        }
+        ensure!(
+            !reading.is_empty(),
+            "No well readings found in plate-reader export"
+        );
+        Ok(reading)
+    }
+
+    /// Adds the readings on one line of an export, as `parse` does, so that an export can be
+    /// read as it arrives. Lines without readings are ignored.
+    pub fn read_line(&mut self, line: &str, format: PlateFormat) {
+        let cells: Vec<&str> = line
+            .split([',', ';', '\t'])
+            .map(|cell| cell.trim().trim_matches('"'))
+            .collect();
+        let (first, rest) = match cells.split_first() {
+            Some(split) => split,
+            None => return,
+        };
+
+        if let Ok(well) = format.parse_well(first) {
+            match rest.iter().find_map(|cell| absorbance(cell)) {
+                Some(value) => self.insert(well, value),
+                None if rest.iter().any(|cell| !cell.is_empty()) => self.insert_unreadable(well),
+                None => {}
+            }
+            return;
+        }
+
+        let is_row_letter = first.len() == 1 && format.parse_well(&format!("{first}1")).is_ok();
+        let columns = format.get_columns();
+        if !is_row_letter || rest.len() < columns {
+            return;
+        }
+        let values: Vec<Option<f64>> = rest[..columns]
+            .iter()
+            .map(|cell| absorbance(cell))
+            .collect();
+        if values.iter().all(Option::is_none) {
+            return;
+        }
+        let row = first.to_ascii_uppercase();
+        for (column, (cell, value)) in rest.iter().zip(values).enumerate() {
+            let well = format!("{row}{}", column + 1);
+            match value {
+                Some(value) => self.insert(well, value),
+                None if !cell.is_empty() => self.insert_unreadable(well),
+                None => {}
+            }
+        }
+    }
+    fn insert(&mut self, well: String, value: f64) {
+        self.unreadable.remove(&well);
+        self.absorbances.insert(well, value);
+    }
+    fn insert_unreadable(&mut self, well: String) {
+        self.absorbances.remove(&well);
+        self.unreadable.insert(well);
+    }
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.absorbances.is_empty()
+    }
+    #[must_use]
+    pub fn get_absorbances(&self) -> BTreeMap<String, f64> {
+        self.absorbances.clone()
+    }
+    /// Wells the export listed without a usable value.
+    #[must_use]
+    pub fn get_unreadable(&self) -> Vec<String> {
+        self.unreadable.iter().cloned().collect()
+    }
+    #[must_use]
+    pub fn is_unreadable(&self, well: &str) -> bool {
+        self.unreadable.contains(well)
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error if the well was not read or its value could not be read.
+    pub fn get(&self, well: &str) -> Result<f64> {
+        if self.is_unreadable(well) {
+            bail!("Well {well} could not be read");
+        }
+        self.absorbances
+            .get(well)
+            .copied()
+            .ok_or_else(|| anyhow!("No reading for well {well}"))
+    }
+}
+
+/// An exported cell as an absorbance, if it is a finite number.
+fn absorbance(cell: &str) -> Option<f64> {
+    cell.parse::<f64>().ok().filter(|value| value.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_grid() {
+        let export = "Plate:\tPlate1\n\
+                      Wavelength:\t562\n\
+                      \t1\t2\t3\t4\t5\t6\t7\t8\t9\t10\t11\t12\n\
+                      A\t0.1\t0.2\t0.3\t0.4\t0.5\t0.6\t0.7\t0.8\t0.9\t1.0\t1.1\t1.2\t562\n\
+                      B\t1\t2\t3\t4\t5\t6\t7\t8\t9\t10\t11\t12\n";
+        let reading = PlateReading::parse(export, PlateFormat::Wells96).unwrap();
+        assert_eq!(reading.get_absorbances().len(), 24);
+        assert!((reading.get("A12").unwrap() - 1.2).abs() < 1e-12);
+        assert!((reading.get("B3").unwrap() - 3.0).abs() < 1e-12);
+        assert!(reading.get("C1").is_err());
+    }
+    #[test]
+    fn test_parse_grid_unreadable() {
+        let export = "A,0.1,0.2,OVRFLW,0.4,0.5,0.6,0.7,0.8,0.9,1.0,1.1,1.2\n\
+                      B,****,2,3,4,5,6,7,8,9,10,11,NaN\n";
+        let reading = PlateReading::parse(export, PlateFormat::Wells96).unwrap();
+        assert_eq!(reading.get_absorbances().len(), 21);
+        assert!((reading.get("A4").unwrap() - 0.4).abs() < 1e-12);
+        assert_eq!(reading.get_unreadable(), vec!["A3", "B1", "B12"]);
+        assert_eq!(
+            reading.get("A3").unwrap_err().to_string(),
+            "Well A3 could not be read"
+        );
+    }
+    #[test]
+    fn test_parse_list() {
+        let export = "Well;Absorbance\n\"A01\";0.125\nH12;2.5\n";
+        let reading = PlateReading::parse(export, PlateFormat::Wells96).unwrap();
+        assert!((reading.get("A1").unwrap() - 0.125).abs() < 1e-12);
+        assert!((reading.get("H12").unwrap() - 2.5).abs() < 1e-12);
+    }
+    #[test]
+    fn test_read_line() {
+        let mut reading = PlateReading::default();
+        reading.read_line("Wavelength:,562", PlateFormat::Wells96);
+        assert!(reading.is_empty());
+        reading.read_line("B07,0.5", PlateFormat::Wells96);
+        assert!((reading.get("B7").unwrap() - 0.5).abs() < 1e-12);
+        reading.read_line("B08,OVRFLW", PlateFormat::Wells96);
+        assert!(reading.is_unreadable("B8"));
+    }
+    #[test]
+    fn test_parse_empty() {
+        assert!(PlateReading::parse("nothing here", PlateFormat::Wells96).is_err());
+    }
+}
@@ -0,0 +1,157 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+
+/// An unknown sample read on the same plate as the standards.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct Sample {
+    name: String,
+    absorbance: f64,
+    dilution_factor: f64,
+}
+
+impl Sample {
+    #[must_use]
+    pub fn new(name: &str, absorbance: f64, dilution_factor: f64) -> Self {
+        Self {
+            name: name.to_string(),
+            absorbance,
+            dilution_factor,
+        }
+    }
+    #[must_use]
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+    #[must_use]
+    pub const fn get_absorbance(&self) -> f64 {
+        self.absorbance
+    }
+    #[must_use]
+    pub const fn get_dilution_factor(&self) -> f64 {
+        self.dilution_factor
+    }
+}
+
+/// A sample's concentration in µg/mL of the undiluted stock. `in_range` is false when the
+/// diluted reading fell outside the standards and the value is an extrapolation.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct SampleResult {
+    name: String,
+    absorbance: f64,
+    dilution_factor: f64,
+    concentration: Option<f64>,
+    in_range: bool,
+}
+
+impl SampleResult {
+    #[must_use]
+    pub fn new(sample: &Sample, concentration: Option<f64>, in_range: bool) -> Self {
+        Self {
+            name: sample.name.clone(),
+            absorbance: sample.absorbance,
+            dilution_factor: sample.dilution_factor,
+            concentration,
+            in_range,
+        }
+    }
+    #[must_use]
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+    #[must_use]
+    pub const fn get_absorbance(&self) -> f64 {
+        self.absorbance
+    }
+    #[must_use]
+    pub const fn get_dilution_factor(&self) -> f64 {
+        self.dilution_factor
+    }
+    #[must_use]
+    pub const fn get_concentration(&self) -> Option<f64> {
+        self.concentration
+    }
+    #[must_use]
+    pub const fn is_in_range(&self) -> bool {
+        self.in_range
+    }
+}
+
+impl Display for SampleResult {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let concentration = self
+            .concentration
+            .map_or_else(|| "n/a".to_string(), |c| c.to_string());
+        write!(
+            f,
+            "Name: {}\tAbsorbance: {}\tDilution: {}\tConcentration: {}\tIn Range: {}",
+            self.name, self.absorbance, self.dilution_factor, concentration, self.in_range
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let sample = Sample::new("lysate", 0.6, 10.0);
+        let result = SampleResult::new(&sample, Some(5000.0), true);
+        assert_eq!(result.get_name(), "lysate");
+        assert_eq!(result.get_concentration(), Some(5000.0));
+        assert!(result.is_in_range());
+    }
+    #[test]
+    fn test_fmt() {
+        let sample = Sample::new("lysate", 0.6, 10.0);
+        let result = SampleResult::new(&sample, None, false);
+        assert_eq!(
+            format!("{result}"),
+            "Name: lysate\tAbsorbance: 0.6\tDilution: 10\tConcentration: n/a\tIn Range: false"
+        );
+    }
+}
@@ -0,0 +1,406 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use crate::bca::least_squares::{levenberg_marquardt, polynomial, MAX_ITERATIONS};
+use crate::bca::{Sample, SampleResult};
+use anyhow::{anyhow, ensure, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CurveModel {
+    #[default]
+    Linear,
+    Quadratic,
+    /// `A = d + (a - d) / (1 + (x / c)^b)`, with coefficients stored as `[a, b, c, d]`.
+    FourParameterLogistic,
+}
+
+impl CurveModel {
+    pub const ALL: [Self; 3] = [Self::Linear, Self::Quadratic, Self::FourParameterLogistic];
+
+    #[must_use]
+    pub const fn get_name(self) -> &'static str {
+        match self {
+            Self::Linear => "linear",
+            Self::Quadratic => "quadratic",
+            Self::FourParameterLogistic => "four_parameter_logistic",
+        }
+    }
+    /// Number of distinct standard concentrations needed to fit the model.
+    #[must_use]
+    pub const fn get_parameter_count(self) -> usize {
+        match self {
+            Self::Linear => 2,
+            Self::Quadratic => 3,
+            Self::FourParameterLogistic => 4,
+        }
+    }
+}
+
+impl Display for CurveModel {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.get_name())
+    }
+}
+
+impl FromStr for CurveModel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "4pl" => Ok(Self::FourParameterLogistic),
+            name => Self::ALL
+                .into_iter()
+                .find(|model| model.get_name() == name)
+                .ok_or_else(|| anyhow!("Unknown curve model: {s}")),
+        }
+    }
+}
+
+/// A BSA standard: known concentration in µg/mL and its blank-corrected absorbance.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+pub struct Standard {
+    concentration: f64,
+    absorbance: f64,
+}
+
+impl Standard {
+    #[must_use]
+    pub const fn new(concentration: f64, absorbance: f64) -> Self {
+        Self {
+            concentration,
+            absorbance,
+        }
+    }
+    #[must_use]
+    pub const fn get_concentration(&self) -> f64 {
+        self.concentration
+    }
+    #[must_use]
+    pub const fn get_absorbance(&self) -> f64 {
+        self.absorbance
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct StandardCurve {
+    model: CurveModel,
+    coefficients: Vec<f64>,
+    r_squared: f64,
+    residuals: Vec<f64>,
+    min_concentration: f64,
+    max_concentration: f64,
+}
+
+impl StandardCurve {
+    /// Fits absorbance as a function of concentration. Replicate standards may share a
+    /// concentration; each is fitted as its own observation.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a value is not finite, there are too few distinct concentrations
+    /// for the model, the fit is degenerate, or an iterative fit does not converge.
+    pub fn fit(model: CurveModel, standards: &[Standard]) -> Result<Self> {
+        ensure!(
+            standards
+                .iter()
+                .all(|s| s.concentration.is_finite() && s.absorbance.is_finite()),
+            "Standards must have finite concentrations and absorbances"
+        );
+        let x: Vec<f64> = standards.iter().map(|s| s.concentration).collect();
+        let y: Vec<f64> = standards.iter().map(|s| s.absorbance).collect();
+        let mut distinct = x.clone();
+        distinct.sort_by(f64::total_cmp);
+        distinct.dedup();
+        ensure!(
+            distinct.len() >= model.get_parameter_count(),
+            "A {} curve needs at least {} distinct standard concentrations, got {}",
+            model,
+            model.get_parameter_count(),
+            distinct.len()
+        );
+
+        let coefficients = match model {
+            CurveModel::Linear => polynomial(&x, &y, 1),
+            CurveModel::Quadratic => polynomial(&x, &y, 2),
+            CurveModel::FourParameterLogistic => {
+                ensure!(
+                    x.iter().all(|&c| c >= 0.0),
+                    "A four parameter logistic curve needs non-negative concentrations"
+                );
+                let coefficients = levenberg_marquardt(
+                    &x,
+                    &y,
+                    four_parameter_guess(&x, &y),
+                    four_parameter_logistic,
+                );
+                ensure!(
+                    coefficients.is_some(),
+                    "The {model} fit did not converge within {MAX_ITERATIONS} iterations"
+                );
+                coefficients
+            }
+        }
+        .filter(|coefficients| coefficients.iter().all(|c| c.is_finite()))
+        .ok_or_else(|| anyhow!("Could not fit a {model} curve to the standards"))?;
+
+        let mut curve = Self {
+            model,
+            coefficients,
+            r_squared: 0.0,
+            residuals: Vec::new(),
+            min_concentration: distinct[0],
+            max_concentration: distinct[distinct.len() - 1],
+        };
+        curve.residuals = standards
+            .iter()
+            .map(|s| s.absorbance - curve.predict(s.concentration))
+            .collect();
+        #[allow(clippy::cast_precision_loss)]
+        let mean = y.iter().sum::<f64>() / y.len() as f64;
+        let total: f64 = y.iter().map(|a| (a - mean).powi(2)).sum();
+        let residual: f64 = curve.residuals.iter().map(|r| r.powi(2)).sum();
+        curve.r_squared = if total > 0.0 {
+            1.0 - residual / total
+        } else {
+            0.0
+        };
+        Ok(curve)
+    }
+    #[must_use]
+    pub const fn get_model(&self) -> CurveModel {
+        self.model
+    }
+    #[must_use]
+    pub fn get_coefficients(&self) -> Vec<f64> {
+        self.coefficients.clone()
+    }
+    #[must_use]
+    pub const fn get_r_squared(&self) -> f64 {
+        self.r_squared
+    }
+    #[must_use]
+    pub fn get_residuals(&self) -> Vec<f64> {
+        self.residuals.clone()
+    }
+    #[must_use]
+    pub const fn get_min_concentration(&self) -> f64 {
+        self.min_concentration
+    }
+    #[must_use]
+    pub const fn get_max_concentration(&self) -> f64 {
+        self.max_concentration
+    }
+
+    /// Absorbance predicted by the curve at a concentration.
+    #[must_use]
+    pub fn predict(&self, concentration: f64) -> f64 {
+        let c = &self.coefficients;
+        match self.model {
+            CurveModel::Linear => c[0] + c[1] * concentration,
+            CurveModel::Quadratic => c[0] + c[1] * concentration + c[2] * concentration.powi(2),
+            CurveModel::FourParameterLogistic => four_parameter_logistic(concentration, c).0,
+        }
+    }
+
+    /// Concentration read off the curve for an absorbance, or `None` if the curve never
+    /// reaches it.
+    #[must_use]
+    pub fn interpolate(&self, absorbance: f64) -> Option<f64> {
+        let c = &self.coefficients;
+        let concentration = match self.model {
+            CurveModel::Linear => (absorbance - c[0]) / c[1],
+            CurveModel::Quadratic if c[2].abs() < f64::EPSILON => (absorbance - c[0]) / c[1],
+            CurveModel::Quadratic => {
+                let discriminant = c[1].powi(2) - 4.0 * c[2] * (c[0] - absorbance);
+                if discriminant < 0.0 {
+                    return None;
+                }
+                // Take the root on the same branch of the parabola as the lowest standard.
+                let roots = [
+                    (-c[1] + discriminant.sqrt()) / (2.0 * c[2]),
+                    (-c[1] - discriminant.sqrt()) / (2.0 * c[2]),
+                ];
+                let slope_at_minimum = c[1] + 2.0 * c[2] * self.min_concentration;
+                roots
+                    .into_iter()
+                    .filter(|r| (c[1] + 2.0 * c[2] * r).signum() == slope_at_minimum.signum())
+                    .min_by(|a, b| a.abs().total_cmp(&b.abs()))?
+            }
+            CurveModel::FourParameterLogistic => {
+                let ratio = (c[0] - c[3]) / (absorbance - c[3]) - 1.0;
+                if ratio <= 0.0 {
+                    return None;
+                }
+                c[2] * ratio.powf(1.0 / c[1])
+            }
+        };
+        Some(concentration).filter(|c| c.is_finite())
+    }
+
+    /// Interpolates every sample and scales it back up by its dilution factor.
+    #[must_use]
+    pub fn quantify(&self, samples: &[Sample]) -> Vec<SampleResult> {
+        samples
+            .iter()
+            .map(|sample| {
+                let measured = self.interpolate(sample.get_absorbance());
+                let in_range = measured
+                    .is_some_and(|c| c >= self.min_concentration && c <= self.max_concentration);
+                SampleResult::new(
+                    sample,
+                    measured.map(|c| c * sample.get_dilution_factor()),
+                    in_range,
+                )
+            })
+            .collect()
+    }
+}
+
+impl Display for StandardCurve {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Model: {}\tCoefficients: {:?}\tR²: {}",
+            self.model, self.coefficients, self.r_squared
+        )
+    }
+}
+
+/// Value and parameter gradient of the four parameter logistic `[a, b, c, d]` at `x`.
+fn four_parameter_logistic(x: f64, params: &[f64]) -> (f64, Vec<f64>) {
+    let (a, b, c, d) = (params[0], params[1], params[2], params[3]);
+    let u = if x > 0.0 { (x / c).powf(b) } else { 0.0 };
+    let denominator = 1.0 + u;
+    let value = d + (a - d) / denominator;
+    let shared = (a - d) * u / denominator.powi(2);
+    let gradient = vec![
+        1.0 / denominator,
+        if x > 0.0 { -shared * (x / c).ln() } else { 0.0 },
+        shared * b / c,
+        1.0 - 1.0 / denominator,
+    ];
+    (value, gradient)
+}
+
+fn four_parameter_guess(x: &[f64], y: &[f64]) -> Vec<f64> {
+    let bottom = y.iter().copied().fold(f64::INFINITY, f64::min);
+    let top = y.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let midpoint = x.iter().copied().fold(f64::NEG_INFINITY, f64::max) / 2.0;
+    vec![bottom, 1.0, midpoint, top + (top - bottom) / 2.0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn standards<F: Fn(f64) -> f64>(curve: F) -> Vec<Standard> {
+        [
+            0.0, 25.0, 125.0, 250.0, 500.0, 750.0, 1000.0, 1500.0, 2000.0,
+        ]
+        .into_iter()
+        .map(|c| Standard::new(c, curve(c)))
+        .collect()
+    }
+
+    #[test]
+    fn test_linear() {
+        let curve =
+            StandardCurve::fit(CurveModel::Linear, &standards(|c| 0.1 + 0.001 * c)).unwrap();
+        let coefficients = curve.get_coefficients();
+        assert!((coefficients[0] - 0.1).abs() < 1e-9);
+        assert!((coefficients[1] - 0.001).abs() < 1e-12);
+        assert!((curve.get_r_squared() - 1.0).abs() < 1e-9);
+        assert!(curve.get_residuals().iter().all(|r| r.abs() < 1e-9));
+        assert!((curve.interpolate(0.6).unwrap() - 500.0).abs() < 1e-6);
+    }
+    #[test]
+    fn test_quadratic() {
+        let truth = |c: f64| 0.1 + 0.0012 * c - 1.5e-7 * c * c;
+        let curve = StandardCurve::fit(CurveModel::Quadratic, &standards(truth)).unwrap();
+        assert!((curve.get_r_squared() - 1.0).abs() < 1e-9);
+        assert!((curve.interpolate(truth(800.0)).unwrap() - 800.0).abs() < 1e-6);
+    }
+    #[test]
+    fn test_four_parameter_logistic() {
+        let truth = |c: f64| 2.5 + (0.1 - 2.5) / (1.0 + (c / 900.0).powf(1.2));
+        let curve =
+            StandardCurve::fit(CurveModel::FourParameterLogistic, &standards(truth)).unwrap();
+        assert!(curve.get_r_squared() > 0.9999);
+        assert!((curve.interpolate(truth(600.0)).unwrap() - 600.0).abs() < 1.0);
+        assert_eq!(curve.interpolate(3.0), None);
+    }
+    #[test]
+    fn test_fit_needs_enough_standards() {
+        let standards = vec![Standard::new(0.0, 0.1), Standard::new(0.0, 0.11)];
+        assert!(StandardCurve::fit(CurveModel::Linear, &standards).is_err());
+    }
+    #[test]
+    fn test_quantify() {
+        let curve =
+            StandardCurve::fit(CurveModel::Linear, &standards(|c| 0.1 + 0.001 * c)).unwrap();
+        let results = curve.quantify(&[
+            Sample::new("lysate", 0.6, 10.0),
+            Sample::new("too concentrated", 2.6, 1.0),
+        ]);
+        assert!((results[0].get_concentration().unwrap() - 5000.0).abs() < 1e-6);
+        assert!(results[0].is_in_range());
+        assert!(!results[1].is_in_range());
+    }
+    #[test]
+    fn test_curve_model_from_str() {
+        assert_eq!(
+            "4PL".parse::<CurveModel>().unwrap(),
+            CurveModel::FourParameterLogistic
+        );
+        assert_eq!(
+            "quadratic".parse::<CurveModel>().unwrap(),
+            CurveModel::Quadratic
+        );
+        assert!("cubic".parse::<CurveModel>().is_err());
+    }
+}
@@ -43,5 +43,7 @@
 // * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
 
 mod amino_acids;
+mod grantham_distances;
 
-pub use amino_acids::amino_acid_library;
+pub use amino_acids::{amino_acid_library, load_amino_acid_library, AMINO_ACID_DATA_PATH};
+pub use grantham_distances::{distance, GRANTHAM_ORDER};
@@ -42,18 +42,135 @@
 // * SOFTWARE.
 // * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
 
-use crate::models::AminoAcid;
+use crate::models::{AminoAcid, STANDARD_RESIDUES};
+use anyhow::{ensure, Context, Result};
+use std::collections::HashSet;
 use std::fs;
+use std::path::Path;
+
+/// Where the bundled amino acid data lives, relative to the crate root.
+pub const AMINO_ACID_DATA_PATH: &str = "src/distance_aa_lib/data/amino_acid_data.json";
 
 #[must_use]
 pub fn amino_acid_library() -> Vec<AminoAcid> {
-    let mut all_amino_acids: Vec<AminoAcid> = Vec::new();
+    load_amino_acid_library(AMINO_ACID_DATA_PATH).expect("Could not read amino acid data file")
+}
+
+/// Reads and validates an amino acid data file: every standard residue must appear exactly
+/// once under its one-letter code, with a unique name, a positive molecular weight and codons
+/// written as DNA.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or parsed, or fails validation.
+pub fn load_amino_acid_library(path: impl AsRef<Path>) -> Result<Vec<AminoAcid>> {
+    let path = path.as_ref();
+    let data = fs::read_to_string(path)
+        .with_context(|| format!("Could not read amino acid data file {}", path.display()))?;
+    let amino_acids: Vec<AminoAcid> = serde_json::from_str(&data)
+        .with_context(|| format!("Invalid amino acid data in {}", path.display()))?;
+    validate(&amino_acids)
+        .with_context(|| format!("Invalid amino acid data in {}", path.display()))?;
+    Ok(amino_acids)
+}
+
+fn validate(amino_acids: &[AminoAcid]) -> Result<()> {
+    let mut names = HashSet::new();
+    let mut codes = HashSet::new();
+    for amino_acid in amino_acids {
+        let name = amino_acid.get_name();
+        let code = amino_acid
+            .get_code()
+            .filter(|c| STANDARD_RESIDUES.contains(*c));
+        ensure!(
+            code.is_some(),
+            "{name} has abbreviation '{}', which is not a standard one-letter code",
+            amino_acid.get_abbreviation()
+        );
+        ensure!(
+            amino_acid.get_molecular_weight() > 0.0,
+            "{name} has a non-positive molecular weight"
+        );
+        for codon in amino_acid.get_codon() {
+            ensure!(
+                codon.len() == 3 && codon.chars().all(|base| "ACGT".contains(base)),
+                "{name} has codon '{codon}', which is not three DNA bases"
+            );
+        }
+        ensure!(names.insert(name.clone()), "{name} appears more than once");
+        ensure!(
+            codes.insert(code),
+            "More than one amino acid has the code '{}'",
+            amino_acid.get_abbreviation()
+        );
+    }
+    let missing: String = STANDARD_RESIDUES
+        .chars()
+        .filter(|c| !codes.contains(&Some(*c)))
+        .collect();
+    ensure!(missing.is_empty(), "Missing standard residues: {missing}");
+    Ok(())
+}
 
-    if let Ok(data) = fs::read_to_string("src/distance_aa_lib/data/amino_acid_data.json") {
-        let amino_acids: Vec<AminoAcid> = serde_json::from_str(&data).unwrap();
-        all_amino_acids.extend(amino_acids);
-    } else {
-        panic!("Could not read amino acid data file");
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_amino_acid_library() {
+        let library = amino_acid_library();
+        assert_eq!(library.len(), 20);
+        assert!(library.iter().any(|aa| aa.get_name() == "Tryptophan"));
+    }
+    #[test]
+    fn test_amino_acid_library_codes_and_codons() {
+        let library = amino_acid_library();
+        let find = |name: &str| library.iter().find(|aa| aa.get_name() == name).unwrap();
+        assert_eq!(find("Aspartic Acid").get_abbreviation(), "D");
+        assert_eq!(find("Aspartic Acid").get_codon(), ["GAT", "GAC"]);
+        assert_eq!(find("Cysteine").get_abbreviation(), "C");
+    }
+    #[test]
+    fn test_load_missing_file() {
+        let error = load_amino_acid_library("does/not/exist.json").unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Could not read amino acid data file"));
+    }
+    #[test]
+    fn test_validate_duplicate_code() {
+        let mut library = amino_acid_library();
+        library.push(AminoAcid::new(
+            "Selenocysteine",
+            "Sec",
+            "C",
+            "Polar",
+            168.06,
+            &["TGA"],
+        ));
+        let error = validate(&library).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "More than one amino acid has the code 'C'"
+        );
+    }
+    #[test]
+    fn test_validate_rna_codon() {
+        let mut library = amino_acid_library();
+        library[0] = AminoAcid::new("Alanine", "Ala", "A", "Nonpolar", 89.09, &["GCU"]);
+        let error = validate(&library).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Alanine has codon 'GCU', which is not three DNA bases"
+        );
+    }
+    #[test]
+    fn test_validate_missing_residue() {
+        let library: Vec<AminoAcid> = amino_acid_library()
+            .into_iter()
+            .filter(|aa| aa.get_abbreviation() != "W")
+            .collect();
+        let error = validate(&library).unwrap_err();
+        assert_eq!(error.to_string(), "Missing standard residues: W");
     }
-    all_amino_acids
 }
@@ -42,18 +42,115 @@
 // * SOFTWARE.
 // * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
 
-use crate::models::GranthamDistance;
-use std::fs;
+use crate::models::{AminoAcid, DistanceMetric, GranthamDistance};
 
+/// Row and column order of `GRANTHAM`, as laid out in the original publication.
+pub const GRANTHAM_ORDER: &str = "SRLPTAVGIFYCHQNKDEMW";
+
+/// Grantham (1974), Science 185:862-864. Only the upper triangle is given; the matrix is
+/// symmetric with a zero diagonal.
+const GRANTHAM: [[usize; 20]; 20] = [
+    [
+        0, 110, 145, 74, 58, 99, 124, 56, 142, 155, 144, 112, 89, 68, 46, 121, 65, 80, 135, 177,
+    ],
+    [
+        0, 0, 102, 103, 71, 112, 96, 125, 97, 97, 77, 180, 29, 43, 86, 26, 96, 54, 91, 101,
+    ],
+    [
+        0, 0, 0, 98, 92, 96, 32, 138, 5, 22, 36, 198, 99, 113, 153, 107, 172, 138, 15, 61,
+    ],
+    [
+        0, 0, 0, 0, 38, 27, 68, 42, 95, 114, 110, 169, 77, 76, 91, 103, 108, 93, 87, 147,
+    ],
+    [
+        0, 0, 0, 0, 0, 58, 69, 59, 89, 103, 92, 149, 47, 42, 65, 78, 85, 65, 81, 128,
+    ],
+    [
+        0, 0, 0, 0, 0, 0, 64, 60, 94, 113, 112, 195, 86, 91, 111, 106, 126, 107, 84, 148,
+    ],
+    [
+        0, 0, 0, 0, 0, 0, 0, 109, 29, 50, 55, 192, 84, 96, 133, 97, 152, 121, 21, 88,
+    ],
+    [
+        0, 0, 0, 0, 0, 0, 0, 0, 135, 153, 147, 159, 98, 87, 80, 127, 94, 98, 127, 184,
+    ],
+    [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 21, 33, 198, 94, 109, 149, 102, 168, 134, 10, 61,
+    ],
+    [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 22, 205, 100, 116, 158, 102, 177, 140, 28, 40,
+    ],
+    [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 194, 83, 99, 143, 85, 160, 122, 36, 37,
+    ],
+    [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 174, 154, 139, 202, 154, 170, 196, 215,
+    ],
+    [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 24, 68, 32, 81, 40, 87, 115,
+    ],
+    [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 46, 53, 61, 29, 101, 130,
+    ],
+    [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 94, 23, 42, 142, 174,
+    ],
+    [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 101, 56, 95, 110,
+    ],
+    [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 45, 160, 181,
+    ],
+    [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 126, 152,
+    ],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 67],
+    [0; 20],
+];
+
+fn grantham(first: char, second: char) -> Option<usize> {
+    let i = GRANTHAM_ORDER.find(first)?;
+    let j = GRANTHAM_ORDER.find(second)?;
+    Some(GRANTHAM[i.min(j)][i.max(j)])
+}
+
+/// Looks up the distance between two residues by their one-letter codes, or `None` if either
+/// is not a standard residue.
 #[must_use]
-pub fn amino_acid_library() -> Vec<AminoAcid> {
-    let mut all_amino_acids: Vec<AminoAcid> = Vec::new();
+pub fn distance(
+    first: &AminoAcid,
+    second: &AminoAcid,
+    metric: DistanceMetric,
+) -> Option<GranthamDistance> {
+    let value = match metric {
+        DistanceMetric::Grantham => grantham(first.get_code()?, second.get_code()?)?,
+    };
+    Some(GranthamDistance::new(first.clone(), second.clone(), value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if let Ok(data) = fs::read_to_string("src/distance_aa_lib/data/amino_acid_data.json") {
-        let amino_acids: Vec<AminoAcid> = serde_json::from_str(&data).unwrap();
-        all_amino_acids.extend(amino_acids);
-    } else {
-        panic!("Could not read amino acid data file");
+    #[test]
+    fn test_grantham() {
+        assert_eq!(grantham('A', 'K'), Some(106));
+        assert_eq!(grantham('K', 'A'), Some(106));
+        assert_eq!(grantham('L', 'I'), Some(5));
+        assert_eq!(grantham('C', 'W'), Some(215));
+        assert_eq!(grantham('G', 'G'), Some(0));
+        assert_eq!(grantham('X', 'G'), None);
+    }
+    #[test]
+    fn test_distance() {
+        let alanine = AminoAcid::new("Alanine", "Ala", "A", "Nonpolar", 89.09, &["GCT"]);
+        let lysine = AminoAcid::new("Lysine", "Lys", "K", "Basic", 146.19, &["AAA"]);
+        let result = distance(&alanine, &lysine, DistanceMetric::Grantham).unwrap();
+        assert_eq!(result.get_distance(), 106);
+        assert_eq!(result.get_first(), alanine);
+        assert_eq!(
+            distance(&alanine, &AminoAcid::default(), DistanceMetric::Grantham),
+            None
+        );
     }
-    all_amino_acids
 }
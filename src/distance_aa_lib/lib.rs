@@ -42,8 +42,21 @@
 // * SOFTWARE.
 // * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
 
+mod bca;
 mod data;
 mod models;
 
-pub use data::amino_acid_library;
-pub use models::AminoAcid;
+pub use bca::{
+    CurveModel, LaneLoading, LoadingPlan, LoadingSample, PlateAnalysis, PlateFormat, PlateLayout,
+    PlateReading, ReplicateSummary, Sample, SampleResult, Standard, StandardCurve, WellGroup,
+    WellRole,
+};
+pub use data::{
+    amino_acid_library, distance, load_amino_acid_library, AMINO_ACID_DATA_PATH, GRANTHAM_ORDER,
+};
+pub use models::{
+    AminoAcid, Concentration, CysteineState, DistanceMatrix, DistanceMetric, ExtinctionCoefficient,
+    GranthamDistance, HydropathyPoint, HydropathyProfile, HydropathyScale, PositionComparison,
+    ProteinSequence, ProteinVariant, ResidueCode, ResidueOrder, SequenceComparison,
+    SubstitutionClass, SubstitutionCounts, STANDARD_RESIDUES,
+};
@@ -43,8 +43,29 @@
 // * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
 
 mod amino_acid;
+mod distance_matrix;
+mod distance_metric;
+mod extinction_coefficient;
 mod grantham_distance;
+mod hydropathy_profile;
+mod hydropathy_scale;
+mod protein_sequence;
+mod protein_variant;
+mod residue_code;
+mod residue_order;
+mod sequence_comparison;
+mod substitution_class;
 
 pub use amino_acid::AminoAcid;
-#[allow(unused_imports)]
+pub use distance_matrix::DistanceMatrix;
+pub use distance_metric::DistanceMetric;
+pub use extinction_coefficient::{Concentration, CysteineState, ExtinctionCoefficient};
 pub use grantham_distance::GranthamDistance;
+pub use hydropathy_profile::{HydropathyPoint, HydropathyProfile};
+pub use hydropathy_scale::HydropathyScale;
+pub use protein_sequence::{ProteinSequence, STANDARD_RESIDUES};
+pub use protein_variant::ProteinVariant;
+pub use residue_code::ResidueCode;
+pub use residue_order::ResidueOrder;
+pub use sequence_comparison::{PositionComparison, SequenceComparison, SubstitutionCounts};
+pub use substitution_class::SubstitutionClass;
@@ -42,11 +42,15 @@
 // * SOFTWARE.
 // * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
 
+use crate::models::HydropathyScale;
 use serde::{Deserialize, Serialize};
 use std::fmt::{self, Display, Formatter};
 use std::string::ToString;
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
 pub struct AminoAcid {
     name: String,
     short_name: String,
@@ -107,6 +111,27 @@ impl AminoAcid {
     pub fn get_codon_count(&self) -> usize {
         self.codon.len()
     }
+    /// Whether `query` is this residue's name, three-letter or one-letter code, ignoring case.
+    #[must_use]
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.trim();
+        [&self.name, &self.short_name, &self.abbreviation]
+            .iter()
+            .any(|candidate| candidate.eq_ignore_ascii_case(query))
+    }
+    /// The one-letter code, or `None` if the abbreviation is not a single character.
+    #[must_use]
+    pub fn get_code(&self) -> Option<char> {
+        let mut code = self.abbreviation.chars();
+        match (code.next(), code.next()) {
+            (Some(residue), None) => Some(residue),
+            _ => None,
+        }
+    }
+    #[must_use]
+    pub fn get_hydropathy(&self, scale: HydropathyScale) -> Option<f64> {
+        scale.value(self.get_code()?)
+    }
 }
 
 impl Display for AminoAcid {
@@ -237,6 +262,46 @@ mod tests {
         );
         assert_eq!(amino_acid.get_codon_count(), 4);
     }
+    #[test]
+    fn test_matches() {
+        let amino_acid = AminoAcid::new(
+            "Alanine",
+            "Ala",
+            "A",
+            "Nonpolar",
+            89.09,
+            &["GCT", "GCC", "GCA", "GCG"],
+        );
+        assert!(amino_acid.matches("alanine"));
+        assert!(amino_acid.matches("ALA"));
+        assert!(amino_acid.matches("a"));
+        assert!(!amino_acid.matches("Arg"));
+    }
+    #[test]
+    fn test_get_code() {
+        let amino_acid = AminoAcid::new("Alanine", "Ala", "A", "Nonpolar", 89.09, &["GCT"]);
+        assert_eq!(amino_acid.get_code(), Some('A'));
+        assert_eq!(AminoAcid::default().get_code(), Some('?'));
+    }
+    #[test]
+    fn test_get_hydropathy() {
+        let amino_acid = AminoAcid::new(
+            "Alanine",
+            "Ala",
+            "A",
+            "Nonpolar",
+            89.09,
+            &["GCT", "GCC", "GCA", "GCG"],
+        );
+        assert_eq!(
+            amino_acid.get_hydropathy(HydropathyScale::KyteDoolittle),
+            Some(1.8)
+        );
+        assert_eq!(
+            AminoAcid::default().get_hydropathy(HydropathyScale::KyteDoolittle),
+            None
+        );
+    }
 
     #[test]
     fn test_fmt() {
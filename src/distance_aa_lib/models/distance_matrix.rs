@@ -0,0 +1,243 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use crate::data::distance;
+use crate::models::{AminoAcid, DistanceMetric, GranthamDistance, ResidueCode, ResidueOrder};
+use anyhow::{anyhow, ensure, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// Every pairwise distance between the residues of a library under one metric, computed up
+/// front so lookups are plain indexing.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct DistanceMatrix {
+    metric: DistanceMetric,
+    residues: Vec<AminoAcid>,
+    distances: Vec<Vec<usize>>,
+}
+
+impl DistanceMatrix {
+    /// # Errors
+    ///
+    /// Returns an error if the metric has no value for some pair of residues.
+    pub fn new(library: &[AminoAcid], metric: DistanceMetric) -> Result<Self> {
+        let distances = library
+            .iter()
+            .map(|first| {
+                library
+                    .iter()
+                    .map(|second| {
+                        distance(first, second, metric)
+                            .map(|d| d.get_distance())
+                            .ok_or_else(|| {
+                                anyhow!(
+                                    "No {metric} distance between {} and {}",
+                                    first.get_name(),
+                                    second.get_name()
+                                )
+                            })
+                    })
+                    .collect::<Result<Vec<usize>>>()
+            })
+            .collect::<Result<Vec<Vec<usize>>>>()?;
+        Ok(Self {
+            metric,
+            residues: library.to_vec(),
+            distances,
+        })
+    }
+    #[must_use]
+    pub const fn get_metric(&self) -> DistanceMetric {
+        self.metric
+    }
+    #[must_use]
+    pub fn get_residues(&self) -> Vec<AminoAcid> {
+        self.residues.clone()
+    }
+    #[must_use]
+    pub fn get_distances(&self) -> Vec<Vec<usize>> {
+        self.distances.clone()
+    }
+    #[must_use]
+    pub fn get(&self, first: &AminoAcid, second: &AminoAcid) -> Option<GranthamDistance> {
+        let i = self.residues.iter().position(|aa| aa == first)?;
+        let j = self.residues.iter().position(|aa| aa == second)?;
+        Some(GranthamDistance::new(
+            first.clone(),
+            second.clone(),
+            self.distances[i][j],
+        ))
+    }
+    /// Looks a distance up by one-letter codes.
+    #[must_use]
+    pub fn get_by_code(&self, first: char, second: char) -> Option<usize> {
+        let index = |code: char| {
+            self.residues
+                .iter()
+                .position(|aa| aa.get_code() == Some(code))
+        };
+        Some(self.distances[index(first)?][index(second)?])
+    }
+    /// Returns the matrix with its rows and columns rearranged, keeping only the residues the
+    /// order names.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the order names a residue that is not in the matrix, or names one
+    /// twice.
+    pub fn ordered(&self, order: &ResidueOrder) -> Result<Self> {
+        let mut indices: Vec<usize> = Vec::new();
+        for code in order.codes(&self.residues) {
+            let index = self
+                .residues
+                .iter()
+                .position(|aa| aa.get_code() == Some(code))
+                .ok_or_else(|| anyhow!("Unknown residue '{code}' in order {order}"))?;
+            ensure!(
+                !indices.contains(&index),
+                "Residue '{code}' appears more than once in order {order}"
+            );
+            indices.push(index);
+        }
+        Ok(Self {
+            metric: self.metric,
+            residues: indices.iter().map(|&i| self.residues[i].clone()).collect(),
+            distances: indices
+                .iter()
+                .map(|&i| indices.iter().map(|&j| self.distances[i][j]).collect())
+                .collect(),
+        })
+    }
+    #[must_use]
+    pub fn get_labels(&self, code: ResidueCode) -> Vec<String> {
+        self.residues.iter().map(|aa| code.label(aa)).collect()
+    }
+    /// Writes the matrix as a table with residue labels in the first row and column, e.g.
+    /// CSV with `','` or TSV with `'\t'`.
+    #[must_use]
+    pub fn to_delimited(&self, delimiter: char, code: ResidueCode) -> String {
+        let labels = self.get_labels(code);
+        let mut table = String::new();
+        for label in &labels {
+            table.push(delimiter);
+            table.push_str(label);
+        }
+        table.push('\n');
+        for (label, row) in labels.iter().zip(&self.distances) {
+            table.push_str(label);
+            for distance in row {
+                // Writing to a String cannot fail.
+                let _ = write!(table, "{delimiter}{distance}");
+            }
+            table.push('\n');
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn library() -> Vec<AminoAcid> {
+        vec![
+            AminoAcid::new("Alanine", "Ala", "A", "Nonpolar", 89.09, &["GCT"]),
+            AminoAcid::new("Lysine", "Lys", "K", "Basic", 146.19, &["AAA"]),
+        ]
+    }
+
+    #[test]
+    fn test_new() {
+        let matrix = DistanceMatrix::new(&library(), DistanceMetric::Grantham).unwrap();
+        assert_eq!(matrix.get_distances(), vec![vec![0, 106], vec![106, 0]]);
+        assert_eq!(matrix.get_metric(), DistanceMetric::Grantham);
+    }
+    #[test]
+    fn test_new_rejects_unknown_residue() {
+        let mut library = library();
+        library.push(AminoAcid::default());
+        assert!(DistanceMatrix::new(&library, DistanceMetric::Grantham).is_err());
+    }
+    #[test]
+    fn test_get() {
+        let library = library();
+        let matrix = DistanceMatrix::new(&library, DistanceMetric::Grantham).unwrap();
+        let result = matrix.get(&library[1], &library[0]).unwrap();
+        assert_eq!(result.get_distance(), 106);
+        assert_eq!(result.get_first(), library[1]);
+        assert_eq!(matrix.get(&library[0], &AminoAcid::default()), None);
+    }
+    #[test]
+    fn test_get_by_code() {
+        let matrix = DistanceMatrix::new(&library(), DistanceMetric::Grantham).unwrap();
+        assert_eq!(matrix.get_by_code('K', 'A'), Some(106));
+        assert_eq!(matrix.get_by_code('K', 'W'), None);
+    }
+    #[test]
+    fn test_ordered() {
+        let matrix = DistanceMatrix::new(&library(), DistanceMetric::Grantham).unwrap();
+        let ordered = matrix
+            .ordered(&ResidueOrder::Custom(vec!['K', 'A']))
+            .unwrap();
+        assert_eq!(ordered.get_labels(ResidueCode::OneLetter), vec!["K", "A"]);
+        let subset = matrix.ordered(&ResidueOrder::Custom(vec!['K'])).unwrap();
+        assert_eq!(subset.get_distances(), vec![vec![0]]);
+        assert!(matrix.ordered(&ResidueOrder::Custom(vec!['W'])).is_err());
+        assert!(matrix
+            .ordered(&ResidueOrder::Custom(vec!['A', 'A']))
+            .is_err());
+    }
+    #[test]
+    fn test_to_delimited() {
+        let matrix = DistanceMatrix::new(&library(), DistanceMetric::Grantham).unwrap();
+        assert_eq!(
+            matrix.to_delimited(',', ResidueCode::OneLetter),
+            ",A,K\nA,0,106\nK,106,0\n"
+        );
+        assert_eq!(
+            matrix.to_delimited('\t', ResidueCode::ThreeLetter),
+            "\tAla\tLys\nAla\t0\t106\nLys\t106\t0\n"
+        );
+    }
+}
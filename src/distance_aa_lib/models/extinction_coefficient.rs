@@ -0,0 +1,239 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use anyhow::{ensure, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+/// Pace et al. (1995) molar absorptivities at 280 nm, in M⁻¹ cm⁻¹.
+const TRYPTOPHAN_280: f64 = 5500.0;
+const TYROSINE_280: f64 = 1490.0;
+const CYSTINE_280: f64 = 125.0;
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CysteineState {
+    /// All cysteines are free thiols and contribute nothing at 280 nm.
+    #[default]
+    Reduced,
+    /// All cysteines are paired into cystines.
+    Oxidised,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+pub struct Concentration {
+    molar: f64,
+    mg_per_ml: f64,
+}
+
+impl Concentration {
+    #[must_use]
+    pub const fn new(molar: f64, mg_per_ml: f64) -> Self {
+        Self { molar, mg_per_ml }
+    }
+    #[must_use]
+    pub const fn get_molar(&self) -> f64 {
+        self.molar
+    }
+    #[must_use]
+    pub const fn get_mg_per_ml(&self) -> f64 {
+        self.mg_per_ml
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct ExtinctionCoefficient {
+    reduced: f64,
+    oxidised: f64,
+    molecular_weight: f64,
+}
+
+impl ExtinctionCoefficient {
+    #[must_use]
+    pub const fn new(reduced: f64, oxidised: f64, molecular_weight: f64) -> Self {
+        Self {
+            reduced,
+            oxidised,
+            molecular_weight,
+        }
+    }
+
+    /// Computes both coefficients from residue counts using the Pace method.
+    #[must_use]
+    pub fn from_counts(
+        tryptophans: usize,
+        tyrosines: usize,
+        cysteines: usize,
+        molecular_weight: f64,
+    ) -> Self {
+        #[allow(clippy::cast_precision_loss)]
+        let reduced = tryptophans as f64 * TRYPTOPHAN_280 + tyrosines as f64 * TYROSINE_280;
+        #[allow(clippy::cast_precision_loss)]
+        let oxidised = reduced + (cysteines / 2) as f64 * CYSTINE_280;
+        Self::new(reduced, oxidised, molecular_weight)
+    }
+    #[must_use]
+    pub const fn get_reduced(&self) -> f64 {
+        self.reduced
+    }
+    #[must_use]
+    pub const fn get_oxidised(&self) -> f64 {
+        self.oxidised
+    }
+    #[must_use]
+    pub const fn get_molecular_weight(&self) -> f64 {
+        self.molecular_weight
+    }
+    #[must_use]
+    pub const fn get_molar(&self, state: CysteineState) -> f64 {
+        match state {
+            CysteineState::Reduced => self.reduced,
+            CysteineState::Oxidised => self.oxidised,
+        }
+    }
+
+    /// Absorbance of a 1 mg/mL solution over a 1 cm path.
+    #[must_use]
+    pub fn get_mass(&self, state: CysteineState) -> f64 {
+        self.get_molar(state) / self.molecular_weight
+    }
+
+    /// Converts a measured A280 into concentration by the Beer–Lambert law.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the absorbance is negative or not finite, the path length is not
+    /// positive, or the protein does not absorb at 280 nm (no tryptophan, tyrosine or cystine).
+    pub fn concentration(
+        &self,
+        absorbance: f64,
+        path_length_cm: f64,
+        state: CysteineState,
+    ) -> Result<Concentration> {
+        ensure!(
+            absorbance.is_finite() && absorbance >= 0.0,
+            "Absorbance must be a non-negative number, got {absorbance}"
+        );
+        ensure!(
+            path_length_cm > 0.0 && path_length_cm.is_finite(),
+            "Path length must be positive, got {path_length_cm}"
+        );
+        let coefficient = self.get_molar(state);
+        ensure!(
+            coefficient > 0.0,
+            "Sequence has no tryptophan, tyrosine or cystine and does not absorb at 280 nm"
+        );
+        let molar = absorbance / (coefficient * path_length_cm);
+        Ok(Concentration::new(molar, molar * self.molecular_weight))
+    }
+}
+
+impl Display for ExtinctionCoefficient {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Reduced: {} M-1 cm-1\tOxidised: {} M-1 cm-1\tMolecular Weight: {}",
+            self.reduced, self.oxidised, self.molecular_weight
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_counts() {
+        let coefficient = ExtinctionCoefficient::from_counts(2, 3, 5, 10000.0);
+        assert!((coefficient.get_reduced() - 15470.0).abs() < 1e-9);
+        assert!((coefficient.get_oxidised() - 15720.0).abs() < 1e-9);
+    }
+    #[test]
+    fn test_get_mass() {
+        let coefficient = ExtinctionCoefficient::new(15470.0, 15720.0, 10000.0);
+        assert!((coefficient.get_mass(CysteineState::Reduced) - 1.547).abs() < 1e-9);
+    }
+    #[test]
+    fn test_concentration() {
+        let coefficient = ExtinctionCoefficient::new(10000.0, 10250.0, 20000.0);
+        let concentration = coefficient
+            .concentration(0.5, 1.0, CysteineState::Reduced)
+            .unwrap();
+        assert!((concentration.get_molar() - 5e-5).abs() < 1e-12);
+        assert!((concentration.get_mg_per_ml() - 1.0).abs() < 1e-9);
+    }
+    #[test]
+    fn test_concentration_without_chromophores() {
+        let coefficient = ExtinctionCoefficient::from_counts(0, 0, 1, 5000.0);
+        assert!(coefficient
+            .concentration(0.5, 1.0, CysteineState::Oxidised)
+            .is_err());
+    }
+    #[test]
+    fn test_concentration_rejects_path_length() {
+        let coefficient = ExtinctionCoefficient::new(10000.0, 10000.0, 20000.0);
+        assert!(coefficient
+            .concentration(0.5, 0.0, CysteineState::Reduced)
+            .is_err());
+    }
+    #[test]
+    fn test_concentration_rejects_absorbance() {
+        let coefficient = ExtinctionCoefficient::new(10000.0, 10000.0, 20000.0);
+        for absorbance in [-0.1, f64::NAN, f64::INFINITY] {
+            assert!(coefficient
+                .concentration(absorbance, 1.0, CysteineState::Reduced)
+                .is_err());
+        }
+        let error = coefficient
+            .concentration(-0.1, 1.0, CysteineState::Reduced)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Absorbance must be a non-negative number, got -0.1"
+        );
+    }
+}
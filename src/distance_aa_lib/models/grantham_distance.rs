@@ -45,15 +45,17 @@
 use crate::models::AminoAcid;
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
 pub struct GranthamDistance {
     first: AminoAcid,
     second: AminoAcid,
     distance: usize,
 }
 
-#[allow(dead_code)]
 impl GranthamDistance {
     #[must_use]
     pub fn new(first: AminoAcid, second: AminoAcid, distance: usize) -> Self {
@@ -88,17 +90,24 @@ impl Display for GranthamDistance {
 }
 
 #[cfg(test)]
-
 mod tests {
     use super::*;
 
     #[test]
     fn test_new() {
-        let grantham_distance =
-            GranthamDistance::new(AminoAcid::new("Alanine"), AminoAcid::new("Lysine"), 2);
-        assert_eq!(grantham_distance.get_first(), AminoAcid::new("Alanine"));
-        assert_eq!(grantham_distance.get_second(), AminoAcid::new("Lysine"));
-        assert_eq!(grantham_distance.get_distance(), 2);
+        let alanine = AminoAcid::new(
+            "Alanine",
+            "Ala",
+            "A",
+            "Nonpolar",
+            89.09,
+            &["GCT", "GCC", "GCA", "GCG"],
+        );
+        let lysine = AminoAcid::new("Lysine", "Lys", "K", "Basic", 146.19, &["AAA", "AAG"]);
+        let grantham_distance = GranthamDistance::new(alanine.clone(), lysine.clone(), 106);
+        assert_eq!(grantham_distance.get_first(), alanine);
+        assert_eq!(grantham_distance.get_second(), lysine);
+        assert_eq!(grantham_distance.get_distance(), 106);
     }
 
     #[test]
@@ -0,0 +1,153 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use crate::models::HydropathyScale;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+pub struct HydropathyPoint {
+    position: usize,
+    residue: char,
+    score: f64,
+}
+
+impl HydropathyPoint {
+    #[must_use]
+    pub const fn new(position: usize, residue: char, score: f64) -> Self {
+        Self {
+            position,
+            residue,
+            score,
+        }
+    }
+    #[must_use]
+    pub const fn get_position(&self) -> usize {
+        self.position
+    }
+    #[must_use]
+    pub const fn get_residue(&self) -> char {
+        self.residue
+    }
+    #[must_use]
+    pub const fn get_score(&self) -> f64 {
+        self.score
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct HydropathyProfile {
+    scale: HydropathyScale,
+    window: usize,
+    points: Vec<HydropathyPoint>,
+}
+
+impl HydropathyProfile {
+    #[must_use]
+    pub fn new(scale: HydropathyScale, window: usize, points: Vec<HydropathyPoint>) -> Self {
+        Self {
+            scale,
+            window,
+            points,
+        }
+    }
+    #[must_use]
+    pub const fn get_scale(&self) -> HydropathyScale {
+        self.scale
+    }
+    #[must_use]
+    pub const fn get_window(&self) -> usize {
+        self.window
+    }
+    #[must_use]
+    pub fn get_points(&self) -> Vec<HydropathyPoint> {
+        self.points.clone()
+    }
+    #[must_use]
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("position,residue,score\n");
+        for point in &self.points {
+            // Writing to a String cannot fail.
+            let _ = writeln!(csv, "{},{},{}", point.position, point.residue, point.score);
+        }
+        csv
+    }
+    /// # Errors
+    ///
+    /// Returns an error if serialization fails.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile() -> HydropathyProfile {
+        HydropathyProfile::new(
+            HydropathyScale::KyteDoolittle,
+            3,
+            vec![
+                HydropathyPoint::new(2, 'I', 1.5),
+                HydropathyPoint::new(3, 'R', -1.5),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_to_csv() {
+        assert_eq!(
+            profile().to_csv(),
+            "position,residue,score\n2,I,1.5\n3,R,-1.5\n"
+        );
+    }
+    #[test]
+    fn test_to_json() {
+        assert_eq!(
+            profile().to_json().unwrap(),
+            r#"{"scale":"kyte_doolittle","window":3,"points":[{"position":2,"residue":"I","score":1.5},{"position":3,"residue":"R","score":-1.5}]}"#
+        );
+    }
+}
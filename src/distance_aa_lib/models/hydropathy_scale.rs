@@ -0,0 +1,165 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use crate::models::STANDARD_RESIDUES;
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+/// Kyte & Doolittle (1982), in the order of `STANDARD_RESIDUES`.
+const KYTE_DOOLITTLE: [f64; 20] = [
+    1.8, -4.5, -3.5, -3.5, 2.5, -3.5, -3.5, -0.4, -3.2, 4.5, 3.8, -3.9, 1.9, 2.8, -1.6, -0.8, -0.7,
+    -0.9, -1.3, 4.2,
+];
+
+/// Hopp & Woods (1981), in the order of `STANDARD_RESIDUES`.
+const HOPP_WOODS: [f64; 20] = [
+    -0.5, 3.0, 0.2, 3.0, -1.0, 0.2, 3.0, 0.0, -0.5, -1.8, -1.8, 3.0, -1.3, -2.5, 0.0, 0.3, -0.4,
+    -3.4, -2.3, -1.5,
+];
+
+/// Eisenberg et al. (1984) normalized consensus, in the order of `STANDARD_RESIDUES`.
+const EISENBERG: [f64; 20] = [
+    0.62, -2.53, -0.78, -0.90, 0.29, -0.85, -0.74, 0.48, -0.40, 1.38, 1.06, -1.50, 0.64, 1.19,
+    0.12, -0.18, -0.05, 0.81, 0.26, 1.08,
+];
+
+/// Engelman, Steitz & Goldman (1986), in the order of `STANDARD_RESIDUES`.
+const ENGELMAN_GES: [f64; 20] = [
+    1.6, -12.3, -4.8, -9.2, 2.0, -4.1, -8.2, 1.0, -3.0, 3.1, 2.8, -8.8, 3.4, 3.7, -0.2, 0.6, 1.2,
+    1.9, -0.7, 2.6,
+];
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy, Default)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum HydropathyScale {
+    #[default]
+    KyteDoolittle,
+    HoppWoods,
+    Eisenberg,
+    EngelmanGes,
+}
+
+impl HydropathyScale {
+    pub const ALL: [Self; 4] = [
+        Self::KyteDoolittle,
+        Self::HoppWoods,
+        Self::Eisenberg,
+        Self::EngelmanGes,
+    ];
+
+    /// Returns the hydropathy of a residue given by its one-letter code, or `None` if the
+    /// code is not one of the twenty standard residues.
+    #[must_use]
+    pub fn value(self, residue: char) -> Option<f64> {
+        let index = STANDARD_RESIDUES.find(residue.to_ascii_uppercase())?;
+        let table = match self {
+            Self::KyteDoolittle => &KYTE_DOOLITTLE,
+            Self::HoppWoods => &HOPP_WOODS,
+            Self::Eisenberg => &EISENBERG,
+            Self::EngelmanGes => &ENGELMAN_GES,
+        };
+        Some(table[index])
+    }
+    #[must_use]
+    pub const fn get_name(self) -> &'static str {
+        match self {
+            Self::KyteDoolittle => "kyte_doolittle",
+            Self::HoppWoods => "hopp_woods",
+            Self::Eisenberg => "eisenberg",
+            Self::EngelmanGes => "engelman_ges",
+        }
+    }
+}
+
+impl Display for HydropathyScale {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.get_name())
+    }
+}
+
+impl FromStr for HydropathyScale {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|scale| scale.get_name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| anyhow!("Unknown hydropathy scale: {s}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value() {
+        assert_eq!(HydropathyScale::KyteDoolittle.value('I'), Some(4.5));
+        assert_eq!(HydropathyScale::KyteDoolittle.value('r'), Some(-4.5));
+        assert_eq!(HydropathyScale::HoppWoods.value('W'), Some(-3.4));
+        assert_eq!(HydropathyScale::Eisenberg.value('G'), Some(0.48));
+        assert_eq!(HydropathyScale::EngelmanGes.value('F'), Some(3.7));
+    }
+    #[test]
+    fn test_value_unknown_residue() {
+        assert_eq!(HydropathyScale::KyteDoolittle.value('X'), None);
+    }
+    #[test]
+    fn test_from_str() {
+        assert_eq!(
+            "Hopp_Woods".parse::<HydropathyScale>().unwrap(),
+            HydropathyScale::HoppWoods
+        );
+        assert!("octanol".parse::<HydropathyScale>().is_err());
+    }
+    #[test]
+    fn test_default() {
+        assert_eq!(HydropathyScale::default(), HydropathyScale::KyteDoolittle);
+    }
+}
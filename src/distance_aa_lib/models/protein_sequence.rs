@@ -0,0 +1,357 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use crate::models::{
+    AminoAcid, ExtinctionCoefficient, HydropathyPoint, HydropathyProfile, HydropathyScale,
+};
+use anyhow::{anyhow, bail, ensure, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+
+/// The twenty standard residues by one-letter code.
+pub const STANDARD_RESIDUES: &str = "ARNDCQEGHILKMFPSTWYV";
+
+/// Average mass of water, lost once per peptide bond.
+const WATER_MASS: f64 = 18.015;
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct ProteinSequence {
+    id: Option<String>,
+    residues: String,
+}
+
+impl ProteinSequence {
+    /// Builds a sequence from one-letter codes. Whitespace and a trailing stop (`*`) are
+    /// ignored and lower-case codes are accepted; anything else outside the twenty standard
+    /// residues is rejected.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sequence is empty or contains a non-standard residue.
+    pub fn new(id: Option<&str>, residues: &str) -> Result<Self> {
+        let mut cleaned: String = residues
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| c.to_ascii_uppercase())
+            .collect();
+        if cleaned.ends_with('*') {
+            cleaned.pop();
+        }
+        ensure!(!cleaned.is_empty(), "Sequence is empty");
+        if let Some((position, residue)) = cleaned
+            .chars()
+            .enumerate()
+            .find(|(_, c)| !STANDARD_RESIDUES.contains(*c))
+        {
+            bail!("Unknown residue '{}' at position {}", residue, position + 1);
+        }
+        Ok(Self {
+            id: id.map(ToString::to_string),
+            residues: cleaned,
+        })
+    }
+
+    /// Parses either a raw sequence or one or more FASTA records.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any record is empty or contains a non-standard residue.
+    pub fn parse(input: &str) -> Result<Vec<Self>> {
+        Self::parse_records(input).into_iter().collect()
+    }
+
+    /// Parses like `parse`, but each record on its own, so that one bad record in a large
+    /// file does not hide the others.
+    #[must_use]
+    pub fn parse_records(input: &str) -> Vec<Result<Self>> {
+        let input = input.trim();
+        if !input.starts_with('>') {
+            return vec![Self::new(None, input)];
+        }
+
+        input
+            .split('>')
+            .skip(1)
+            .map(|record| {
+                let (header, body) = record.split_once('\n').unwrap_or((record, ""));
+                let id = header.split_whitespace().next();
+                Self::new(id, body)
+                    .map_err(|error| anyhow!("{}: {}", id.unwrap_or("unnamed record"), error))
+            })
+            .collect()
+    }
+    #[must_use]
+    pub fn get_id(&self) -> Option<String> {
+        self.id.clone()
+    }
+    #[must_use]
+    pub fn get_residues(&self) -> String {
+        self.residues.clone()
+    }
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.residues.len()
+    }
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.residues.is_empty()
+    }
+    #[must_use]
+    pub fn count(&self, residue: char) -> usize {
+        let residue = residue.to_ascii_uppercase();
+        self.residues.chars().filter(|&c| c == residue).count()
+    }
+
+    /// Average mass of the chain in daltons, from the free amino acid weights in `library`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a residue in the sequence is missing from `library`.
+    pub fn molecular_weight(&self, library: &[AminoAcid]) -> Result<f64> {
+        let mut total = WATER_MASS;
+        for residue in self.residues.chars() {
+            let amino_acid = library
+                .iter()
+                .find(|aa| aa.get_abbreviation() == residue.to_string())
+                .ok_or_else(|| anyhow!("No amino acid data for residue '{residue}'"))?;
+            total += amino_acid.get_molecular_weight() - WATER_MASS;
+        }
+        Ok(total)
+    }
+
+    /// Molar extinction coefficient at 280 nm by the Pace method.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a residue in the sequence is missing from `library`.
+    pub fn extinction_coefficient(&self, library: &[AminoAcid]) -> Result<ExtinctionCoefficient> {
+        Ok(ExtinctionCoefficient::from_counts(
+            self.count('W'),
+            self.count('Y'),
+            self.count('C'),
+            self.molecular_weight(library)?,
+        ))
+    }
+
+    /// Grand average of hydropathy: the mean scale value over every residue.
+    #[must_use]
+    pub fn gravy(&self, scale: HydropathyScale) -> f64 {
+        #[allow(clippy::cast_precision_loss)]
+        let length = self.len() as f64;
+        self.scores(scale).sum::<f64>() / length
+    }
+
+    /// Sliding-window hydropathy profile, with each window average reported at the
+    /// position of its central residue.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the window is even, zero, or longer than the sequence.
+    pub fn hydropathy_profile(
+        &self,
+        scale: HydropathyScale,
+        window: usize,
+    ) -> Result<HydropathyProfile> {
+        ensure!(window % 2 == 1, "Window size must be odd, got {window}");
+        ensure!(
+            window <= self.len(),
+            "Window size {} is longer than the sequence ({} residues)",
+            window,
+            self.len()
+        );
+
+        let scores: Vec<f64> = self.scores(scale).collect();
+        let residues: Vec<char> = self.residues.chars().collect();
+        let half = window / 2;
+        #[allow(clippy::cast_precision_loss)]
+        let points = scores
+            .windows(window)
+            .enumerate()
+            .map(|(start, values)| {
+                HydropathyPoint::new(
+                    start + half + 1,
+                    residues[start + half],
+                    values.iter().sum::<f64>() / window as f64,
+                )
+            })
+            .collect();
+        Ok(HydropathyProfile::new(scale, window, points))
+    }
+
+    fn scores(&self, scale: HydropathyScale) -> impl Iterator<Item = f64> + '_ {
+        // Residues are validated on construction, so every lookup succeeds.
+        self.residues
+            .chars()
+            .map(move |c| scale.value(c).unwrap_or_default())
+    }
+}
+
+impl Display for ProteinSequence {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, ">{}", self.id.as_deref().unwrap_or("sequence"))?;
+        write!(f, "{}", self.residues)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let sequence = ProteinSequence::new(Some("test"), "mkt ayi*").unwrap();
+        assert_eq!(sequence.get_id(), Some("test".to_string()));
+        assert_eq!(sequence.get_residues(), "MKTAYI");
+        assert_eq!(sequence.len(), 6);
+    }
+    #[test]
+    fn test_new_rejects_unknown_residue() {
+        let error = ProteinSequence::new(None, "MKXA").unwrap_err();
+        assert_eq!(error.to_string(), "Unknown residue 'X' at position 3");
+    }
+    #[test]
+    fn test_new_rejects_empty() {
+        assert!(ProteinSequence::new(None, " * ").is_err());
+    }
+    #[test]
+    fn test_parse_raw() {
+        let sequences = ProteinSequence::parse("MKTAYIAK\nQRQISFVK").unwrap();
+        assert_eq!(sequences.len(), 1);
+        assert_eq!(sequences[0].get_id(), None);
+        assert_eq!(sequences[0].get_residues(), "MKTAYIAKQRQISFVK");
+    }
+    #[test]
+    fn test_parse_fasta() {
+        let input = ">sp|P1 first protein\nMKTA\nYIAK\n>sp|P2\nGGGG\n";
+        let sequences = ProteinSequence::parse(input).unwrap();
+        assert_eq!(sequences.len(), 2);
+        assert_eq!(sequences[0].get_id(), Some("sp|P1".to_string()));
+        assert_eq!(sequences[0].get_residues(), "MKTAYIAK");
+        assert_eq!(sequences[1].get_residues(), "GGGG");
+    }
+    #[test]
+    fn test_parse_fasta_reports_record() {
+        let error = ProteinSequence::parse(">bad\nMKZ").unwrap_err();
+        assert_eq!(error.to_string(), "bad: Unknown residue 'Z' at position 3");
+    }
+    #[test]
+    fn test_parse_records() {
+        let records = ProteinSequence::parse_records(">ok\nMKTA\n>bad\nMKZ\n>also_ok\nGG\n");
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].as_ref().unwrap().get_residues(), "MKTA");
+        assert_eq!(
+            records[1].as_ref().unwrap_err().to_string(),
+            "bad: Unknown residue 'Z' at position 3"
+        );
+        assert_eq!(
+            records[2].as_ref().unwrap().get_id(),
+            Some("also_ok".to_string())
+        );
+    }
+    #[test]
+    fn test_gravy() {
+        let sequence = ProteinSequence::new(None, "AIR").unwrap();
+        let gravy = sequence.gravy(HydropathyScale::KyteDoolittle);
+        assert!((gravy - (1.8 + 4.5 - 4.5) / 3.0).abs() < 1e-12);
+    }
+    #[test]
+    fn test_hydropathy_profile() {
+        let sequence = ProteinSequence::new(None, "IIRRA").unwrap();
+        let profile = sequence
+            .hydropathy_profile(HydropathyScale::KyteDoolittle, 3)
+            .unwrap();
+        let points = profile.get_points();
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0].get_position(), 2);
+        assert_eq!(points[0].get_residue(), 'I');
+        assert!((points[0].get_score() - (4.5 + 4.5 - 4.5) / 3.0).abs() < 1e-12);
+        assert_eq!(points[2].get_position(), 4);
+    }
+    #[test]
+    fn test_hydropathy_profile_rejects_bad_window() {
+        let sequence = ProteinSequence::new(None, "IIRRA").unwrap();
+        assert!(sequence
+            .hydropathy_profile(HydropathyScale::KyteDoolittle, 4)
+            .is_err());
+        assert!(sequence
+            .hydropathy_profile(HydropathyScale::KyteDoolittle, 7)
+            .is_err());
+    }
+    #[test]
+    fn test_count() {
+        let sequence = ProteinSequence::new(None, "WYCWC").unwrap();
+        assert_eq!(sequence.count('W'), 2);
+        assert_eq!(sequence.count('c'), 2);
+        assert_eq!(sequence.count('A'), 0);
+    }
+    #[test]
+    fn test_molecular_weight() {
+        let library = vec![
+            AminoAcid::new("Glycine", "Gly", "G", "Nonpolar", 75.07, &["GGT"]),
+            AminoAcid::new("Tryptophan", "Trp", "W", "Nonpolar", 204.23, &["TGG"]),
+        ];
+        let sequence = ProteinSequence::new(None, "GW").unwrap();
+        let weight = sequence.molecular_weight(&library).unwrap();
+        assert!((weight - (75.07 + 204.23 - WATER_MASS)).abs() < 1e-9);
+        let missing = ProteinSequence::new(None, "GA").unwrap();
+        assert!(missing.molecular_weight(&library).is_err());
+    }
+    #[test]
+    fn test_extinction_coefficient() {
+        let library = vec![
+            AminoAcid::new("Cysteine", "Cys", "C", "Polar", 121.15, &["TGT"]),
+            AminoAcid::new("Tryptophan", "Trp", "W", "Nonpolar", 204.23, &["TGG"]),
+            AminoAcid::new("Tyrosine", "Tyr", "Y", "Polar", 181.19, &["TAT"]),
+        ];
+        let sequence = ProteinSequence::new(None, "WYCC").unwrap();
+        let coefficient = sequence.extinction_coefficient(&library).unwrap();
+        assert!((coefficient.get_reduced() - 6990.0).abs() < 1e-9);
+        assert!((coefficient.get_oxidised() - 7115.0).abs() < 1e-9);
+    }
+    #[test]
+    fn test_fmt() {
+        let sequence = ProteinSequence::new(Some("test"), "MKT").unwrap();
+        assert_eq!(format!("{}", sequence), ">test\nMKT");
+    }
+}
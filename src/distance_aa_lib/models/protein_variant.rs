@@ -0,0 +1,194 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use anyhow::{bail, ensure, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+/// A missense substitution in HGVS protein notation, such as `p.Arg97Cys`, `p.(R97C)` or
+/// `R97C`. Residues are kept as written, in one- or three-letter codes.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct ProteinVariant {
+    reference: String,
+    position: usize,
+    alternate: String,
+}
+
+impl ProteinVariant {
+    #[must_use]
+    pub fn new(reference: &str, position: usize, alternate: &str) -> Self {
+        Self {
+            reference: reference.to_string(),
+            position,
+            alternate: alternate.to_string(),
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error if the notation is malformed or is not a missense substitution
+    /// (synonymous, nonsense, frameshift, deletion or insertion).
+    pub fn parse(notation: &str) -> Result<Self> {
+        let trimmed = notation.trim();
+        let body = trimmed.strip_prefix("p.").unwrap_or(trimmed);
+        let body = body
+            .strip_prefix('(')
+            .and_then(|b| b.strip_suffix(')'))
+            .unwrap_or(body);
+
+        let digits_start = body
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(body.len());
+        let (reference, rest) = body.split_at(digits_start);
+        let digits_end = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (position, alternate) = rest.split_at(digits_end);
+
+        ensure!(
+            !reference.is_empty() && !position.is_empty() && !alternate.is_empty(),
+            "Invalid protein variant: '{notation}'"
+        );
+        if alternate == "="
+            || alternate == "*"
+            || alternate.eq_ignore_ascii_case("ter")
+            || alternate.contains("fs")
+            || alternate.contains("del")
+            || alternate.contains("ins")
+            || alternate.contains("dup")
+        {
+            bail!("Only missense substitutions are supported: '{notation}'");
+        }
+        let is_code = |code: &str| {
+            (code.len() == 1 || code.len() == 3) && code.chars().all(|c| c.is_ascii_alphabetic())
+        };
+        ensure!(
+            is_code(reference) && is_code(alternate),
+            "Invalid protein variant: '{notation}'"
+        );
+        let position: usize = position.parse()?;
+        ensure!(position > 0, "Variant positions start at 1: '{notation}'");
+        Ok(Self::new(reference, position, alternate))
+    }
+    #[must_use]
+    pub fn get_reference(&self) -> String {
+        self.reference.clone()
+    }
+    #[must_use]
+    pub const fn get_position(&self) -> usize {
+        self.position
+    }
+    #[must_use]
+    pub fn get_alternate(&self) -> String {
+        self.alternate.clone()
+    }
+}
+
+impl Display for ProteinVariant {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "p.{}{}{}", self.reference, self.position, self.alternate)
+    }
+}
+
+impl FromStr for ProteinVariant {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_three_letter() {
+        let variant = ProteinVariant::parse("p.Arg97Cys").unwrap();
+        assert_eq!(variant.get_reference(), "Arg");
+        assert_eq!(variant.get_position(), 97);
+        assert_eq!(variant.get_alternate(), "Cys");
+    }
+    #[test]
+    fn test_parse_one_letter() {
+        assert_eq!(
+            ProteinVariant::parse("R97C").unwrap(),
+            ProteinVariant::new("R", 97, "C")
+        );
+        assert_eq!(
+            ProteinVariant::parse("p.(R97C)").unwrap(),
+            ProteinVariant::new("R", 97, "C")
+        );
+    }
+    #[test]
+    fn test_parse_rejects_non_missense() {
+        for notation in [
+            "p.Arg97=",
+            "p.Arg97Ter",
+            "p.R97*",
+            "p.Arg97fs",
+            "p.Arg97del",
+        ] {
+            let error = ProteinVariant::parse(notation).unwrap_err();
+            assert!(error.to_string().starts_with("Only missense"), "{notation}");
+        }
+    }
+    #[test]
+    fn test_parse_rejects_malformed() {
+        for notation in ["", "p.97Cys", "p.Arg", "p.Argi97Cys", "p.Arg0Cys"] {
+            assert!(ProteinVariant::parse(notation).is_err(), "{notation}");
+        }
+    }
+    #[test]
+    fn test_fmt() {
+        assert_eq!(
+            format!("{}", ProteinVariant::new("Arg", 97, "Cys")),
+            "p.Arg97Cys"
+        );
+    }
+}
@@ -0,0 +1,148 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use crate::data::GRANTHAM_ORDER;
+use crate::models::AminoAcid;
+use anyhow::{bail, ensure};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// The order of the rows and columns of a distance matrix.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub enum ResidueOrder {
+    /// The order of the amino acid data file.
+    #[default]
+    Library,
+    /// Alphabetical by one-letter code.
+    Alphabetical,
+    /// The order of Grantham's published table, which keeps similar residues together.
+    Grantham,
+    /// Explicit one-letter codes, which may also select a subset of residues.
+    Custom(Vec<char>),
+}
+
+impl ResidueOrder {
+    /// Returns the one-letter codes of `residues` in this order.
+    #[must_use]
+    pub fn codes(&self, residues: &[AminoAcid]) -> Vec<char> {
+        match self {
+            Self::Library => residues.iter().filter_map(AminoAcid::get_code).collect(),
+            Self::Alphabetical => {
+                let mut codes = Self::Library.codes(residues);
+                codes.sort_unstable();
+                codes
+            }
+            Self::Grantham => GRANTHAM_ORDER.chars().collect(),
+            Self::Custom(codes) => codes.clone(),
+        }
+    }
+}
+
+impl Display for ResidueOrder {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Library => write!(f, "library"),
+            Self::Alphabetical => write!(f, "alphabetical"),
+            Self::Grantham => write!(f, "grantham"),
+            Self::Custom(codes) => write!(f, "{}", codes.iter().collect::<String>()),
+        }
+    }
+}
+
+/// Accepts `library`, `alphabetical` or `grantham`, or else a list of one-letter codes such as
+/// `GAVLI` or `G,A,V,L,I`.
+impl FromStr for ResidueOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        for order in [Self::Library, Self::Alphabetical, Self::Grantham] {
+            if order.to_string().eq_ignore_ascii_case(s) {
+                return Ok(order);
+            }
+        }
+        let codes: Vec<char> = s
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != ',')
+            .map(|c| c.to_ascii_uppercase())
+            .collect();
+        ensure!(!codes.is_empty(), "Residue order is empty");
+        if let Some(c) = codes.iter().find(|c| !c.is_ascii_alphabetic()) {
+            bail!("Unknown residue order: {s} ('{c}' is not a one-letter code)");
+        }
+        Ok(Self::Custom(codes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn library() -> Vec<AminoAcid> {
+        vec![
+            AminoAcid::new("Lysine", "Lys", "K", "Basic", 146.19, &["AAA"]),
+            AminoAcid::new("Alanine", "Ala", "A", "Nonpolar", 89.09, &["GCT"]),
+        ]
+    }
+
+    #[test]
+    fn test_codes() {
+        assert_eq!(ResidueOrder::Library.codes(&library()), vec!['K', 'A']);
+        assert_eq!(ResidueOrder::Alphabetical.codes(&library()), vec!['A', 'K']);
+        assert_eq!(ResidueOrder::Grantham.codes(&library()).len(), 20);
+    }
+    #[test]
+    fn test_from_str() {
+        assert_eq!(
+            "Grantham".parse::<ResidueOrder>().unwrap(),
+            ResidueOrder::Grantham
+        );
+        assert_eq!(
+            "g, a,v".parse::<ResidueOrder>().unwrap(),
+            ResidueOrder::Custom(vec!['G', 'A', 'V'])
+        );
+        assert!("A1".parse::<ResidueOrder>().is_err());
+        assert!(" , ".parse::<ResidueOrder>().is_err());
+    }
+}
@@ -0,0 +1,500 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use crate::models::{DistanceMatrix, DistanceMetric, ProteinSequence, SubstitutionClass};
+use anyhow::{anyhow, bail, ensure, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+/// Cost of opening or extending a gap when aligning. It is more than half the largest Grantham
+/// distance (215), so sequences of equal length are never gapped to avoid a substitution.
+const GAP_COST: usize = 110;
+
+/// One column of the comparison. Positions are 1-based and `None` on the side of a gap.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct PositionComparison {
+    first_position: Option<usize>,
+    second_position: Option<usize>,
+    first: Option<char>,
+    second: Option<char>,
+    distance: Option<usize>,
+    class: Option<SubstitutionClass>,
+}
+
+impl PositionComparison {
+    #[must_use]
+    pub const fn get_first_position(&self) -> Option<usize> {
+        self.first_position
+    }
+    #[must_use]
+    pub const fn get_second_position(&self) -> Option<usize> {
+        self.second_position
+    }
+    #[must_use]
+    pub const fn get_first(&self) -> Option<char> {
+        self.first
+    }
+    #[must_use]
+    pub const fn get_second(&self) -> Option<char> {
+        self.second
+    }
+    #[must_use]
+    pub const fn get_distance(&self) -> Option<usize> {
+        self.distance
+    }
+    #[must_use]
+    pub const fn get_class(&self) -> Option<SubstitutionClass> {
+        self.class
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy, Default)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct SubstitutionCounts {
+    identical: usize,
+    conservative: usize,
+    moderately_conservative: usize,
+    moderately_radical: usize,
+    radical: usize,
+    gaps: usize,
+}
+
+impl SubstitutionCounts {
+    #[must_use]
+    pub const fn get(&self, class: SubstitutionClass) -> usize {
+        match class {
+            SubstitutionClass::Identical => self.identical,
+            SubstitutionClass::Conservative => self.conservative,
+            SubstitutionClass::ModeratelyConservative => self.moderately_conservative,
+            SubstitutionClass::ModeratelyRadical => self.moderately_radical,
+            SubstitutionClass::Radical => self.radical,
+        }
+    }
+    #[must_use]
+    pub const fn get_gaps(&self) -> usize {
+        self.gaps
+    }
+    fn add(&mut self, class: Option<SubstitutionClass>) {
+        let count = match class {
+            Some(SubstitutionClass::Identical) => &mut self.identical,
+            Some(SubstitutionClass::Conservative) => &mut self.conservative,
+            Some(SubstitutionClass::ModeratelyConservative) => &mut self.moderately_conservative,
+            Some(SubstitutionClass::ModeratelyRadical) => &mut self.moderately_radical,
+            Some(SubstitutionClass::Radical) => &mut self.radical,
+            None => &mut self.gaps,
+        };
+        *count += 1;
+    }
+}
+
+/// Position-by-position distances between two protein sequences.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct SequenceComparison {
+    metric: DistanceMetric,
+    aligned: bool,
+    total_distance: usize,
+    mean_distance: f64,
+    counts: SubstitutionCounts,
+    positions: Vec<PositionComparison>,
+}
+
+impl SequenceComparison {
+    /// Compares `first` and `second` residue by residue. With `align`, the sequences are first
+    /// globally aligned (Needleman-Wunsch, minimising total distance plus gap costs), so they
+    /// may differ in length.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the lengths differ and `align` is false, or if the matrix has no
+    /// distance for some pair of residues.
+    pub fn new(
+        first: &ProteinSequence,
+        second: &ProteinSequence,
+        matrix: &DistanceMatrix,
+        align: bool,
+    ) -> Result<Self> {
+        Self::cancellable(first, second, matrix, align, &AtomicBool::new(false))
+    }
+
+    /// Compares like `new`, but gives up on an alignment once `cancelled` is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error as `new` does, or if the comparison was cancelled.
+    pub fn cancellable(
+        first: &ProteinSequence,
+        second: &ProteinSequence,
+        matrix: &DistanceMatrix,
+        align: bool,
+        cancelled: &AtomicBool,
+    ) -> Result<Self> {
+        let a: Vec<char> = first.get_residues().chars().collect();
+        let b: Vec<char> = second.get_residues().chars().collect();
+        ensure!(
+            align || a.len() == b.len(),
+            "Sequences differ in length ({} and {}); request an alignment to compare them",
+            a.len(),
+            b.len()
+        );
+        let columns = if align {
+            needleman_wunsch(&a, &b, matrix, cancelled)?
+        } else {
+            a.iter()
+                .zip(&b)
+                .map(|(&x, &y)| (Some(x), Some(y)))
+                .collect()
+        };
+
+        let mut counts = SubstitutionCounts::default();
+        let mut total_distance = 0;
+        let mut compared = 0;
+        let (mut i, mut j) = (0, 0);
+        let mut positions = Vec::with_capacity(columns.len());
+        for (x, y) in columns {
+            let distance = match (x, y) {
+                (Some(x), Some(y)) => Some(lookup(matrix, x, y)?),
+                _ => None,
+            };
+            let class = distance.map(SubstitutionClass::from_distance);
+            counts.add(class);
+            if let Some(distance) = distance {
+                total_distance += distance;
+                compared += 1;
+            }
+            positions.push(PositionComparison {
+                first_position: x.map(|_| {
+                    i += 1;
+                    i
+                }),
+                second_position: y.map(|_| {
+                    j += 1;
+                    j
+                }),
+                first: x,
+                second: y,
+                distance,
+                class,
+            });
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let mean_distance = if compared == 0 {
+            0.0
+        } else {
+            total_distance as f64 / compared as f64
+        };
+        Ok(Self {
+            metric: matrix.get_metric(),
+            aligned: align,
+            total_distance,
+            mean_distance,
+            counts,
+            positions,
+        })
+    }
+    #[must_use]
+    pub const fn get_metric(&self) -> DistanceMetric {
+        self.metric
+    }
+    #[must_use]
+    pub const fn is_aligned(&self) -> bool {
+        self.aligned
+    }
+    #[must_use]
+    pub const fn get_total_distance(&self) -> usize {
+        self.total_distance
+    }
+    #[must_use]
+    pub const fn get_mean_distance(&self) -> f64 {
+        self.mean_distance
+    }
+    #[must_use]
+    pub const fn get_counts(&self) -> SubstitutionCounts {
+        self.counts
+    }
+    #[must_use]
+    pub fn get_positions(&self) -> Vec<PositionComparison> {
+        self.positions.clone()
+    }
+}
+
+fn lookup(matrix: &DistanceMatrix, first: char, second: char) -> Result<usize> {
+    matrix.get_by_code(first, second).ok_or_else(|| {
+        anyhow!(
+            "No {} distance between {first} and {second}",
+            matrix.get_metric()
+        )
+    })
+}
+
+/// Substitution costs between the residues of one comparison, looked up once so that the
+/// alignment does not go back to the matrix for every cell.
+struct Costs {
+    residues: Vec<char>,
+    table: Vec<usize>,
+}
+
+impl Costs {
+    fn new(a: &[char], b: &[char], matrix: &DistanceMatrix) -> Result<Self> {
+        let mut residues: Vec<char> = a.iter().chain(b).copied().collect();
+        residues.sort_unstable();
+        residues.dedup();
+        let mut table = Vec::with_capacity(residues.len() * residues.len());
+        for &x in &residues {
+            for &y in &residues {
+                table.push(lookup(matrix, x, y)?);
+            }
+        }
+        Ok(Self { residues, table })
+    }
+    fn index(&self, sequence: &[char]) -> Vec<usize> {
+        sequence
+            .iter()
+            .map(|residue| self.residues.binary_search(residue).unwrap_or_default())
+            .collect()
+    }
+    fn get(&self, x: usize, y: usize) -> usize {
+        self.table[x * self.residues.len() + y]
+    }
+}
+
+type Column = (Option<usize>, Option<usize>);
+
+/// Global alignment as pairs of residues, with `None` marking a gap. Hirschberg's method keeps
+/// memory linear in the sequence lengths; the time is still their product.
+fn needleman_wunsch(
+    a: &[char],
+    b: &[char],
+    matrix: &DistanceMatrix,
+    cancelled: &AtomicBool,
+) -> Result<Vec<(Option<char>, Option<char>)>> {
+    let costs = Costs::new(a, b, matrix)?;
+    let mut columns = Vec::with_capacity(a.len().max(b.len()));
+    let alignment = Alignment {
+        costs: &costs,
+        cancelled,
+    };
+    alignment.hirschberg(&costs.index(a), &costs.index(b), &mut columns)?;
+    let residue = |index: Option<usize>| index.map(|index| costs.residues[index]);
+    Ok(columns
+        .into_iter()
+        .map(|(x, y)| (residue(x), residue(y)))
+        .collect())
+}
+
+struct Alignment<'a> {
+    costs: &'a Costs,
+    cancelled: &'a AtomicBool,
+}
+
+impl Alignment<'_> {
+    fn hirschberg(&self, a: &[usize], b: &[usize], columns: &mut Vec<Column>) -> Result<()> {
+        if a.len() <= 1 || b.len() <= 1 {
+            align_small(a, b, self.costs, columns);
+            return Ok(());
+        }
+        let middle = a.len() / 2;
+        let forward = self.last_row(a[..middle].iter(), b.iter())?;
+        let backward = self.last_row(a[middle..].iter().rev(), b.iter().rev())?;
+        let split = (0..=b.len())
+            .min_by_key(|&j| forward[j] + backward[b.len() - j])
+            .unwrap_or_default();
+        self.hirschberg(&a[..middle], &b[..split], columns)?;
+        self.hirschberg(&a[middle..], &b[split..], columns)
+    }
+
+    /// The cost of aligning all of `a` against each prefix of `b`, keeping two rows at a time.
+    fn last_row<'b>(
+        &self,
+        a: impl Iterator<Item = &'b usize>,
+        b: impl Iterator<Item = &'b usize> + Clone,
+    ) -> Result<Vec<usize>> {
+        let mut previous: Vec<usize> = (0..=b.clone().count()).map(|j| j * GAP_COST).collect();
+        let mut current = vec![0; previous.len()];
+        for &x in a {
+            if self.cancelled.load(Ordering::Relaxed) {
+                bail!("The alignment was cancelled");
+            }
+            current[0] = previous[0] + GAP_COST;
+            for (j, &y) in b.clone().enumerate() {
+                let substitution = previous[j] + self.costs.get(x, y);
+                let deletion = previous[j + 1] + GAP_COST;
+                let insertion = current[j] + GAP_COST;
+                current[j + 1] = substitution.min(deletion).min(insertion);
+            }
+            std::mem::swap(&mut previous, &mut current);
+        }
+        Ok(previous)
+    }
+}
+
+/// The full cost table, for when one side has at most one residue and the table is a
+/// single row or column.
+fn align_small(a: &[usize], b: &[usize], costs: &Costs, columns: &mut Vec<Column>) {
+    let (n, m) = (a.len(), b.len());
+    let mut cost = vec![vec![0; m + 1]; n + 1];
+    for (i, row) in cost.iter_mut().enumerate() {
+        row[0] = i * GAP_COST;
+    }
+    for (j, value) in cost[0].iter_mut().enumerate() {
+        *value = j * GAP_COST;
+    }
+    for i in 1..=n {
+        for j in 1..=m {
+            let substitution = cost[i - 1][j - 1] + costs.get(a[i - 1], b[j - 1]);
+            let deletion = cost[i - 1][j] + GAP_COST;
+            let insertion = cost[i][j - 1] + GAP_COST;
+            cost[i][j] = substitution.min(deletion).min(insertion);
+        }
+    }
+
+    let start = columns.len();
+    let (mut i, mut j) = (n, m);
+    while i > 0 || j > 0 {
+        if i > 0 && j > 0 && cost[i][j] == cost[i - 1][j - 1] + costs.get(a[i - 1], b[j - 1]) {
+            columns.push((Some(a[i - 1]), Some(b[j - 1])));
+            i -= 1;
+            j -= 1;
+        } else if i > 0 && cost[i][j] == cost[i - 1][j] + GAP_COST {
+            columns.push((Some(a[i - 1]), None));
+            i -= 1;
+        } else {
+            columns.push((None, Some(b[j - 1])));
+            j -= 1;
+        }
+    }
+    columns[start..].reverse();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::amino_acid_library;
+
+    fn matrix() -> DistanceMatrix {
+        DistanceMatrix::new(&amino_acid_library(), DistanceMetric::Grantham).unwrap()
+    }
+    fn sequence(residues: &str) -> ProteinSequence {
+        ProteinSequence::new(None, residues).unwrap()
+    }
+
+    #[test]
+    fn test_new() {
+        let comparison =
+            SequenceComparison::new(&sequence("AKRW"), &sequence("KKCW"), &matrix(), false)
+                .unwrap();
+        let distances: Vec<Option<usize>> = comparison
+            .get_positions()
+            .iter()
+            .map(PositionComparison::get_distance)
+            .collect();
+        assert_eq!(distances, vec![Some(106), Some(0), Some(180), Some(0)]);
+        assert_eq!(comparison.get_total_distance(), 286);
+        assert!((comparison.get_mean_distance() - 71.5).abs() < 1e-9);
+        let counts = comparison.get_counts();
+        assert_eq!(counts.get(SubstitutionClass::Identical), 2);
+        assert_eq!(counts.get(SubstitutionClass::ModeratelyRadical), 1);
+        assert_eq!(counts.get(SubstitutionClass::Radical), 1);
+        assert_eq!(counts.get_gaps(), 0);
+    }
+    #[test]
+    fn test_new_rejects_different_lengths() {
+        let error = SequenceComparison::new(&sequence("AKR"), &sequence("AK"), &matrix(), false)
+            .unwrap_err();
+        assert!(error.to_string().contains("differ in length (3 and 2)"));
+    }
+    #[test]
+    fn test_new_aligned() {
+        let comparison =
+            SequenceComparison::new(&sequence("MKWVTF"), &sequence("MKVTF"), &matrix(), true)
+                .unwrap();
+        let positions = comparison.get_positions();
+        assert_eq!(positions.len(), 6);
+        assert_eq!(positions[2].get_second(), None);
+        assert_eq!(positions[3].get_second_position(), Some(3));
+        assert_eq!(comparison.get_total_distance(), 0);
+        assert_eq!(comparison.get_counts().get_gaps(), 1);
+        assert!(comparison.is_aligned());
+    }
+    #[test]
+    fn test_new_aligned_long() {
+        let first = "MKWVTFISLLFLFSSAYSRGVFRRDTHKSEIAHRFKDLGEEHFKGLVLIAFSQYLQQCPFDEHVKLV";
+        let second = "MKWVTFISLLLLFSSAYSRGVFRRDAHKSEVAHRFKDLGEENFKALVLIAFAQYLQQCPFEDHVKLV";
+        let comparison =
+            SequenceComparison::new(&sequence(first), &sequence(second), &matrix(), true).unwrap();
+        let unaligned =
+            SequenceComparison::new(&sequence(first), &sequence(second), &matrix(), false).unwrap();
+        assert_eq!(comparison.get_counts().get_gaps(), 0);
+        assert_eq!(
+            comparison.get_total_distance(),
+            unaligned.get_total_distance()
+        );
+
+        let comparison =
+            SequenceComparison::new(&sequence(first), &sequence(&second[10..]), &matrix(), true)
+                .unwrap();
+        assert_eq!(comparison.get_counts().get_gaps(), 10);
+        assert_eq!(comparison.get_positions().len(), first.len());
+    }
+    #[test]
+    fn test_cancellable() {
+        let (first, second) = (sequence("MKWVTF"), sequence("MKVTF"));
+        let error = SequenceComparison::cancellable(
+            &first,
+            &second,
+            &matrix(),
+            true,
+            &AtomicBool::new(true),
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "The alignment was cancelled");
+        let cancelled = AtomicBool::new(true);
+        assert!(
+            SequenceComparison::cancellable(&first, &first, &matrix(), false, &cancelled).is_ok()
+        );
+    }
+}
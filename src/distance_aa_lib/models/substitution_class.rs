@@ -0,0 +1,131 @@
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// * Copyright (c) 2022 Cognitive Disorders Research Laboratory
+// *
+// * This project is dual-licensed under the MIT and Apache licenses.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** APACHE 2.0 LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Licensed under the Apache License, Version 2.0 (the "License");
+// * you may not use this file except in compliance with the License.
+// * You may obtain a copy of the License at
+// *
+// *     http://www.apache.org/licenses/LICENSE-2.0
+// *
+// * Unless required by applicable law or agreed to in writing, software
+// * distributed under the License is distributed on an "AS IS" BASIS,
+// * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// * See the License for the specific language governing permissions and
+// * limitations under the License.
+// *
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// ** MIT LICENSE
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+// *
+// * Permission is hereby granted, free of charge, to any person obtaining a copy
+// * of this software and associated documentation files (the "Software"), to deal
+// * in the Software without restriction, including without limitation the rights
+// * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// * copies of the Software, and to permit persons to whom the Software is
+// * furnished to do so, subject to the following conditions:
+// *
+// * The above copyright notice and this permission notice shall be included in all
+// * copies or substantial portions of the Software.
+// *
+// * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// * SOFTWARE.
+// * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *
+
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+/// Li, Wu & Luo (1984) classes of a substitution by its Grantham distance.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum SubstitutionClass {
+    /// The residue is unchanged.
+    Identical,
+    /// 1 to 50.
+    Conservative,
+    /// 51 to 100.
+    ModeratelyConservative,
+    /// 101 to 150.
+    ModeratelyRadical,
+    /// Above 150.
+    Radical,
+}
+
+impl SubstitutionClass {
+    pub const ALL: [Self; 5] = [
+        Self::Identical,
+        Self::Conservative,
+        Self::ModeratelyConservative,
+        Self::ModeratelyRadical,
+        Self::Radical,
+    ];
+
+    #[must_use]
+    pub const fn from_distance(distance: usize) -> Self {
+        match distance {
+            0 => Self::Identical,
+            1..=50 => Self::Conservative,
+            51..=100 => Self::ModeratelyConservative,
+            101..=150 => Self::ModeratelyRadical,
+            _ => Self::Radical,
+        }
+    }
+    #[must_use]
+    pub const fn get_name(self) -> &'static str {
+        match self {
+            Self::Identical => "identical",
+            Self::Conservative => "conservative",
+            Self::ModeratelyConservative => "moderately_conservative",
+            Self::ModeratelyRadical => "moderately_radical",
+            Self::Radical => "radical",
+        }
+    }
+}
+
+impl Display for SubstitutionClass {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.get_name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_distance() {
+        assert_eq!(
+            SubstitutionClass::from_distance(0),
+            SubstitutionClass::Identical
+        );
+        assert_eq!(
+            SubstitutionClass::from_distance(50),
+            SubstitutionClass::Conservative
+        );
+        assert_eq!(
+            SubstitutionClass::from_distance(51),
+            SubstitutionClass::ModeratelyConservative
+        );
+        assert_eq!(
+            SubstitutionClass::from_distance(106),
+            SubstitutionClass::ModeratelyRadical
+        );
+        assert_eq!(
+            SubstitutionClass::from_distance(215),
+            SubstitutionClass::Radical
+        );
+    }
+}